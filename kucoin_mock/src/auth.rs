@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config::MockUser;
+use crate::error::MockError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone, Debug)]
+struct Key {
+    secret: String,
+    passphrase: String,
+}
+
+/// Checks signed requests the way Kucoin does: KC-API-SIGN is the base64 HMAC-SHA256, keyed
+/// with the secret, of timestamp + method + path with its query + body.
+#[derive(Clone, Debug, Default)]
+pub struct Authenticator {
+    keys: HashMap<String, Key>,
+    window_ms: i64,
+}
+
+/// The parts of a request that go into the signature.
+#[derive(Clone, Copy)]
+pub struct Signed<'a> {
+    pub method: &'a str,
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+    pub body: &'a str,
+}
+
+impl Authenticator {
+    pub fn new(users: &[MockUser], window_ms: i64) -> Self {
+        let keys = users
+            .iter()
+            .map(|u| {
+                (
+                    u.api_key.clone(),
+                    Key {
+                        secret: u.api_secret.clone(),
+                        passphrase: u.api_pass.clone(),
+                    },
+                )
+            })
+            .collect();
+        Authenticator { keys, window_ms }
+    }
+
+    /// The API key the request was signed with. `header` looks up a request header and `now`
+    /// is the mock's clock in milliseconds.
+    pub fn verify<'h>(
+        &self,
+        header: impl Fn(&str) -> Option<&'h str>,
+        request: &Signed,
+        now: i64,
+    ) -> Result<String, MockError> {
+        let (key, sign, timestamp, passphrase) = match (
+            header("kc-api-key"),
+            header("kc-api-sign"),
+            header("kc-api-timestamp"),
+            header("kc-api-passphrase"),
+        ) {
+            (Some(k), Some(s), Some(t), Some(p)) if !k.is_empty() => (k, s, t, p),
+            _ => return Err(MockError::new(401, "400001", "Please check the header of your request for KC-API-KEY, KC-API-SIGN, KC-API-TIMESTAMP, KC-API-PASSPHRASE")),
+        };
+        let stamp: i64 = timestamp
+            .parse()
+            .map_err(|_| MockError::new(401, "400002", "KC-API-TIMESTAMP Invalid"))?;
+        if (stamp - now).abs() > self.window_ms {
+            return Err(MockError::new(401, "400002", "KC-API-TIMESTAMP Invalid"));
+        }
+        let found = self
+            .keys
+            .get(key)
+            .ok_or_else(|| MockError::new(401, "400003", "KC-API-KEY not exists"))?;
+        if found.passphrase != passphrase {
+            return Err(MockError::new(401, "400004", "Invalid KC-API-PASSPHRASE"));
+        }
+        if sign != signature(&found.secret, timestamp, request) {
+            return Err(MockError::new(401, "400005", "Invalid KC-API-SIGN"));
+        }
+        Ok(key.to_string())
+    }
+}
+
+pub fn signature(secret: &str, timestamp: &str, request: &Signed) -> String {
+    let query = request.query.map(|q| format!("?{}", q)).unwrap_or_default();
+    let prehash = format!(
+        "{}{}{}{}{}",
+        timestamp, request.method, request.path, query, request.body
+    );
+    let mut mac = HmacSha256::new_varkey(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.input(prehash.as_bytes());
+    base64::encode(mac.result().code())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use kucoin_rs_custom::kucoin::client::{Credentials, Kucoin, KucoinEnv};
+    use kucoin_rs_custom::kucoin::model::Method;
+
+    use crate::auth::{Authenticator, Signed};
+    use crate::config::MockUser;
+
+    fn user() -> MockUser {
+        MockUser {
+            api_key: "key".to_string(),
+            api_secret: "secret".to_string(),
+            api_pass: "pass".to_string(),
+            ..MockUser::default()
+        }
+    }
+
+    #[test]
+    fn accepts_what_sign_headers_signs() {
+        let client = Kucoin::new(
+            KucoinEnv::Custom("http://mock".to_string()),
+            Some(Credentials::new("key", "secret", "pass")),
+        )
+        .unwrap();
+        let auth = Authenticator::new(&[user()], 5_000);
+        let mut params = HashMap::new();
+        params.insert("symbol".to_string(), "ETH-BTC".to_string());
+        let body = serde_json::json!(&params).to_string();
+        let headers = client
+            .sign_headers(
+                "/api/v1/orders".to_string(),
+                Some(&params),
+                None,
+                Method::POST,
+            )
+            .unwrap();
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let now: i64 = header("kc-api-timestamp").unwrap().parse().unwrap();
+        let request = Signed {
+            method: "POST",
+            path: "/api/v1/orders",
+            query: None,
+            body: &body,
+        };
+        assert_eq!(auth.verify(header, &request, now).unwrap(), "key");
+
+        let tampered = Signed {
+            body: r#"{"symbol":"XRP-BTC"}"#,
+            ..request
+        };
+        assert_eq!(
+            auth.verify(header, &tampered, now).unwrap_err().code,
+            "400005"
+        );
+        let late = auth.verify(header, &request, now + 10_000).unwrap_err();
+        assert_eq!(late.code, "400002");
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::units;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+}
+
+/// An order waiting in the book.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resting {
+    pub order_id: String,
+    pub owner: String,
+    pub price: i64,
+    pub size: i64,
+}
+
+/// One match between a taker and a resting order, at the resting order's price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub maker_order_id: String,
+    pub maker_owner: String,
+    pub price: i64,
+    pub size: i64,
+}
+
+/// How much a taker is after, in the base currency or, for market buys, in the quote one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Amount {
+    Size(i64),
+    Funds(i64),
+}
+
+/// Price-time priority book for one symbol. Bids and asks are queues per price level,
+/// orders at the same price fill in the order they arrived.
+#[derive(Debug, Default)]
+pub struct OrderBook {
+    bids: BTreeMap<i64, VecDeque<Resting>>,
+    asks: BTreeMap<i64, VecDeque<Resting>>,
+}
+
+impl OrderBook {
+    pub fn best_bid(&self) -> Option<(i64, i64)> {
+        self.bids.iter().next_back().map(level_total)
+    }
+
+    pub fn best_ask(&self) -> Option<(i64, i64)> {
+        self.asks.iter().next().map(level_total)
+    }
+
+    /// Price and total size of the best `levels` price levels on `side`, best first.
+    pub fn depth(&self, side: Side, levels: usize) -> Vec<(i64, i64)> {
+        match side {
+            Side::Buy => self
+                .bids
+                .iter()
+                .rev()
+                .take(levels)
+                .map(level_total)
+                .collect(),
+            Side::Sell => self.asks.iter().take(levels).map(level_total).collect(),
+        }
+    }
+
+    /// Total size resting at `price` on `side`.
+    pub fn level(&self, side: Side, price: i64) -> i64 {
+        let levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        levels
+            .get(&price)
+            .map(|queue| queue.iter().map(|o| o.size).sum())
+            .unwrap_or(0)
+    }
+
+    /// Whether a taker on `side` could get all of `amount` without crossing `price`.
+    pub fn fillable(&self, side: Side, price: Option<i64>, amount: Amount) -> bool {
+        let mut left = amount;
+        for (level_price, queue) in self.opposite(side) {
+            if !crosses(side, price, level_price) {
+                break;
+            }
+            let size: i64 = queue.iter().map(|o| o.size).sum();
+            left = match left {
+                Amount::Size(s) => Amount::Size(s - size),
+                Amount::Funds(f) => Amount::Funds(f - units::quote(*level_price, size)),
+            };
+            match left {
+                Amount::Size(s) | Amount::Funds(s) if s <= 0 => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Matches a taker on `side` against the other side of the book, best price first and
+    /// oldest first within a price, until `amount` is used up or the next level is beyond
+    /// `price`. Market orders pass no price.
+    pub fn take(&mut self, side: Side, price: Option<i64>, amount: Amount) -> Vec<Fill> {
+        let mut fills = Vec::new();
+        let mut left = amount;
+        loop {
+            let level_price = match side {
+                Side::Buy => self.asks.keys().next().copied(),
+                Side::Sell => self.bids.keys().next_back().copied(),
+            };
+            let level_price = match level_price {
+                Some(p) if crosses(side, price, &p) => p,
+                _ => break,
+            };
+            let levels = match side {
+                Side::Buy => &mut self.asks,
+                Side::Sell => &mut self.bids,
+            };
+            let queue = levels.get_mut(&level_price).expect("level exists");
+            while let Some(maker) = queue.front_mut() {
+                let wanted = match left {
+                    Amount::Size(s) => s,
+                    Amount::Funds(f) => units::base_for(f, level_price),
+                };
+                let size = wanted.min(maker.size);
+                if size <= 0 {
+                    break;
+                }
+                maker.size -= size;
+                fills.push(Fill {
+                    maker_order_id: maker.order_id.clone(),
+                    maker_owner: maker.owner.clone(),
+                    price: level_price,
+                    size,
+                });
+                left = match left {
+                    Amount::Size(s) => Amount::Size(s - size),
+                    Amount::Funds(f) => Amount::Funds(f - units::quote(level_price, size)),
+                };
+                // A maker left with size means the taker got all it wanted.
+                if maker.size > 0 {
+                    break;
+                }
+                queue.pop_front();
+            }
+            let emptied = queue.is_empty();
+            if emptied {
+                levels.remove(&level_price);
+            }
+            let done = match left {
+                Amount::Size(s) => s <= 0,
+                Amount::Funds(f) => units::base_for(f, level_price) <= 0,
+            };
+            if done || !emptied {
+                break;
+            }
+        }
+        fills
+    }
+
+    /// Puts an order at the back of its price level.
+    pub fn rest(&mut self, side: Side, order: Resting) {
+        let levels = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        levels.entry(order.price).or_default().push_back(order);
+    }
+
+    /// Takes an order out of the book, with the side it was on.
+    pub fn cancel(&mut self, order_id: &str) -> Option<(Side, Resting)> {
+        for (side, levels) in [(Side::Buy, &mut self.bids), (Side::Sell, &mut self.asks)] {
+            let found = levels.iter_mut().find_map(|(price, queue)| {
+                queue
+                    .iter()
+                    .position(|o| o.order_id == order_id)
+                    .map(|i| (*price, i))
+            });
+            if let Some((price, i)) = found {
+                let queue = levels.get_mut(&price).expect("level exists");
+                let order = queue.remove(i).expect("index in range");
+                if queue.is_empty() {
+                    levels.remove(&price);
+                }
+                return Some((side, order));
+            }
+        }
+        None
+    }
+
+    fn opposite(&self, side: Side) -> Box<dyn Iterator<Item = (&i64, &VecDeque<Resting>)> + '_> {
+        match side {
+            Side::Buy => Box::new(self.asks.iter()),
+            Side::Sell => Box::new(self.bids.iter().rev()),
+        }
+    }
+}
+
+fn level_total((price, queue): (&i64, &VecDeque<Resting>)) -> (i64, i64) {
+    (*price, queue.iter().map(|o| o.size).sum())
+}
+
+// Whether a taker on `side` with limit `price` accepts a resting order at `level`.
+fn crosses(side: Side, price: Option<i64>, level: &i64) -> bool {
+    match (side, price) {
+        (_, None) => true,
+        (Side::Buy, Some(p)) => *level <= p,
+        (Side::Sell, Some(p)) => *level >= p,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::book::{Amount, OrderBook, Resting, Side};
+
+    fn resting(id: &str, price: i64, size: i64) -> Resting {
+        Resting {
+            order_id: id.to_string(),
+            owner: "maker".to_string(),
+            price,
+            size,
+        }
+    }
+
+    #[test]
+    fn fills_by_price_then_time() {
+        let mut book = OrderBook::default();
+        book.rest(Side::Sell, resting("late", 100, 5));
+        book.rest(Side::Sell, resting("cheap", 99, 2));
+        book.rest(Side::Sell, resting("later", 100, 5));
+        book.rest(Side::Buy, resting("bid", 90, 1));
+        let fills = book.take(Side::Buy, Some(100), Amount::Size(8));
+        let taken: Vec<(&str, i64, i64)> = fills
+            .iter()
+            .map(|f| (f.maker_order_id.as_str(), f.price, f.size))
+            .collect();
+        assert_eq!(
+            taken,
+            vec![("cheap", 99, 2), ("late", 100, 5), ("later", 100, 1)]
+        );
+        assert_eq!(book.best_ask(), Some((100, 4)));
+        assert_eq!(book.best_bid(), Some((90, 1)));
+        // Nothing crosses a bid below the best ask.
+        assert!(book.take(Side::Buy, Some(99), Amount::Size(1)).is_empty());
+    }
+
+    #[test]
+    fn market_buys_spend_funds_and_fok_checks_depth() {
+        let mut book = OrderBook::default();
+        book.rest(
+            Side::Sell,
+            resting("a", 2 * crate::units::SCALE, 3 * crate::units::SCALE),
+        );
+        book.rest(
+            Side::Sell,
+            resting("b", 4 * crate::units::SCALE, 10 * crate::units::SCALE),
+        );
+        let funds = 10 * crate::units::SCALE;
+        assert!(book.fillable(Side::Buy, None, Amount::Funds(funds)));
+        assert!(!book.fillable(
+            Side::Buy,
+            Some(2 * crate::units::SCALE),
+            Amount::Funds(funds)
+        ));
+        let fills = book.take(Side::Buy, None, Amount::Funds(funds));
+        // 3 at 2 for 6, then 1 at 4 for the other 4.
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[1].size, crate::units::SCALE);
+        assert_eq!(
+            book.cancel("b").map(|(side, o)| (side, o.size)),
+            Some((Side::Sell, 9 * crate::units::SCALE))
+        );
+        assert!(book.best_ask().is_none());
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Everything the mock is started with. Ports of 0 pick a free one, as tests do.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MockConfig {
+    pub host: String,
+    pub rest_port: u16,
+    pub ws_port: u16,
+    /// Taker and maker fee, taken in the quote currency.
+    pub fee_rate: f64,
+    /// How far a signed request's KC-API-TIMESTAMP may be from the mock's clock.
+    pub timestamp_window_ms: i64,
+    /// Added to the local clock for `/api/v1/timestamp` and the signature check, to test
+    /// clock syncing.
+    pub clock_offset_ms: i64,
+    pub latency_ms: u64,
+    /// Share of requests, from 0 to 1, answered with a random server error or 429.
+    pub error_rate: f64,
+    pub users: Vec<MockUser>,
+    pub markets: Vec<MockMarket>,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            host: "127.0.0.1".to_string(),
+            rest_port: 0,
+            ws_port: 0,
+            fee_rate: 0.001,
+            timestamp_window_ms: 5_000,
+            clock_offset_ms: 0,
+            latency_ms: 0,
+            error_rate: 0.0,
+            users: Vec::new(),
+            markets: Vec::new(),
+        }
+    }
+}
+
+/// An account with its API key and starting balances per currency.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct MockUser {
+    pub api_key: String,
+    pub api_secret: String,
+    pub api_pass: String,
+    pub main: HashMap<String, f64>,
+    pub trade: HashMap<String, f64>,
+}
+
+/// A symbol and the liquidity the house seeds around `price`: `levels` orders of
+/// `level_size` on each side, `step` apart starting one step from `price`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MockMarket {
+    pub symbol: String,
+    pub price: f64,
+    pub step: f64,
+    pub levels: u32,
+    pub level_size: f64,
+    pub base_min_size: f64,
+    pub base_increment: f64,
+    pub price_increment: f64,
+    pub quote_increment: f64,
+}
+
+impl Default for MockMarket {
+    fn default() -> Self {
+        MockMarket {
+            symbol: String::new(),
+            price: 0.0,
+            step: 0.0,
+            levels: 10,
+            level_size: 100.0,
+            base_min_size: 0.0001,
+            base_increment: 0.0001,
+            price_increment: 0.00000001,
+            quote_increment: 0.00000001,
+        }
+    }
+}
+
+impl MockMarket {
+    pub fn new(symbol: &str, price: f64) -> Self {
+        MockMarket {
+            symbol: symbol.to_string(),
+            price,
+            step: price / 1000.0,
+            ..MockMarket::default()
+        }
+    }
+
+    pub fn base(&self) -> &str {
+        self.symbol.split('-').next().unwrap_or_default()
+    }
+
+    pub fn quote(&self) -> &str {
+        self.symbol.split('-').nth(1).unwrap_or_default()
+    }
+}
+
+impl MockConfig {
+    pub fn load(path: &Path) -> Result<MockConfig> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read mock config {}", path.display()))?;
+        let config: MockConfig = serde_json::from_str(&json)
+            .with_context(|| format!("Mock config {} is not valid", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for market in &self.markets {
+            if market.base().is_empty() || market.quote().is_empty() {
+                anyhow::bail!("Market {} is not in BASE-QUOTE form", market.symbol);
+            }
+            if market.price <= 0.0 || market.step <= 0.0 {
+                anyhow::bail!("Market {} needs a positive price and step", market.symbol);
+            }
+        }
+        if !(0.0..=1.0).contains(&self.error_rate) {
+            anyhow::bail!("error_rate must be between 0 and 1");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use serde_json::json;
+
+/// An error answered the way Kucoin answers one: an HTTP status and a body with a business
+/// code and message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockError {
+    pub status: u16,
+    pub code: String,
+    pub msg: String,
+}
+
+impl MockError {
+    pub fn new(status: u16, code: &str, msg: &str) -> Self {
+        MockError {
+            status,
+            code: code.to_string(),
+            msg: msg.to_string(),
+        }
+    }
+
+    pub fn bad_request(msg: &str) -> Self {
+        MockError::new(400, "400100", msg)
+    }
+
+    pub fn not_found(msg: &str) -> Self {
+        MockError::new(404, "400100", msg)
+    }
+
+    pub fn body(&self) -> serde_json::Value {
+        json!({ "code": self.code, "msg": self.msg })
+    }
+}
+
+impl std::fmt::Display for MockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.status, self.code, self.msg)
+    }
+}
+
+impl std::error::Error for MockError {}
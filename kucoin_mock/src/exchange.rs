@@ -0,0 +1,1190 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+
+use crate::book::{Amount, Fill, OrderBook, Resting, Side};
+use crate::config::{MockConfig, MockMarket};
+use crate::error::MockError;
+use crate::units;
+
+/// Owner of the seeded liquidity. The house has no balances and can't run out.
+pub const HOUSE: &str = "house";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccountKind {
+    Main,
+    Trade,
+}
+
+impl AccountKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "main" => Some(AccountKind::Main),
+            "trade" => Some(AccountKind::Trade),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountKind::Main => "main",
+            AccountKind::Trade => "trade",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Balance {
+    pub total: i64,
+    pub holds: i64,
+}
+
+impl Balance {
+    pub fn available(&self) -> i64 {
+        self.total - self.holds
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderType {
+    Limit,
+    Market,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeInForce {
+    Gtc,
+    Ioc,
+    Fok,
+}
+
+impl TimeInForce {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "GTC" => Some(TimeInForce::Gtc),
+            "IOC" => Some(TimeInForce::Ioc),
+            "FOK" => Some(TimeInForce::Fok),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            TimeInForce::Gtc => "GTC",
+            TimeInForce::Ioc => "IOC",
+            TimeInForce::Fok => "FOK",
+        }
+    }
+}
+
+/// An order as placed, before it is checked against the market and the balances.
+#[derive(Debug, Clone)]
+pub struct NewOrder {
+    pub client_oid: String,
+    pub symbol: String,
+    pub side: Side,
+    pub order_type: OrderType,
+    pub price: Option<i64>,
+    pub size: Option<i64>,
+    pub funds: Option<i64>,
+    pub time_in_force: TimeInForce,
+}
+
+#[derive(Debug, Clone)]
+struct Order {
+    id: String,
+    client_oid: String,
+    owner: String,
+    symbol: String,
+    side: Side,
+    order_type: OrderType,
+    time_in_force: TimeInForce,
+    price: i64,
+    size: i64,
+    funds: i64,
+    deal_size: i64,
+    deal_funds: i64,
+    fee: i64,
+    /// What is still held for the order in the trade account.
+    hold_left: i64,
+    is_active: bool,
+    cancel_exist: bool,
+    created_at: i64,
+}
+
+#[derive(Debug, Clone)]
+struct FillRecord {
+    owner: String,
+    symbol: String,
+    trade_id: String,
+    order_id: String,
+    counter_order_id: String,
+    side: Side,
+    liquidity: &'static str,
+    order_type: OrderType,
+    price: i64,
+    size: i64,
+    funds: i64,
+    fee: i64,
+    fee_currency: String,
+    created_at: i64,
+}
+
+#[derive(Debug)]
+struct Market {
+    config: MockMarket,
+    base_min_size: i64,
+    base_increment: i64,
+    price_increment: i64,
+    quote_increment: i64,
+    book: OrderBook,
+    last: i64,
+    vol: i64,
+    vol_value: i64,
+    sequence: i64,
+}
+
+/// Something that happened on the exchange, pushed to the websocket subscribers of `topic`.
+/// Events with an `owner` only go to that account's private connections.
+#[derive(Debug, Clone)]
+pub struct FeedEvent {
+    pub topic: String,
+    pub subject: String,
+    pub owner: Option<String>,
+    pub data: Value,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    markets: BTreeMap<String, Market>,
+    balances: HashMap<(String, String, AccountKind), Balance>,
+    orders: BTreeMap<String, Order>,
+    fills: Vec<FillRecord>,
+    next_id: u64,
+}
+
+/// Balances, books and orders of the mock. Cloning is cheap and clones share the state.
+#[derive(Clone, Debug)]
+pub struct Exchange {
+    state: Arc<Mutex<State>>,
+    events: broadcast::Sender<FeedEvent>,
+    fee_rate: i64,
+    clock_offset_ms: i64,
+}
+
+impl Exchange {
+    /// Funds the users and seeds every market with house liquidity.
+    pub fn new(config: &MockConfig) -> Self {
+        let (events, _) = broadcast::channel(1024);
+        let exchange = Exchange {
+            state: Arc::new(Mutex::new(State::default())),
+            events,
+            fee_rate: units::from_f64(config.fee_rate),
+            clock_offset_ms: config.clock_offset_ms,
+        };
+        exchange.with_state(|state| {
+            for user in &config.users {
+                for (kind, balances) in [
+                    (AccountKind::Main, &user.main),
+                    (AccountKind::Trade, &user.trade),
+                ] {
+                    for (currency, amount) in balances {
+                        state.balance(&user.api_key, currency, kind).total +=
+                            units::from_f64(*amount);
+                    }
+                }
+            }
+            for market_config in &config.markets {
+                let increment = units::from_f64(market_config.price_increment).max(1);
+                let round = |price: f64| units::from_f64(price) / increment * increment;
+                let mut market = Market {
+                    base_min_size: units::from_f64(market_config.base_min_size),
+                    base_increment: units::from_f64(market_config.base_increment).max(1),
+                    price_increment: increment,
+                    quote_increment: units::from_f64(market_config.quote_increment).max(1),
+                    book: OrderBook::default(),
+                    last: round(market_config.price),
+                    vol: 0,
+                    vol_value: 0,
+                    sequence: 0,
+                    config: market_config.clone(),
+                };
+                for level in 1..=market_config.levels {
+                    let offset = market_config.step * level as f64;
+                    for (side, price) in [
+                        (Side::Sell, round(market_config.price + offset)),
+                        (Side::Buy, round(market_config.price - offset)),
+                    ] {
+                        if price <= 0 {
+                            continue;
+                        }
+                        let id = state.order_id();
+                        market.book.rest(
+                            side,
+                            Resting {
+                                order_id: id.clone(),
+                                owner: HOUSE.to_string(),
+                                price,
+                                size: units::from_f64(market_config.level_size),
+                            },
+                        );
+                        state.orders.insert(
+                            id.clone(),
+                            Order {
+                                id,
+                                client_oid: String::new(),
+                                owner: HOUSE.to_string(),
+                                symbol: market_config.symbol.clone(),
+                                side,
+                                order_type: OrderType::Limit,
+                                time_in_force: TimeInForce::Gtc,
+                                price,
+                                size: units::from_f64(market_config.level_size),
+                                funds: 0,
+                                deal_size: 0,
+                                deal_funds: 0,
+                                fee: 0,
+                                hold_left: 0,
+                                is_active: true,
+                                cancel_exist: false,
+                                created_at: 0,
+                            },
+                        );
+                    }
+                }
+                state.markets.insert(market_config.symbol.clone(), market);
+            }
+        });
+        exchange
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut State) -> T) -> T {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut state)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<FeedEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, events: Vec<FeedEvent>) {
+        for event in events {
+            // Nobody listening is fine.
+            let _ = self.events.send(event);
+        }
+    }
+
+    /// The mock's clock, in milliseconds since the epoch.
+    pub fn now_ms(&self) -> i64 {
+        let local = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        local + self.clock_offset_ms
+    }
+
+    pub fn symbols(&self) -> Vec<Value> {
+        self.with_state(|state| {
+            state
+                .markets
+                .values()
+                .map(|m| {
+                    json!({
+                        "symbol": m.config.symbol,
+                        "name": m.config.symbol,
+                        "baseCurrency": m.config.base(),
+                        "quoteCurrency": m.config.quote(),
+                        "feeCurrency": m.config.quote(),
+                        "market": m.config.quote(),
+                        "baseMinSize": units::format(m.base_min_size),
+                        "quoteMinSize": units::format(m.quote_increment),
+                        "baseMaxSize": "10000000000",
+                        "quoteMaxSize": "99999999",
+                        "baseIncrement": units::format(m.base_increment),
+                        "quoteIncrement": units::format(m.quote_increment),
+                        "priceIncrement": units::format(m.price_increment),
+                        "priceLimitRate": "0.1",
+                        "isMarginEnabled": false,
+                        "enableTrading": true
+                    })
+                })
+                .collect()
+        })
+    }
+
+    pub fn ticker(&self, symbol: &str) -> Result<Value, MockError> {
+        let now = self.now_ms();
+        self.with_state(|state| {
+            let market = state.market(symbol)?;
+            let (bid, bid_size) = market.book.best_bid().unwrap_or_default();
+            let (ask, ask_size) = market.book.best_ask().unwrap_or_default();
+            Ok(json!({
+                "sequence": market.sequence.to_string(),
+                "price": units::format(market.last),
+                "size": "0",
+                "bestBid": units::format(bid),
+                "bestBidSize": units::format(bid_size),
+                "bestAsk": units::format(ask),
+                "bestAskSize": units::format(ask_size),
+                "time": now
+            }))
+        })
+    }
+
+    pub fn all_tickers(&self) -> Value {
+        let now = self.now_ms();
+        self.with_state(|state| {
+            let ticker: Vec<Value> = state
+                .markets
+                .values()
+                .map(|m| {
+                    json!({
+                        "symbol": m.config.symbol,
+                        "symbolName": m.config.symbol,
+                        "buy": units::format(m.book.best_bid().map(|b| b.0).unwrap_or_default()),
+                        "sell": units::format(m.book.best_ask().map(|a| a.0).unwrap_or_default()),
+                        "changeRate": "0",
+                        "changePrice": "0",
+                        "high": units::format(m.last),
+                        "low": units::format(m.last),
+                        "vol": units::format(m.vol),
+                        "volValue": units::format(m.vol_value),
+                        "last": units::format(m.last)
+                    })
+                })
+                .collect();
+            json!({ "time": now, "ticker": ticker })
+        })
+    }
+
+    /// Aggregated price levels, `levels` deep on each side.
+    pub fn level2(&self, symbol: &str, levels: usize) -> Result<Value, MockError> {
+        let now = self.now_ms();
+        self.with_state(|state| {
+            let market = state.market(symbol)?;
+            let side = |side| -> Vec<[String; 2]> {
+                market
+                    .book
+                    .depth(side, levels)
+                    .into_iter()
+                    .map(|(p, s)| [units::format(p), units::format(s)])
+                    .collect()
+            };
+            Ok(json!({
+                "sequence": market.sequence.to_string(),
+                "time": now,
+                "bids": side(Side::Buy),
+                "asks": side(Side::Sell)
+            }))
+        })
+    }
+
+    pub fn accounts(
+        &self,
+        owner: &str,
+        currency: Option<&str>,
+        kind: Option<AccountKind>,
+    ) -> Vec<Value> {
+        self.with_state(|state| {
+            let mut accounts: Vec<Value> = state
+                .balances
+                .iter()
+                .filter(|((o, c, k), _)| {
+                    o == owner && currency.is_none_or(|x| x == c) && kind.is_none_or(|x| x == *k)
+                })
+                .map(|((_, c, k), b)| {
+                    json!({
+                        "id": format!("{}-{}", k.as_str(), c),
+                        "currency": c,
+                        "type": k.as_str(),
+                        "balance": units::format(b.total),
+                        "available": units::format(b.available()),
+                        "holds": units::format(b.holds)
+                    })
+                })
+                .collect();
+            accounts.sort_by_key(|a| a["id"].as_str().map(|s| s.to_string()));
+            accounts
+        })
+    }
+
+    pub fn balance(&self, owner: &str, currency: &str, kind: AccountKind) -> Balance {
+        self.with_state(|state| *state.balance(owner, currency, kind))
+    }
+
+    pub fn transferable(&self, owner: &str, currency: &str, kind: AccountKind) -> Value {
+        let b = self.balance(owner, currency, kind);
+        json!({
+            "currency": currency,
+            "balance": units::format(b.total),
+            "available": units::format(b.available()),
+            "holds": units::format(b.holds),
+            "transferable": units::format(b.available())
+        })
+    }
+
+    pub fn inner_transfer(
+        &self,
+        owner: &str,
+        currency: &str,
+        from: AccountKind,
+        to: AccountKind,
+        amount: i64,
+    ) -> Result<String, MockError> {
+        if amount <= 0 {
+            return Err(MockError::bad_request("amount must be positive"));
+        }
+        self.with_state(|state| {
+            if state.balance(owner, currency, from).available() < amount {
+                return Err(insufficient());
+            }
+            state.balance(owner, currency, from).total -= amount;
+            state.balance(owner, currency, to).total += amount;
+            Ok(state.order_id())
+        })
+    }
+
+    /// Checks the order against its market and the owner's trade account, holds the funds it
+    /// needs and matches it. Returns the order id, also for FOK and IOC orders that didn't fill.
+    pub fn place(&self, owner: &str, order: NewOrder) -> Result<String, MockError> {
+        let now = self.now_ms();
+        let fee_rate = self.fee_rate;
+        let (id, events) = self.with_state(|state| {
+            let market = state.market(&order.symbol)?;
+            let (base, quote) = (
+                market.config.base().to_string(),
+                market.config.quote().to_string(),
+            );
+            let (price, size, funds) = validate(market, &order)?;
+            let amount = match (order.size, order.funds) {
+                (Some(s), _) => Amount::Size(s),
+                (None, _) => Amount::Funds(funds),
+            };
+            let limit = if order.order_type == OrderType::Limit {
+                Some(price)
+            } else {
+                None
+            };
+            let (hold_currency, hold) = match (order.side, order.order_type, amount) {
+                (Side::Sell, _, _) => (&base, size),
+                (Side::Buy, OrderType::Limit, _) => {
+                    let cost = units::quote(price, size);
+                    (&quote, cost + units::fee(cost, fee_rate))
+                }
+                (Side::Buy, OrderType::Market, Amount::Funds(f)) => {
+                    (&quote, f + units::fee(f, fee_rate))
+                }
+                // Without a price the whole balance may be needed, what's left is released after.
+                (Side::Buy, OrderType::Market, Amount::Size(_)) => {
+                    let available = state.balance(owner, &quote, AccountKind::Trade).available();
+                    (&quote, available)
+                }
+            };
+            if state
+                .balance(owner, hold_currency, AccountKind::Trade)
+                .available()
+                < hold
+                || hold <= 0
+            {
+                return Err(insufficient());
+            }
+            let id = state.order_id();
+            let mut events = Vec::new();
+            let fillable = order.time_in_force != TimeInForce::Fok
+                || state
+                    .market(&order.symbol)?
+                    .book
+                    .fillable(order.side, limit, amount);
+            state.orders.insert(
+                id.clone(),
+                Order {
+                    id: id.clone(),
+                    client_oid: order.client_oid.clone(),
+                    owner: owner.to_string(),
+                    symbol: order.symbol.clone(),
+                    side: order.side,
+                    order_type: order.order_type,
+                    time_in_force: order.time_in_force,
+                    price,
+                    size,
+                    funds,
+                    deal_size: 0,
+                    deal_funds: 0,
+                    fee: 0,
+                    hold_left: hold,
+                    is_active: true,
+                    cancel_exist: false,
+                    created_at: now,
+                },
+            );
+            state
+                .balance(owner, hold_currency, AccountKind::Trade)
+                .holds += hold;
+            let fills = if fillable {
+                state
+                    .markets
+                    .get_mut(&order.symbol)
+                    .expect("market checked")
+                    .book
+                    .take(order.side, limit, amount)
+            } else {
+                Vec::new()
+            };
+            let mut touched = BTreeSet::new();
+            for fill in &fills {
+                touched.insert((opposite(order.side), fill.price));
+                state.settle(&id, fill, fee_rate, now, &mut events);
+            }
+            let taker = state.orders.get(&id).expect("just inserted").clone();
+            // Market orders are done once they've taken what they can.
+            let filled = match amount {
+                Amount::Size(s) => taker.deal_size >= s,
+                Amount::Funds(_) => true,
+            };
+            if !filled
+                && fillable
+                && order.order_type == OrderType::Limit
+                && order.time_in_force == TimeInForce::Gtc
+            {
+                let market = state
+                    .markets
+                    .get_mut(&order.symbol)
+                    .expect("market checked");
+                market.book.rest(
+                    order.side,
+                    Resting {
+                        order_id: id.clone(),
+                        owner: owner.to_string(),
+                        price,
+                        size: size - taker.deal_size,
+                    },
+                );
+                touched.insert((order.side, price));
+                events.push(order_event(&taker, "open", now));
+            } else {
+                state.close(
+                    &id,
+                    !filled && order.order_type == OrderType::Limit,
+                    now,
+                    &mut events,
+                );
+            }
+            let market = state.markets.get(&order.symbol).expect("market checked");
+            events.extend(level2_event(market, &touched));
+            Ok((id, events))
+        })?;
+        self.emit(events);
+        Ok(id)
+    }
+
+    pub fn cancel(&self, owner: &str, order_id: &str) -> Result<Vec<String>, MockError> {
+        let now = self.now_ms();
+        let events = self.with_state(|state| {
+            let mut events = Vec::new();
+            state.cancel(owner, order_id, now, &mut events)?;
+            Ok(events)
+        })?;
+        self.emit(events);
+        Ok(vec![order_id.to_string()])
+    }
+
+    pub fn cancel_all(&self, owner: &str, symbol: Option<&str>) -> Vec<String> {
+        let now = self.now_ms();
+        let (ids, events) = self.with_state(|state| {
+            let ids: Vec<String> = state
+                .orders
+                .values()
+                .filter(|o| o.owner == owner && o.is_active && symbol.is_none_or(|s| s == o.symbol))
+                .map(|o| o.id.clone())
+                .collect();
+            let mut events = Vec::new();
+            for id in &ids {
+                let _ = state.cancel(owner, id, now, &mut events);
+            }
+            (ids, events)
+        });
+        self.emit(events);
+        ids
+    }
+
+    pub fn order(&self, owner: &str, order_id: &str) -> Result<Value, MockError> {
+        self.with_state(|state| {
+            state
+                .orders
+                .get(order_id)
+                .filter(|o| o.owner == owner)
+                .map(|o| order_json(o, &state.markets))
+                .ok_or_else(|| MockError::not_found("order not exist."))
+        })
+    }
+
+    /// The owner's order placed with `client_oid`, `Null` when there is none.
+    pub fn order_by_client_oid(&self, owner: &str, client_oid: &str) -> Value {
+        self.with_state(|state| {
+            state
+                .orders
+                .values()
+                .find(|o| o.owner == owner && o.client_oid == client_oid)
+                .map(|o| order_json(o, &state.markets))
+                .unwrap_or(Value::Null)
+        })
+    }
+
+    /// The owner's orders, newest first. `status` is `active` or `done`.
+    pub fn orders(
+        &self,
+        owner: &str,
+        status: Option<&str>,
+        symbol: Option<&str>,
+        side: Option<&str>,
+    ) -> Vec<Value> {
+        self.with_state(|state| {
+            state
+                .orders
+                .values()
+                .rev()
+                .filter(|o| o.owner == owner)
+                .filter(|o| match status {
+                    Some("active") => o.is_active,
+                    Some("done") => !o.is_active,
+                    _ => true,
+                })
+                .filter(|o| symbol.is_none_or(|s| s == o.symbol))
+                .filter(|o| side.is_none_or(|s| s == o.side.as_str()))
+                .map(|o| order_json(o, &state.markets))
+                .collect()
+        })
+    }
+
+    /// The owner's fills, newest first.
+    pub fn fills(&self, owner: &str, order_id: Option<&str>, symbol: Option<&str>) -> Vec<Value> {
+        self.with_state(|state| {
+            state
+                .fills
+                .iter()
+                .rev()
+                .filter(|f| f.owner == owner)
+                .filter(|f| order_id.is_none_or(|id| id == f.order_id))
+                .filter(|f| symbol.is_none_or(|s| s == f.symbol))
+                .map(|f| fill_json(f, self.fee_rate))
+                .collect()
+        })
+    }
+}
+
+impl State {
+    fn order_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("{:024x}", self.next_id)
+    }
+
+    fn market(&self, symbol: &str) -> Result<&Market, MockError> {
+        self.markets
+            .get(symbol)
+            .ok_or_else(|| MockError::new(400, "900001", "symbol not exists"))
+    }
+
+    fn balance(&mut self, owner: &str, currency: &str, kind: AccountKind) -> &mut Balance {
+        self.balances
+            .entry((owner.to_string(), currency.to_string(), kind))
+            .or_default()
+    }
+
+    /// Books one match for both the taker `order_id` and the resting order it hit.
+    fn settle(
+        &mut self,
+        order_id: &str,
+        fill: &Fill,
+        fee_rate: i64,
+        now: i64,
+        events: &mut Vec<FeedEvent>,
+    ) {
+        let trade_id = self.order_id();
+        let symbol = self.orders[order_id].symbol.clone();
+        let taker_side = self.orders[order_id].side;
+        let market = self.markets.get_mut(&symbol).expect("order has a market");
+        market.sequence += 1;
+        market.last = fill.price;
+        market.vol += fill.size;
+        market.vol_value += units::quote(fill.price, fill.size);
+        events.push(FeedEvent {
+            topic: format!("/market/match:{}", symbol),
+            subject: "trade.l3match".to_string(),
+            owner: None,
+            data: json!({
+                "sequence": market.sequence.to_string(),
+                "symbol": symbol,
+                "side": taker_side.as_str(),
+                "size": units::format(fill.size),
+                "price": units::format(fill.price),
+                "takerOrderId": order_id,
+                "makerOrderId": fill.maker_order_id,
+                "tradeId": trade_id,
+                "time": (now * 1_000_000).to_string(),
+                "type": "match"
+            }),
+        });
+        let (best_bid, best_bid_size) = market.book.best_bid().unwrap_or_default();
+        let (best_ask, best_ask_size) = market.book.best_ask().unwrap_or_default();
+        events.push(FeedEvent {
+            topic: format!("/market/ticker:{}", symbol),
+            subject: "trade.ticker".to_string(),
+            owner: None,
+            data: json!({
+                "sequence": market.sequence.to_string(),
+                "price": units::format(fill.price),
+                "size": units::format(fill.size),
+                "bestBid": units::format(best_bid),
+                "bestBidSize": units::format(best_bid_size),
+                "bestAsk": units::format(best_ask),
+                "bestAskSize": units::format(best_ask_size)
+            }),
+        });
+        self.book_fill(
+            order_id,
+            &fill.maker_order_id,
+            &trade_id,
+            "taker",
+            fill,
+            fee_rate,
+            now,
+            events,
+        );
+        self.book_fill(
+            &fill.maker_order_id,
+            order_id,
+            &trade_id,
+            "maker",
+            fill,
+            fee_rate,
+            now,
+            events,
+        );
+        let maker = &self.orders[&fill.maker_order_id];
+        if maker.deal_size >= maker.size {
+            self.close(&fill.maker_order_id, false, now, events);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn book_fill(
+        &mut self,
+        order_id: &str,
+        counter_order_id: &str,
+        trade_id: &str,
+        liquidity: &'static str,
+        fill: &Fill,
+        fee_rate: i64,
+        now: i64,
+        events: &mut Vec<FeedEvent>,
+    ) {
+        let cost = units::quote(fill.price, fill.size);
+        let fee = units::fee(cost, fee_rate);
+        let State {
+            orders,
+            markets,
+            balances,
+            fills,
+            ..
+        } = self;
+        let order = orders.get_mut(order_id).expect("fill of a known order");
+        order.deal_size += fill.size;
+        order.deal_funds += cost;
+        order.fee += fee;
+        if order.owner == HOUSE {
+            return;
+        }
+        let market = &markets[&order.symbol].config;
+        let mut balance = |currency: &str| {
+            *balances
+                .entry((
+                    order.owner.clone(),
+                    currency.to_string(),
+                    AccountKind::Trade,
+                ))
+                .or_default()
+        };
+        let (mut base, mut quote) = (balance(market.base()), balance(market.quote()));
+        match order.side {
+            Side::Buy => {
+                let spent = cost + fee;
+                let reserved = match order.order_type {
+                    OrderType::Limit => {
+                        let at_limit = units::quote(order.price, fill.size);
+                        at_limit + units::fee(at_limit, fee_rate)
+                    }
+                    OrderType::Market => spent,
+                };
+                let release = reserved.min(order.hold_left);
+                order.hold_left -= release;
+                quote.holds -= release;
+                quote.total -= spent;
+                base.total += fill.size;
+            }
+            Side::Sell => {
+                let release = fill.size.min(order.hold_left);
+                order.hold_left -= release;
+                base.holds -= release;
+                base.total -= fill.size;
+                quote.total += cost - fee;
+            }
+        }
+        balances.insert(
+            (
+                order.owner.clone(),
+                market.base().to_string(),
+                AccountKind::Trade,
+            ),
+            base,
+        );
+        balances.insert(
+            (
+                order.owner.clone(),
+                market.quote().to_string(),
+                AccountKind::Trade,
+            ),
+            quote,
+        );
+        fills.push(FillRecord {
+            owner: order.owner.clone(),
+            symbol: order.symbol.clone(),
+            trade_id: trade_id.to_string(),
+            order_id: order_id.to_string(),
+            counter_order_id: counter_order_id.to_string(),
+            side: order.side,
+            liquidity,
+            order_type: order.order_type,
+            price: fill.price,
+            size: fill.size,
+            funds: cost,
+            fee,
+            fee_currency: market.quote().to_string(),
+            created_at: now,
+        });
+        let mut event = order_event(order, "match", now);
+        event.data["liquidity"] = json!(liquidity);
+        event.data["matchPrice"] = json!(units::format(fill.price));
+        event.data["matchSize"] = json!(units::format(fill.size));
+        event.data["tradeId"] = json!(trade_id);
+        events.push(event);
+    }
+
+    /// Marks an order done and releases what is still held for it.
+    fn close(&mut self, order_id: &str, cancelled: bool, now: i64, events: &mut Vec<FeedEvent>) {
+        let State {
+            orders,
+            markets,
+            balances,
+            ..
+        } = self;
+        let order = orders.get_mut(order_id).expect("closing a known order");
+        order.is_active = false;
+        order.cancel_exist = cancelled;
+        if order.owner != HOUSE && order.hold_left > 0 {
+            let market = &markets[&order.symbol].config;
+            let currency = match order.side {
+                Side::Buy => market.quote(),
+                Side::Sell => market.base(),
+            };
+            let balance = balances
+                .entry((
+                    order.owner.clone(),
+                    currency.to_string(),
+                    AccountKind::Trade,
+                ))
+                .or_default();
+            balance.holds -= order.hold_left;
+            order.hold_left = 0;
+        }
+        events.push(order_event(
+            order,
+            if cancelled { "canceled" } else { "filled" },
+            now,
+        ));
+    }
+
+    fn cancel(
+        &mut self,
+        owner: &str,
+        order_id: &str,
+        now: i64,
+        events: &mut Vec<FeedEvent>,
+    ) -> Result<(), MockError> {
+        let order = self
+            .orders
+            .get(order_id)
+            .filter(|o| o.owner == owner && o.is_active)
+            .ok_or_else(|| MockError::bad_request("order_not_exist_or_not_allow_to_cancel"))?;
+        let market = self
+            .markets
+            .get_mut(&order.symbol)
+            .expect("order has a market");
+        let mut touched = BTreeSet::new();
+        if let Some((side, resting)) = market.book.cancel(order_id) {
+            touched.insert((side, resting.price));
+        }
+        events.extend(level2_event(market, &touched));
+        self.close(order_id, true, now, events);
+        Ok(())
+    }
+}
+
+// Checks increments and minimums, returns the price, size and funds of the order.
+fn validate(market: &Market, order: &NewOrder) -> Result<(i64, i64, i64), MockError> {
+    let (price, size, funds) = match (order.order_type, order.price, order.size, order.funds) {
+        (OrderType::Limit, Some(p), Some(s), _) if p > 0 => (p, s, 0),
+        (OrderType::Limit, _, _, _) => {
+            return Err(MockError::bad_request(
+                "Limit orders need a price and a size.",
+            ))
+        }
+        (OrderType::Market, _, Some(s), None) => (0, s, 0),
+        (OrderType::Market, _, None, Some(f)) if order.side == Side::Buy => (0, 0, f),
+        (OrderType::Market, _, _, _) => {
+            return Err(MockError::bad_request(
+                "Market orders need either a size or, for buys, funds.",
+            ))
+        }
+    };
+    if order.size.is_some() && size < market.base_min_size {
+        return Err(MockError::bad_request(
+            "Order size below the minimum requirement.",
+        ));
+    }
+    if size % market.base_increment != 0 {
+        return Err(MockError::bad_request("Order size increment invalid."));
+    }
+    if price % market.price_increment != 0 {
+        return Err(MockError::bad_request("Order price increment invalid."));
+    }
+    if funds < 0 || funds % market.quote_increment != 0 {
+        return Err(MockError::bad_request("Order funds invalid."));
+    }
+    Ok((price, size, funds))
+}
+
+fn insufficient() -> MockError {
+    MockError::new(400, "200004", "Balance insufficient!")
+}
+
+fn opposite(side: Side) -> Side {
+    match side {
+        Side::Buy => Side::Sell,
+        Side::Sell => Side::Buy,
+    }
+}
+
+fn order_type_str(order_type: OrderType) -> &'static str {
+    match order_type {
+        OrderType::Limit => "limit",
+        OrderType::Market => "market",
+    }
+}
+
+// A private `/spotMarket/tradeOrders` message for the order's owner.
+fn order_event(order: &Order, kind: &str, now: i64) -> FeedEvent {
+    let status = match kind {
+        "open" | "match" => "open",
+        _ => "done",
+    };
+    FeedEvent {
+        topic: "/spotMarket/tradeOrders".to_string(),
+        subject: "orderChange".to_string(),
+        owner: Some(order.owner.clone()),
+        data: json!({
+            "symbol": order.symbol,
+            "orderType": order_type_str(order.order_type),
+            "side": order.side.as_str(),
+            "type": kind,
+            "orderId": order.id,
+            "orderTime": order.created_at,
+            "size": units::format(order.size),
+            "filledSize": units::format(order.deal_size),
+            "price": units::format(order.price),
+            "clientOid": order.client_oid,
+            "remainSize": units::format((order.size - order.deal_size).max(0)),
+            "status": status,
+            "ts": now * 1_000_000
+        }),
+    }
+}
+
+// A `/market/level2` update with the new size of every touched price, 0 for gone levels.
+fn level2_event(market: &Market, touched: &BTreeSet<(Side, i64)>) -> Option<FeedEvent> {
+    if touched.is_empty() {
+        return None;
+    }
+    let sequence = market.sequence.to_string();
+    let changes = |side: Side| -> Vec<[String; 3]> {
+        touched
+            .iter()
+            .filter(|(s, _)| *s == side)
+            .map(|(s, price)| {
+                [
+                    units::format(*price),
+                    units::format(market.book.level(*s, *price)),
+                    sequence.clone(),
+                ]
+            })
+            .collect()
+    };
+    Some(FeedEvent {
+        topic: format!("/market/level2:{}", market.config.symbol),
+        subject: "trade.l2update".to_string(),
+        owner: None,
+        data: json!({
+            "sequenceStart": market.sequence,
+            "sequenceEnd": market.sequence,
+            "symbol": market.config.symbol,
+            "changes": { "asks": changes(Side::Sell), "bids": changes(Side::Buy) }
+        }),
+    })
+}
+
+fn order_json(order: &Order, markets: &BTreeMap<String, Market>) -> Value {
+    let fee_currency = markets
+        .get(&order.symbol)
+        .map(|m| m.config.quote().to_string())
+        .unwrap_or_default();
+    json!({
+        "id": order.id,
+        "symbol": order.symbol,
+        "opType": "DEAL",
+        "type": order_type_str(order.order_type),
+        "side": order.side.as_str(),
+        "price": units::format(order.price),
+        "size": units::format(order.size),
+        "funds": units::format(order.funds),
+        "dealFunds": units::format(order.deal_funds),
+        "dealSize": units::format(order.deal_size),
+        "fee": units::format(order.fee),
+        "feeCurrency": fee_currency,
+        "stp": "",
+        "stop": "",
+        "stopTriggered": false,
+        "stopPrice": "0",
+        "timeInForce": order.time_in_force.as_str(),
+        "postOnly": false,
+        "hidden": false,
+        "iceberg": false,
+        "visibleSize": "0",
+        "cancelAfter": 0,
+        "channel": "API",
+        "clientOid": order.client_oid,
+        "remark": null,
+        "tags": null,
+        "isActive": order.is_active,
+        "cancelExist": order.cancel_exist,
+        "createdAt": order.created_at,
+        "tradeType": "TRADE"
+    })
+}
+
+fn fill_json(fill: &FillRecord, fee_rate: i64) -> Value {
+    json!({
+        "symbol": fill.symbol,
+        "tradeId": fill.trade_id,
+        "orderId": fill.order_id,
+        "counterOrderId": fill.counter_order_id,
+        "side": fill.side.as_str(),
+        "liquidity": fill.liquidity,
+        "forceTaker": false,
+        "price": units::format(fill.price),
+        "size": units::format(fill.size),
+        "funds": units::format(fill.funds),
+        "fee": units::format(fill.fee),
+        "feeRate": units::format(fee_rate),
+        "feeCurrency": fill.fee_currency,
+        "stop": "",
+        "type": order_type_str(fill.order_type),
+        "createdAt": fill.created_at,
+        "tradeType": "TRADE"
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::book::Side;
+    use crate::config::{MockConfig, MockMarket, MockUser};
+    use crate::exchange::{AccountKind, Exchange, NewOrder, OrderType, TimeInForce};
+    use crate::units;
+
+    fn exchange() -> Exchange {
+        let mut config = MockConfig {
+            fee_rate: 0.0,
+            ..MockConfig::default()
+        };
+        config.markets.push(MockMarket::new("ETH-BTC", 0.05));
+        config.users.push(MockUser {
+            api_key: "alice".to_string(),
+            trade: vec![("BTC".to_string(), 10.0)].into_iter().collect(),
+            ..MockUser::default()
+        });
+        Exchange::new(&config)
+    }
+
+    fn limit_buy(price: &str, size: &str, time_in_force: TimeInForce) -> NewOrder {
+        NewOrder {
+            client_oid: "oid".to_string(),
+            symbol: "ETH-BTC".to_string(),
+            side: Side::Buy,
+            order_type: OrderType::Limit,
+            price: units::parse(price),
+            size: units::parse(size),
+            funds: None,
+            time_in_force,
+        }
+    }
+
+    #[test]
+    fn holds_resting_orders_until_cancelled() {
+        let exchange = exchange();
+        let id = exchange
+            .place("alice", limit_buy("0.04", "10", TimeInForce::Gtc))
+            .unwrap();
+        let btc = exchange.balance("alice", "BTC", AccountKind::Trade);
+        assert_eq!(
+            (btc.total, btc.holds),
+            (10 * units::SCALE, units::parse("0.4").unwrap())
+        );
+        assert_eq!(exchange.order("alice", &id).unwrap()["isActive"], true);
+        assert!(exchange.order("bob", &id).is_err());
+
+        exchange.cancel("alice", &id).unwrap();
+        assert_eq!(
+            exchange.balance("alice", "BTC", AccountKind::Trade).holds,
+            0
+        );
+        assert_eq!(exchange.order("alice", &id).unwrap()["cancelExist"], true);
+    }
+
+    #[test]
+    fn settles_fills_and_drops_unfilled_fok() {
+        let exchange = exchange();
+        // The first ask is one step, 0.00005, above 0.05 with 100 ETH on it.
+        exchange
+            .place("alice", limit_buy("0.05005", "2", TimeInForce::Gtc))
+            .unwrap();
+        assert_eq!(
+            exchange.balance("alice", "ETH", AccountKind::Trade).total,
+            2 * units::SCALE
+        );
+        let btc = exchange.balance("alice", "BTC", AccountKind::Trade);
+        assert_eq!((btc.total, btc.holds), (units::parse("9.8999").unwrap(), 0));
+
+        let id = exchange
+            .place("alice", limit_buy("0.05005", "101", TimeInForce::Fok))
+            .unwrap();
+        assert_eq!(exchange.order("alice", &id).unwrap()["dealSize"], "0");
+        assert_eq!(
+            exchange.balance("alice", "BTC", AccountKind::Trade).holds,
+            0
+        );
+        assert!(exchange
+            .place("alice", limit_buy("0.05", "200", TimeInForce::Gtc))
+            .is_err());
+    }
+}
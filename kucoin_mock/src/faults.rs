@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::error::MockError;
+
+/// Errors drawn from when `error_rate` hits, the ones a client should retry.
+const RANDOM_ERRORS: [(u16, &str, &str); 3] = [
+    (500, "500000", "Internal Server Error"),
+    (503, "503000", "Service Unavailable"),
+    (429, "429000", "Too Many Requests"),
+];
+
+#[derive(Debug, Default)]
+struct Knobs {
+    latency: Duration,
+    error_rate: f64,
+    queued: VecDeque<MockError>,
+}
+
+/// Latency and errors added to REST requests, adjustable while the mock runs. Clones share
+/// the knobs.
+#[derive(Clone, Debug, Default)]
+pub struct Faults {
+    knobs: Arc<Mutex<Knobs>>,
+}
+
+/// Body of `POST /mock/faults`, every field optional.
+#[derive(Deserialize, Debug, Default)]
+pub struct FaultUpdate {
+    pub latency_ms: Option<u64>,
+    pub error_rate: Option<f64>,
+    /// Errors answered to the next requests, in order.
+    #[serde(default)]
+    pub fail_next: Vec<QueuedError>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct QueuedError {
+    pub status: u16,
+    pub code: String,
+    #[serde(default)]
+    pub msg: String,
+}
+
+impl Faults {
+    pub fn new(latency: Duration, error_rate: f64) -> Self {
+        let faults = Faults::default();
+        faults.set_latency(latency);
+        faults.set_error_rate(error_rate);
+        faults
+    }
+
+    fn with_knobs<T>(&self, f: impl FnOnce(&mut Knobs) -> T) -> T {
+        let mut knobs = self.knobs.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut knobs)
+    }
+
+    pub fn set_latency(&self, latency: Duration) {
+        self.with_knobs(|k| k.latency = latency);
+    }
+
+    pub fn latency(&self) -> Duration {
+        self.with_knobs(|k| k.latency)
+    }
+
+    pub fn set_error_rate(&self, rate: f64) {
+        self.with_knobs(|k| k.error_rate = rate.clamp(0.0, 1.0));
+    }
+
+    /// Answers the next request with `error`, after any already queued.
+    pub fn fail_next(&self, error: MockError) {
+        self.with_knobs(|k| k.queued.push_back(error));
+    }
+
+    pub fn apply(&self, update: FaultUpdate) {
+        if let Some(ms) = update.latency_ms {
+            self.set_latency(Duration::from_millis(ms));
+        }
+        if let Some(rate) = update.error_rate {
+            self.set_error_rate(rate);
+        }
+        for e in update.fail_next {
+            self.fail_next(MockError::new(e.status, &e.code, &e.msg));
+        }
+    }
+
+    /// The error to answer the current request with, if any.
+    pub fn next_error(&self) -> Option<MockError> {
+        self.with_knobs(|k| {
+            if let Some(e) = k.queued.pop_front() {
+                return Some(e);
+            }
+            let mut rng = rand::thread_rng();
+            if k.error_rate > 0.0 && rng.gen_bool(k.error_rate) {
+                let (status, code, msg) = RANDOM_ERRORS[rng.gen_range(0..RANDOM_ERRORS.len())];
+                return Some(MockError::new(status, code, msg));
+            }
+            None
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::MockError;
+    use crate::faults::Faults;
+    use std::time::Duration;
+
+    #[test]
+    fn queued_errors_come_first_then_the_rate() {
+        let faults = Faults::new(Duration::from_millis(0), 0.0);
+        faults.fail_next(MockError::new(429, "429000", "Too Many Requests"));
+        assert_eq!(faults.next_error().unwrap().status, 429);
+        assert!(faults.next_error().is_none());
+        faults.set_error_rate(1.0);
+        assert!(faults.next_error().is_some());
+    }
+}
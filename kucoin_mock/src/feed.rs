@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::exchange::{Exchange, FeedEvent};
+
+/// Connect tokens handed out by the bullet endpoints, with the account a private token
+/// belongs to.
+#[derive(Clone, Debug, Default)]
+pub struct Tokens {
+    tokens: Arc<Mutex<HashMap<String, Option<String>>>>,
+}
+
+impl Tokens {
+    pub fn issue(&self, owner: Option<String>) -> String {
+        let token: String = (0..32)
+            .map(|_| format!("{:x}", rand::thread_rng().gen_range(0..16)))
+            .collect();
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.insert(token.clone(), owner);
+        token
+    }
+
+    /// `None` for unknown tokens, `Some(None)` for public ones.
+    pub fn owner(&self, token: &str) -> Option<Option<String>> {
+        let tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.get(token).cloned()
+    }
+}
+
+/// Accepts websocket connections until the listener fails.
+pub async fn serve(listener: TcpListener, exchange: Exchange, tokens: Tokens) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let exchange = exchange.clone();
+                let tokens = tokens.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = connection(stream, exchange, tokens).await {
+                        println!("Mock websocket closed: {}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                println!("Mock websocket listener failed: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+// The token is the `token` query parameter, the client appends more after it.
+fn token_of(request: &Request) -> Option<String> {
+    request.uri().query().and_then(|query| {
+        query
+            .split(['&', '?'])
+            .find_map(|pair| pair.strip_prefix("token="))
+            .map(|t| t.to_string())
+    })
+}
+
+async fn connection(stream: TcpStream, exchange: Exchange, tokens: Tokens) -> anyhow::Result<()> {
+    let mut owner = None;
+    // The error type is tungstenite's, the handshake callback has to return it.
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+        match token_of(request).and_then(|t| tokens.owner(&t)) {
+            Some(o) => {
+                owner = o;
+                Ok(response)
+            }
+            None => {
+                let mut refused = ErrorResponse::new(Some("token is invalid".to_string()));
+                *refused.status_mut() = StatusCode::UNAUTHORIZED;
+                Err(refused)
+            }
+        }
+    };
+    let socket = tokio_tungstenite::accept_hdr_async(stream, callback).await?;
+    let (mut sink, mut incoming) = socket.split();
+    let connect_id: u64 = rand::thread_rng().gen();
+    sink.send(text(
+        json!({ "id": connect_id.to_string(), "type": "welcome" }),
+    ))
+    .await?;
+    let mut events = exchange.subscribe();
+    let mut topics: Vec<String> = Vec::new();
+    loop {
+        tokio::select! {
+            msg = incoming.next() => match msg {
+                Some(Ok(Message::Text(msg))) => {
+                    if let Some(reply) = handle(&msg, &mut topics) {
+                        sink.send(text(reply)).await?;
+                    }
+                }
+                Some(Ok(Message::Ping(payload))) => sink.send(Message::Pong(payload)).await?,
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if let Some((topic, subject)) = deliver(&topics, &event, &owner) {
+                        let msg = json!({ "type": "message", "topic": topic, "subject": subject, "data": event.data });
+                        sink.send(text(msg)).await?;
+                    }
+                }
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+}
+
+fn text(value: Value) -> Message {
+    Message::Text(value.to_string())
+}
+
+// Subscribes, unsubscribes and answers pings, returns the reply to send if any.
+fn handle(msg: &str, topics: &mut Vec<String>) -> Option<Value> {
+    let msg: Value = serde_json::from_str(msg).ok()?;
+    let id = msg["id"].as_str().unwrap_or_default().to_string();
+    let topic = msg["topic"].as_str().unwrap_or_default().to_string();
+    match msg["type"].as_str()? {
+        "ping" => Some(json!({ "id": id, "type": "pong" })),
+        "subscribe" => {
+            if !topics.contains(&topic) {
+                topics.push(topic);
+            }
+            if msg["response"].as_bool().unwrap_or(false) {
+                Some(json!({ "id": id, "type": "ack" }))
+            } else {
+                None
+            }
+        }
+        "unsubscribe" => {
+            topics.retain(|t| *t != topic);
+            if msg["response"].as_bool().unwrap_or(false) {
+                Some(json!({ "id": id, "type": "ack" }))
+            } else {
+                None
+            }
+        }
+        _ => {
+            Some(json!({ "id": id, "type": "error", "code": 400, "data": "unknown message type" }))
+        }
+    }
+}
+
+/// The topic and subject `event` goes out with on a connection subscribed to `topics`, if
+/// it goes out at all. A `/market/ticker:all` subscription gets every ticker with the
+/// symbol as the subject, the way Kucoin sends them.
+pub fn deliver(
+    topics: &[String],
+    event: &FeedEvent,
+    owner: &Option<String>,
+) -> Option<(String, String)> {
+    if event.owner.is_some() && event.owner != *owner {
+        return None;
+    }
+    let (prefix, symbol) = event.topic.split_once(':').unwrap_or((&event.topic, ""));
+    topics.iter().find_map(|subscription| {
+        if *subscription == event.topic {
+            return Some((event.topic.clone(), event.subject.clone()));
+        }
+        let (sub_prefix, symbols) = subscription.split_once(':')?;
+        if sub_prefix != prefix {
+            return None;
+        }
+        if prefix == "/market/ticker" && symbols == "all" {
+            return Some((subscription.clone(), symbol.to_string()));
+        }
+        if symbols.split(',').any(|s| s == symbol) {
+            return Some((event.topic.clone(), event.subject.clone()));
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::exchange::FeedEvent;
+    use crate::feed::{deliver, handle};
+
+    fn event(topic: &str, owner: Option<&str>) -> FeedEvent {
+        FeedEvent {
+            topic: topic.to_string(),
+            subject: "trade.ticker".to_string(),
+            owner: owner.map(|o| o.to_string()),
+            data: json!({}),
+        }
+    }
+
+    #[test]
+    fn routes_events_to_subscriptions() {
+        let mut topics = Vec::new();
+        let ack = handle(
+            r#"{"id":"1","type":"subscribe","topic":"/market/ticker:ETH-BTC,XRP-BTC","response":true}"#,
+            &mut topics,
+        );
+        assert_eq!(ack.unwrap()["type"], "ack");
+        let public = event("/market/ticker:XRP-BTC", None);
+        assert!(deliver(&topics, &public, &None).is_some());
+        assert!(deliver(&topics, &event("/market/ticker:ADA-BTC", None), &None).is_none());
+
+        topics.push("/market/ticker:all".to_string());
+        let (topic, subject) =
+            deliver(&topics[1..], &event("/market/ticker:ADA-BTC", None), &None).unwrap();
+        assert_eq!(
+            (topic.as_str(), subject.as_str()),
+            ("/market/ticker:all", "ADA-BTC")
+        );
+
+        topics.push("/spotMarket/tradeOrders".to_string());
+        let private = event("/spotMarket/tradeOrders", Some("alice"));
+        assert!(deliver(&topics, &private, &Some("alice".to_string())).is_some());
+        assert!(deliver(&topics, &private, &Some("bob".to_string())).is_none());
+        assert!(deliver(&topics, &private, &None).is_none());
+    }
+}
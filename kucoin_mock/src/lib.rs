@@ -0,0 +1,222 @@
+//! A stand-in for the Kucoin API to run the bot and its tests against, offline.
+//!
+//! The REST side answers the endpoints the bot uses (symbols, tickers, order books, accounts,
+//! transfers, orders and order batches, fills and the websocket bullets) from in-memory
+//! balances and a price-time matching engine. Signed endpoints check KC-API-SIGN the way
+//! Kucoin does, so a client signing wrong is caught here too. The websocket side pushes tickers, level2
+//! changes, matches and private order changes as orders trade.
+//!
+//! Every market is seeded with house liquidity around its configured price. Latency and
+//! errors can be injected from the config, from `Faults` in tests or with `POST /mock/faults`
+//! while running.
+//!
+//! ```no_run
+//! use kucoin_mock::config::{MockConfig, MockMarket, MockUser};
+//! use kucoin_mock::MockServer;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let mut config = MockConfig::default();
+//! config.markets.push(MockMarket::new("ETH-BTC", 0.05));
+//! config.users.push(MockUser {
+//!     api_key: "key".to_string(),
+//!     api_secret: "secret".to_string(),
+//!     api_pass: "pass".to_string(),
+//!     ..MockUser::default()
+//! });
+//! let server = MockServer::start(config).await?;
+//! println!("Point the client at {}", server.url());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::auth::Authenticator;
+use crate::config::MockConfig;
+use crate::exchange::Exchange;
+use crate::faults::Faults;
+use crate::feed::Tokens;
+use crate::rest::MockState;
+
+pub mod auth;
+pub mod book;
+pub mod config;
+pub mod error;
+pub mod exchange;
+pub mod faults;
+pub mod feed;
+pub mod rest;
+pub mod units;
+
+/// A running mock. The servers run until the process exits.
+pub struct MockServer {
+    pub rest_addr: SocketAddr,
+    pub ws_addr: SocketAddr,
+    pub exchange: Exchange,
+    pub faults: Faults,
+}
+
+impl MockServer {
+    /// Binds both servers and starts serving. Needs to be called within a tokio runtime.
+    pub async fn start(config: MockConfig) -> Result<MockServer> {
+        config.validate()?;
+        let exchange = Exchange::new(&config);
+        let faults = Faults::new(Duration::from_millis(config.latency_ms), config.error_rate);
+        let tokens = Tokens::default();
+
+        let ws_listener = tokio::net::TcpListener::bind((config.host.as_str(), config.ws_port))
+            .await
+            .context("Could not bind the mock websocket port")?;
+        let ws_addr = ws_listener.local_addr()?;
+        tokio::spawn(feed::serve(ws_listener, exchange.clone(), tokens.clone()));
+
+        let rest_listener = std::net::TcpListener::bind((config.host.as_str(), config.rest_port))
+            .context("Could not bind the mock REST port")?;
+        let rest_addr = rest_listener.local_addr()?;
+        let app = rest::app(MockState {
+            exchange: exchange.clone(),
+            auth: Authenticator::new(&config.users, config.timestamp_window_ms),
+            faults: faults.clone(),
+            tokens,
+            ws_url: format!("ws://{}/endpoint", ws_addr),
+        });
+        async_std::task::spawn(async move {
+            if let Err(e) = app.listen(rest_listener).await {
+                println!("Mock REST server stopped: {}", e);
+            }
+        });
+
+        Ok(MockServer {
+            rest_addr,
+            ws_addr,
+            exchange,
+            faults,
+        })
+    }
+
+    /// Base URL to give the client, e.g. as `KucoinEnv::Custom`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.rest_addr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use kucoin_rs_custom::kucoin::client::{Credentials, Kucoin, KucoinEnv};
+    use kucoin_rs_custom::kucoin::model::trade::{Side, TradeType};
+    use kucoin_rs_custom::kucoin::trade::BatchOrder;
+
+    use crate::config::{MockConfig, MockMarket, MockUser};
+    use crate::error::MockError;
+    use crate::MockServer;
+
+    fn config() -> MockConfig {
+        let mut config = MockConfig::default();
+        config.markets.push(MockMarket::new("ETH-BTC", 0.05));
+        config.users.push(MockUser {
+            api_key: "key".to_string(),
+            api_secret: "secret".to_string(),
+            api_pass: "pass".to_string(),
+            trade: vec![("BTC".to_string(), 1.0)].into_iter().collect(),
+            ..MockUser::default()
+        });
+        config
+    }
+
+    #[tokio::test]
+    async fn trades_through_the_client() {
+        let server = MockServer::start(config()).await.unwrap();
+        let client = Kucoin::new(
+            KucoinEnv::Custom(server.url()),
+            Some(Credentials::new("key", "secret", "pass")),
+        )
+        .unwrap();
+        client.sync_time().await.unwrap();
+
+        let symbols = client.get_symbol_list(None).await.unwrap();
+        assert_eq!(symbols[0].symbol, "ETH-BTC");
+
+        let placed = client
+            .post_market_order(
+                "oid-1",
+                "ETH-BTC",
+                Side::Buy,
+                None,
+                Some("0.1".to_string()),
+                None,
+            )
+            .await
+            .unwrap();
+        let order = client.get_order(&placed.order_id).await.unwrap();
+        assert_eq!(order.client_oid, "oid-1");
+        assert_eq!(order.is_active, Some(false));
+        let by_oid = client.get_order_by_client_oid("oid-1").await.unwrap();
+        assert_eq!(by_oid.unwrap().id, placed.order_id);
+        assert!(client
+            .get_order_by_client_oid("unknown")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(order.deal_size.parse::<f64>().unwrap() > 0.0);
+
+        let accounts = client.get_accounts_list(None, Some("trade")).await.unwrap();
+        let eth = accounts.iter().find(|a| a.currency == "ETH").unwrap();
+        assert_eq!(eth.balance, order.deal_size);
+        let btc = accounts.iter().find(|a| a.currency == "BTC").unwrap();
+        assert!(btc.balance.parse::<f64>().unwrap() < 0.9);
+        let stops = client
+            .cancel_all_stop_orders(None, Some(TradeType::Trade), None)
+            .await
+            .unwrap();
+        assert!(stops.cancelled_order_ids.is_empty());
+
+        // Injected errors reach the client as errors.
+        server
+            .faults
+            .fail_next(MockError::new(503, "503000", "Service unavailable"));
+        assert!(client.get_order(&placed.order_id).await.is_err());
+        assert!(client.get_order(&placed.order_id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn places_batches_order_by_order() {
+        let server = MockServer::start(config()).await.unwrap();
+        let client = Kucoin::new(
+            KucoinEnv::Custom(server.url()),
+            Some(Credentials::new("key", "secret", "pass")),
+        )
+        .unwrap();
+        client.sync_time().await.unwrap();
+
+        // Bids well under the book rest, the last one needs more BTC than there is.
+        let orders = [
+            BatchOrder::new("oid-1", Side::Buy, "0.01", "10"),
+            BatchOrder::new("oid-2", Side::Buy, "0.02", "10"),
+            BatchOrder::new("oid-3", Side::Buy, "0.01", "1000"),
+        ];
+        let results = client.post_multi_orders("ETH-BTC", &orders).await.unwrap();
+        assert!(results[0].is_success() && results[1].is_success());
+        assert!(!results[2].is_success());
+        assert_eq!(results[2].client_oid, "oid-3");
+        let order = client
+            .get_order(results[1].id.as_deref().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(order.client_oid, "oid-2");
+        assert_eq!(order.is_active, Some(true));
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_signatures() {
+        let server = MockServer::start(config()).await.unwrap();
+        let client = Kucoin::new(
+            KucoinEnv::Custom(server.url()),
+            Some(Credentials::new("key", "wrong", "pass")),
+        )
+        .unwrap();
+        assert!(client.get_accounts_list(None, None).await.is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use kucoin_mock::config::{MockConfig, MockMarket, MockUser};
+use kucoin_mock::MockServer;
+
+/// Runs the mock until killed. Takes the path of a JSON config, or starts a demo with a
+/// few BTC markets and one account when there is none.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = match std::env::args().nth(1) {
+        Some(path) => MockConfig::load(Path::new(&path))?,
+        None => demo_config(),
+    };
+    let server = MockServer::start(config).await?;
+    println!("Mock Kucoin REST API on {}", server.url());
+    println!("Mock Kucoin websocket on ws://{}/endpoint", server.ws_addr);
+    println!("Point the bot at it with KUCOIN_API_URL={}", server.url());
+    futures::future::pending::<()>().await;
+    Ok(())
+}
+
+fn demo_config() -> MockConfig {
+    MockConfig {
+        rest_port: 8800,
+        ws_port: 8801,
+        markets: vec![
+            MockMarket::new("ETH-BTC", 0.05),
+            MockMarket::new("XRP-BTC", 0.00002),
+            MockMarket::new("ADA-BTC", 0.00003),
+        ],
+        users: vec![MockUser {
+            api_key: "mock-key".to_string(),
+            api_secret: "mock-secret".to_string(),
+            api_pass: "mock-pass".to_string(),
+            main: HashMap::new(),
+            trade: vec![("BTC".to_string(), 1.0)].into_iter().collect(),
+        }],
+        ..MockConfig::default()
+    }
+}
@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Map, Value};
+use tide::{Body, Next, Request, Response};
+
+use crate::auth::{Authenticator, Signed};
+use crate::book::Side;
+use crate::error::MockError;
+use crate::exchange::{AccountKind, Exchange, NewOrder, OrderType, TimeInForce};
+use crate::faults::{FaultUpdate, Faults};
+use crate::feed::Tokens;
+use crate::units;
+
+#[derive(Clone)]
+pub struct MockState {
+    pub exchange: Exchange,
+    pub auth: Authenticator,
+    pub faults: Faults,
+    pub tokens: Tokens,
+    /// Where the websocket feed listens, handed out by the bullet endpoints.
+    pub ws_url: String,
+}
+
+type Query = HashMap<String, String>;
+
+/// The REST side of the mock: the endpoints the bot calls plus `POST /mock/faults`.
+pub fn app(state: MockState) -> tide::Server<MockState> {
+    let mut app = tide::with_state(state);
+    app.with(InjectFaults);
+    app.at("/api/v1/timestamp").get(|req: Request<MockState>| async move {
+        respond(Ok(json!(req.state().exchange.now_ms())))
+    });
+    app.at("/api/v1/symbols")
+        .get(|req: Request<MockState>| async move {
+            respond(Ok(json!(req.state().exchange.symbols())))
+        });
+    app.at("/api/v1/market/orderbook/level1")
+        .get(|req: Request<MockState>| async move {
+            let symbol = param(&query(&req), "symbol");
+            respond(symbol.and_then(|s| req.state().exchange.ticker(&s)))
+        });
+    app.at("/api/v1/market/allTickers").get(|req: Request<MockState>| async move {
+        respond(Ok(req.state().exchange.all_tickers()))
+    });
+    app.at("/api/v1/market/orderbook/level2_20")
+        .get(|req| level2(req, 20));
+    app.at("/api/v1/market/orderbook/level2_100")
+        .get(|req| level2(req, 100));
+    app.at("/api/v2/market/orderbook/level2")
+        .get(|req| level2(req, usize::MAX));
+    app.at("/api/v1/accounts").get(accounts);
+    app.at("/api/v1/accounts/transferable").get(transferable);
+    app.at("/api/v2/accounts/inner-transfer")
+        .post(inner_transfer);
+    app.at("/api/v1/orders")
+        .post(place_order)
+        .get(list_orders)
+        .delete(cancel_all);
+    app.at("/api/v1/orders/multi").post(place_orders);
+    app.at("/api/v1/stop-order/cancel")
+        .delete(cancel_all_stop_orders);
+    app.at("/api/v1/orders/:id")
+        .get(get_order)
+        .delete(cancel_order);
+    app.at("/api/v1/order/client-order/:oid")
+        .get(get_order_by_client_oid);
+    app.at("/api/v1/fills").get(list_fills);
+    app.at("/api/v1/bullet-public")
+        .post(|req| bullet(req, false));
+    app.at("/api/v1/bullet-private")
+        .post(|req| bullet(req, true));
+    app.at("/mock/faults")
+        .post(|mut req: Request<MockState>| async move {
+            let update: FaultUpdate = req.body_json().await?;
+            req.state().faults.apply(update);
+            respond(Ok(Value::Null))
+        });
+    app
+}
+
+/// Delays every API request by the configured latency and answers it with an injected error
+/// when one is due. `/mock` requests are left alone so the knobs stay reachable.
+struct InjectFaults;
+
+#[tide::utils::async_trait]
+impl tide::Middleware<MockState> for InjectFaults {
+    async fn handle(&self, req: Request<MockState>, next: Next<'_, MockState>) -> tide::Result {
+        if req.url().path().starts_with("/mock/") {
+            return Ok(next.run(req).await);
+        }
+        let faults = req.state().faults.clone();
+        let latency = faults.latency();
+        if !latency.is_zero() {
+            async_std::task::sleep(latency).await;
+        }
+        match faults.next_error() {
+            Some(e) => respond(Err(e)),
+            None => Ok(next.run(req).await),
+        }
+    }
+}
+
+/// Wraps data the way Kucoin does, errors become their status and code.
+fn respond(result: Result<Value, MockError>) -> tide::Result {
+    let (status, body) = match result {
+        Ok(data) => (200, json!({ "code": "200000", "data": data })),
+        Err(e) => (e.status, e.body()),
+    };
+    Ok(Response::builder(status)
+        .body(Body::from_json(&body)?)
+        .build())
+}
+
+fn query(req: &Request<MockState>) -> Query {
+    req.query().unwrap_or_default()
+}
+
+fn param(query: &Query, name: &str) -> Result<String, MockError> {
+    query
+        .get(name)
+        .cloned()
+        .ok_or_else(|| MockError::bad_request(&format!("{} is required", name)))
+}
+
+/// Checks the signature and returns the API key with the request body.
+async fn signed(req: &mut Request<MockState>) -> Result<(String, String), MockError> {
+    let body = req
+        .body_string()
+        .await
+        .map_err(|e| MockError::bad_request(&e.to_string()))?;
+    let method = req.method().to_string();
+    let state = req.state();
+    let request = Signed {
+        method: &method,
+        path: req.url().path(),
+        query: req.url().query(),
+        body: &body,
+    };
+    let owner = state.auth.verify(
+        |name| req.header(name).map(|v| v.as_str()),
+        &request,
+        state.exchange.now_ms(),
+    )?;
+    Ok((owner, body))
+}
+
+// Body fields may come as strings, as the client sends them, or as numbers.
+fn field(body: &Map<String, Value>, name: &str) -> Option<String> {
+    match body.get(name)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn amount(body: &Map<String, Value>, name: &str) -> Result<Option<i64>, MockError> {
+    field(body, name)
+        .map(|v| {
+            units::parse(&v)
+                .ok_or_else(|| MockError::bad_request(&format!("{} is not a valid number", name)))
+        })
+        .transpose()
+}
+
+fn json_body(body: &str) -> Result<Map<String, Value>, MockError> {
+    serde_json::from_str(body).map_err(|_| MockError::bad_request("body is not a JSON object"))
+}
+
+fn page(items: Vec<Value>, query: &Query) -> Value {
+    let current_page: usize = query
+        .get("currentPage")
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(1)
+        .max(1);
+    let page_size: usize = query
+        .get("pageSize")
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(50)
+        .clamp(1, 500);
+    let total = items.len();
+    let items: Vec<Value> = items
+        .into_iter()
+        .skip((current_page - 1) * page_size)
+        .take(page_size)
+        .collect();
+    json!({
+        "currentPage": current_page,
+        "pageSize": page_size,
+        "totalNum": total,
+        "totalPage": total.div_ceil(page_size),
+        "items": items
+    })
+}
+
+async fn level2(req: Request<MockState>, levels: usize) -> tide::Result {
+    let symbol = param(&query(&req), "symbol");
+    respond(symbol.and_then(|s| req.state().exchange.level2(&s, levels)))
+}
+
+async fn accounts(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, _) = signed(&mut req).await?;
+        let query = query(&req);
+        let kind = query.get("type").and_then(|t| AccountKind::parse(t));
+        let accounts =
+            req.state()
+                .exchange
+                .accounts(&owner, query.get("currency").map(|c| c.as_str()), kind);
+        Ok(json!(accounts))
+    };
+    respond(res.await)
+}
+
+async fn transferable(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, _) = signed(&mut req).await?;
+        let query = query(&req);
+        let currency = param(&query, "currency")?;
+        let kind = AccountKind::parse(&param(&query, "type")?)
+            .ok_or_else(|| MockError::bad_request("type must be MAIN or TRADE"))?;
+        Ok(req.state().exchange.transferable(&owner, &currency, kind))
+    };
+    respond(res.await)
+}
+
+async fn inner_transfer(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, body) = signed(&mut req).await?;
+        let body = json_body(&body)?;
+        let kind = |name| {
+            field(&body, name)
+                .and_then(|k| AccountKind::parse(&k))
+                .ok_or_else(|| MockError::bad_request(&format!("{} must be main or trade", name)))
+        };
+        let currency = field(&body, "currency")
+            .ok_or_else(|| MockError::bad_request("currency is required"))?;
+        let amount =
+            amount(&body, "amount")?.ok_or_else(|| MockError::bad_request("amount is required"))?;
+        let id = req.state().exchange.inner_transfer(
+            &owner,
+            &currency,
+            kind("from")?,
+            kind("to")?,
+            amount,
+        )?;
+        Ok(json!({ "orderId": id }))
+    };
+    respond(res.await)
+}
+
+// Batches are limit orders on one symbol, each placed or rejected on its own.
+async fn place_orders(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, body) = signed(&mut req).await?;
+        let body = json_body(&body)?;
+        let symbol =
+            field(&body, "symbol").ok_or_else(|| MockError::bad_request("symbol is required"))?;
+        let orders = match body.get("orderList") {
+            Some(Value::Array(orders)) if (1..=5).contains(&orders.len()) => orders,
+            _ => return Err(MockError::bad_request("orderList must hold 1 to 5 orders")),
+        };
+        let results: Vec<Value> = orders
+            .iter()
+            .map(|order| {
+                let mut order = order.as_object().cloned().unwrap_or_default();
+                order.insert("symbol".to_string(), json!(symbol));
+                let placed = match field(&order, "type").as_deref() {
+                    None | Some("limit") => {
+                        new_order(&order).and_then(|o| req.state().exchange.place(&owner, o))
+                    }
+                    Some(_) => Err(MockError::bad_request("batches only take limit orders")),
+                };
+                let (id, status, fail_msg) = match placed {
+                    Ok(id) => (json!(id), "success", Value::Null),
+                    Err(e) => (Value::Null, "fail", json!(e.msg)),
+                };
+                json!({
+                    "id": id,
+                    "clientOid": field(&order, "clientOid").unwrap_or_default(),
+                    "symbol": symbol,
+                    "type": "limit",
+                    "side": field(&order, "side").unwrap_or_default(),
+                    "price": field(&order, "price"),
+                    "size": field(&order, "size"),
+                    "status": status,
+                    "failMsg": fail_msg
+                })
+            })
+            .collect();
+        Ok(json!({ "data": results }))
+    };
+    respond(res.await)
+}
+
+async fn place_order(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, body) = signed(&mut req).await?;
+        let body = json_body(&body)?;
+        let id = req.state().exchange.place(&owner, new_order(&body)?)?;
+        Ok(json!({ "orderId": id }))
+    };
+    respond(res.await)
+}
+
+fn new_order(body: &Map<String, Value>) -> Result<NewOrder, MockError> {
+    let side = match field(body, "side").as_deref() {
+        Some("buy") => Side::Buy,
+        Some("sell") => Side::Sell,
+        _ => return Err(MockError::bad_request("side must be buy or sell")),
+    };
+    let order_type = match field(body, "type").as_deref() {
+        None | Some("limit") => OrderType::Limit,
+        Some("market") => OrderType::Market,
+        Some(_) => return Err(MockError::bad_request("type must be limit or market")),
+    };
+    let time_in_force = match field(body, "timeInForce") {
+        Some(t) => TimeInForce::parse(&t)
+            .ok_or_else(|| MockError::bad_request("timeInForce must be GTC, IOC or FOK"))?,
+        None => TimeInForce::Gtc,
+    };
+    Ok(NewOrder {
+        client_oid: field(body, "clientOid")
+            .ok_or_else(|| MockError::bad_request("clientOid is required"))?,
+        symbol: field(body, "symbol")
+            .ok_or_else(|| MockError::bad_request("symbol is required"))?,
+        side,
+        order_type,
+        price: amount(body, "price")?,
+        size: amount(body, "size")?,
+        funds: amount(body, "funds")?,
+        time_in_force,
+    })
+}
+
+async fn list_orders(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, _) = signed(&mut req).await?;
+        let query = query(&req);
+        let get = |name: &str| query.get(name).map(|v| v.as_str());
+        let orders = req
+            .state()
+            .exchange
+            .orders(&owner, get("status"), get("symbol"), get("side"));
+        Ok(page(orders, &query))
+    };
+    respond(res.await)
+}
+
+async fn get_order(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, _) = signed(&mut req).await?;
+        req.state()
+            .exchange
+            .order(&owner, req.param("id").unwrap_or_default())
+    };
+    respond(res.await)
+}
+
+// Unknown client oids answer with null data rather than an error, like Kucoin.
+async fn get_order_by_client_oid(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, _) = signed(&mut req).await?;
+        Ok(req
+            .state()
+            .exchange
+            .order_by_client_oid(&owner, req.param("oid").unwrap_or_default()))
+    };
+    respond(res.await)
+}
+
+async fn cancel_order(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, _) = signed(&mut req).await?;
+        let ids = req
+            .state()
+            .exchange
+            .cancel(&owner, req.param("id").unwrap_or_default())?;
+        Ok(json!({ "cancelledOrderIds": ids }))
+    };
+    respond(res.await)
+}
+
+async fn cancel_all(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, _) = signed(&mut req).await?;
+        let query = query(&req);
+        let ids = req
+            .state()
+            .exchange
+            .cancel_all(&owner, query.get("symbol").map(|s| s.as_str()));
+        Ok(json!({ "cancelledOrderIds": ids }))
+    };
+    respond(res.await)
+}
+
+/// The mock takes no stop orders, so there are never any to cancel.
+async fn cancel_all_stop_orders(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        signed(&mut req).await?;
+        Ok(json!({ "cancelledOrderIds": [] }))
+    };
+    respond(res.await)
+}
+
+async fn list_fills(mut req: Request<MockState>) -> tide::Result {
+    let res = async {
+        let (owner, _) = signed(&mut req).await?;
+        let query = query(&req);
+        let get = |name: &str| query.get(name).map(|v| v.as_str());
+        let fills = req
+            .state()
+            .exchange
+            .fills(&owner, get("orderId"), get("symbol"));
+        Ok(page(fills, &query))
+    };
+    respond(res.await)
+}
+
+// Public tokens need no signature, private ones are tied to the signing account.
+async fn bullet(mut req: Request<MockState>, private: bool) -> tide::Result {
+    let res = async {
+        let owner = if private {
+            Some(signed(&mut req).await?.0)
+        } else {
+            None
+        };
+        let state = req.state();
+        Ok(json!({
+            "token": state.tokens.issue(owner),
+            "instanceServers": [{
+                "endpoint": state.ws_url,
+                "protocol": "websocket",
+                "encrypt": false,
+                "pingInterval": 18000,
+                "pingTimeout": 10000
+            }]
+        }))
+    };
+    respond(res.await)
+}
@@ -0,0 +1,87 @@
+/// Prices, sizes and balances are kept as integers of 1e-8, so the book and the balances
+/// add up exactly and prices can be ordered.
+pub const SCALE: i64 = 100_000_000;
+
+/// Parses a decimal string such as Kucoin sends, `None` when it isn't a number or has more
+/// than eight decimals.
+pub fn parse(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(v) => (true, v),
+        None => (false, value),
+    };
+    let (int, frac) = match value.split_once('.') {
+        Some((i, f)) => (i, f),
+        None => (value, ""),
+    };
+    if (int.is_empty() && frac.is_empty()) || frac.len() > 8 {
+        return None;
+    }
+    let int: i64 = if int.is_empty() { 0 } else { int.parse().ok()? };
+    let frac: i64 = if frac.is_empty() {
+        0
+    } else {
+        format!("{:0<8}", frac).parse().ok()?
+    };
+    let units = int.checked_mul(SCALE)?.checked_add(frac)?;
+    Some(if negative { -units } else { units })
+}
+
+pub fn from_f64(value: f64) -> i64 {
+    (value * SCALE as f64).round() as i64
+}
+
+/// Formats units back to a decimal string without trailing zeros.
+pub fn format(units: i64) -> String {
+    let sign = if units < 0 { "-" } else { "" };
+    let units = units.abs();
+    let frac = format!("{:08}", units % SCALE);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        format!("{}{}", sign, units / SCALE)
+    } else {
+        format!("{}{}.{}", sign, units / SCALE, frac)
+    }
+}
+
+/// What `size` costs at `price` in the quote currency.
+pub fn quote(price: i64, size: i64) -> i64 {
+    (price as i128 * size as i128 / SCALE as i128) as i64
+}
+
+/// How much of the base currency `funds` buys at `price`.
+pub fn base_for(funds: i64, price: i64) -> i64 {
+    if price <= 0 {
+        return 0;
+    }
+    (funds as i128 * SCALE as i128 / price as i128) as i64
+}
+
+/// `rate` of `amount`, `rate` being in units as well.
+pub fn fee(amount: i64, rate: i64) -> i64 {
+    quote(rate, amount)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::units::{base_for, format, parse, quote};
+
+    #[test]
+    fn round_trips_decimals() {
+        assert_eq!(parse("0.00000001"), Some(1));
+        assert_eq!(parse("12.5"), Some(1_250_000_000));
+        assert_eq!(parse("-3"), Some(-300_000_000));
+        assert_eq!(parse("0.000000001"), None);
+        assert_eq!(parse("abc"), None);
+        assert_eq!(format(1_250_000_000), "12.5");
+        assert_eq!(format(-1), "-0.00000001");
+        assert_eq!(
+            quote(parse("0.05").unwrap(), parse("3").unwrap()),
+            parse("0.15").unwrap()
+        );
+        assert_eq!(
+            base_for(parse("0.15").unwrap(), parse("0.05").unwrap()),
+            parse("3").unwrap()
+        );
+    }
+}
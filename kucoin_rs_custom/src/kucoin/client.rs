@@ -1,28 +1,55 @@
 use reqwest;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 use std::time::Duration;
 
 use base64::encode;
-use failure;
 use hmac::{Hmac, Mac};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use serde_json::json;
+use serde_json::{json, Value};
 use sha2::Sha256;
+use zeroize::Zeroize;
 
 use super::error::APIError;
-use super::model::Method;
+use super::model::{APIDatum, Method};
+use super::rate_limit::{EndpointClass, RateLimiter, RateLimits};
+use super::time_sync::{estimate_offset, is_timestamp_error, TimeSample, TimeSync};
+use super::transport::{ReqwestTransport, Transport};
 use super::utils::get_time;
 
+/// Round trips measured per time sync, the fastest one is used.
+const TIME_SYNC_SAMPLES: usize = 3;
+
 // Alias for HMAC-SHA256
 type HmacSha256 = Hmac<Sha256>;
 
-#[derive(Debug, Clone)]
+/// API keys for signed requests. The keys are wiped from memory on drop and never printed by `Debug`.
+#[derive(Clone)]
 pub struct Credentials {
     api_key: String,
     secret_key: String,
     passphrase: String,
 }
 
+impl Drop for Credentials {
+    fn drop(&mut self) {
+        self.api_key.zeroize();
+        self.secret_key.zeroize();
+        self.passphrase.zeroize();
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("api_key", &"<redacted>")
+            .field("secret_key", &"<redacted>")
+            .field("passphrase", &"<redacted>")
+            .finish()
+    }
+}
+
 impl Credentials {
     pub fn new(api_key: &str, secret_key: &str, passphrase: &str) -> Self {
         Credentials {
@@ -37,6 +64,8 @@ impl Credentials {
 pub enum KucoinEnv {
     Live,
     Sandbox,
+    /// Any other base URL, e.g. a local mock of the API.
+    Custom(String),
 }
 
 #[derive(Debug, Clone)]
@@ -45,13 +74,22 @@ pub struct Kucoin {
     environment: KucoinEnv,
     pub prefix: String,
     pub client: reqwest::Client,
+    rate_limiter: RateLimiter,
+    time_sync: TimeSync,
+    transport: Arc<dyn Transport>,
 }
 
 impl Kucoin {
-    pub fn new(
+    pub fn new(environment: KucoinEnv, credentials: Option<Credentials>) -> Result<Self, APIError> {
+        Kucoin::new_with_rate_limits(environment, credentials, RateLimits::default())
+    }
+
+    /// Same as `new` but with custom quotas for the client side rate limiter.
+    pub fn new_with_rate_limits(
         environment: KucoinEnv,
         credentials: Option<Credentials>,
-    ) -> Result<Self, failure::Error> {
+        rate_limits: RateLimits,
+    ) -> Result<Self, APIError> {
         let client = reqwest::Client::builder()
             .use_rustls_tls()
             .timeout(Duration::from_secs(60))
@@ -59,15 +97,179 @@ impl Kucoin {
         let prefix = match environment {
             KucoinEnv::Live => String::from("https://openapi-v2.kucoin.com"),
             KucoinEnv::Sandbox => String::from("https://openapi-sandbox.kucoin.com"),
+            KucoinEnv::Custom(ref url) => url.trim_end_matches('/').to_string(),
         };
         Ok(Kucoin {
             credentials,
             environment,
             prefix,
+            transport: Arc::new(ReqwestTransport::new(client.clone())),
             client,
+            rate_limiter: RateLimiter::new(rate_limits),
+            time_sync: TimeSync::default(),
         })
     }
 
+    /// Replaces the client's rate limiter, e.g. to share buckets between clients of one account.
+    pub fn set_rate_limiter(&mut self, rate_limiter: RateLimiter) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Replaces the client's clock offset estimate, e.g. to share one between clients.
+    pub fn set_time_sync(&mut self, time_sync: TimeSync) {
+        self.time_sync = time_sync;
+    }
+
+    pub fn time_sync(&self) -> &TimeSync {
+        &self.time_sync
+    }
+
+    /// Replaces what requests are sent through, e.g. with a `MockTransport` in tests.
+    pub fn set_transport(&mut self, transport: Arc<dyn Transport>) {
+        self.transport = transport;
+    }
+
+    /// What requests are currently sent through, e.g. to wrap in a `RecordingTransport`.
+    pub fn transport(&self) -> Arc<dyn Transport> {
+        self.transport.clone()
+    }
+
+    /// Measures the offset between the local clock and Kucoin's, compensating for the round trip,
+    /// and uses it to sign requests from then on. Signed requests resync on their own once the
+    /// estimate is older than the refresh interval or Kucoin rejects a timestamp, calling this
+    /// up front just makes sure the first signed request already uses it.
+    pub async fn sync_time(&self) -> Result<i64, APIError> {
+        if !self.time_sync.begin_sync() {
+            return Ok(self.time_sync.offset_ms());
+        }
+        let res = self.sample_time().await;
+        self.time_sync.end_sync();
+        let offset = res?;
+        self.time_sync.set_offset_ms(offset);
+        Ok(offset)
+    }
+
+    async fn sample_time(&self) -> Result<i64, APIError> {
+        let mut samples = Vec::with_capacity(TIME_SYNC_SAMPLES);
+        for _ in 0..TIME_SYNC_SAMPLES {
+            let sent = get_time() as i64;
+            let server = self.fetch_server_time().await?;
+            let received = get_time() as i64;
+            samples.push(TimeSample {
+                sent,
+                server,
+                received,
+            });
+        }
+        estimate_offset(&samples)
+            .ok_or_else(|| APIError::Other("Clock went backwards while syncing time".to_string()))
+    }
+
+    // Same as `get_server_time`, but bypasses `send_limited` since that may itself call for a sync.
+    async fn fetch_server_time(&self) -> Result<i64, APIError> {
+        let url = format!("{}/api/v1/timestamp", &self.prefix);
+        self.rate_limiter.acquire(EndpointClass::Public).await;
+        let request = self.client.get(&url).build()?;
+        let resp = self.transport.execute(request).await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.bytes().await?;
+            return Err(APIError::from_response(
+                "GET",
+                "/api/v1/timestamp",
+                status.as_u16(),
+                &body,
+            ));
+        }
+        let resp: APIDatum<i64> = resp.json().await?;
+        resp.into_result()
+    }
+
+    // Refreshes the offset in the background, when there is a runtime to run it on.
+    fn spawn_time_sync(&self) {
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let client = self.clone();
+            handle.spawn(async move {
+                let _ = client.sync_time().await;
+            });
+        }
+    }
+
+    // Turns a response outside 2xx into an `APIError::Status`. A signed request Kucoin rejected
+    // for its timestamp resyncs first, so a retry of the request is signed with the new offset.
+    async fn check_status(
+        &self,
+        method: &str,
+        endpoint: &str,
+        resp: reqwest::Response,
+        signed: bool,
+    ) -> Result<reqwest::Response, APIError> {
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+        let body = resp.bytes().await?;
+        if signed && is_timestamp_error(&body) {
+            self.time_sync.invalidate();
+            let _ = self.sync_time().await;
+        }
+        Err(APIError::from_response(
+            method,
+            endpoint,
+            status.as_u16(),
+            &body,
+        ))
+    }
+
+    // Sends a request once the rate limiter allows it. A 429 from Kucoin throttles the
+    // endpoint class and the request is re-sent after the back off.
+    async fn send_limited(
+        &self,
+        request: reqwest::RequestBuilder,
+        signed: bool,
+    ) -> Result<reqwest::Response, APIError> {
+        let request = request.build()?;
+        if signed && self.time_sync.is_stale() {
+            self.spawn_time_sync();
+        }
+        let class = EndpointClass::classify(request.method(), request.url().path(), signed);
+        let method = request.method().to_string();
+        let endpoint = request.url().path().to_string();
+        let limits = self.rate_limiter.limits().clone();
+        let mut backoff = limits.throttle_backoff;
+        let mut attempt = 0;
+        loop {
+            self.rate_limiter.acquire(class).await;
+            let resp = match request.try_clone() {
+                Some(r) => self.transport.execute(r).await?,
+                None => {
+                    let resp = self.transport.execute(request).await?;
+                    return self.check_status(&method, &endpoint, resp, signed).await;
+                }
+            };
+            if resp.status() != reqwest::StatusCode::TOO_MANY_REQUESTS
+                || attempt >= limits.max_throttle_retries
+            {
+                return self.check_status(&method, &endpoint, resp, signed).await;
+            }
+            // Kucoin reports the milliseconds until the window resets in gw-ratelimit-reset.
+            let wait = resp
+                .headers()
+                .get("gw-ratelimit-reset")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(backoff);
+            self.rate_limiter.throttle(class, wait);
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+
     // Generic get request for internal library use.
     // Matches credentials for signed vs. unsigned API calls
     pub async fn get(
@@ -75,24 +277,13 @@ impl Kucoin {
         url: String,
         sign: Option<HeaderMap>,
     ) -> Result<reqwest::Response, APIError> {
-        let req_url = reqwest::Url::parse(&url).unwrap();
+        let req_url = parse_url(&url)?;
         match sign {
             Some(sign) => {
-                let resp = self.client.get(req_url).headers(sign).send().await?;
-                if resp.status().is_success() {
-                    Ok(resp)
-                } else {
-                    Ok(resp)
-                }
-            }
-            None => {
-                let resp = self.client.get(req_url).send().await?;
-                if resp.status().is_success() {
-                    Ok(resp)
-                } else {
-                    Ok(resp)
-                }
+                self.send_limited(self.client.get(req_url).headers(sign), true)
+                    .await
             }
+            None => self.send_limited(self.client.get(req_url), false).await,
         }
     }
 
@@ -102,82 +293,62 @@ impl Kucoin {
         sign: Option<HeaderMap>,
         params: Option<HashMap<String, String>>,
     ) -> Result<reqwest::Response, APIError> {
-        let req_url = reqwest::Url::parse(&url).unwrap();
-        if let Some(s) = sign {
-            if let Some(p) = params {
-                let resp = self
-                    .client
-                    .post(req_url)
-                    .headers(s)
-                    .json(&json!(p))
-                    .send()
-                    .await?;
-                if resp.status().is_success() {
-                    Ok(resp)
-                } else {
-                    Ok(resp)
-                }
-            } else {
-                let resp = self.client.post(req_url).headers(s).send().await?;
-                if resp.status().is_success() {
-                    Ok(resp)
-                } else if resp.status().is_server_error() {
-                    Ok(resp)
-                } else {
-                    Ok(resp)
-                }
-            }
-        } else {
-            panic!("Unsigned POST request...")
+        let req_url = parse_url(&url)?;
+        let sign = sign.ok_or_else(|| APIError::MissingCredentials(req_url.path().to_string()))?;
+        let request = self.client.post(req_url).headers(sign);
+        match params {
+            Some(p) => self.send_limited(request.json(&json!(p)), true).await,
+            None => self.send_limited(request, true).await,
         }
     }
 
+    /// Signed POST of a JSON body, see `sign_json_headers`.
+    pub async fn post_json(
+        &self,
+        url: String,
+        sign: HeaderMap,
+        body: &Value,
+    ) -> Result<reqwest::Response, APIError> {
+        let req_url = parse_url(&url)?;
+        let request = self.client.post(req_url).headers(sign).json(body);
+        self.send_limited(request, true).await
+    }
+
+    /// Unsigned POST for the few public endpoints that take one, e.g. the public websocket bullet.
+    pub async fn post_public(&self, url: String) -> Result<reqwest::Response, APIError> {
+        let req_url = parse_url(&url)?;
+        self.send_limited(self.client.post(req_url), false).await
+    }
+
     pub async fn delete(
         &self,
         url: String,
         sign: Option<HeaderMap>,
     ) -> Result<reqwest::Response, APIError> {
-        let req_url = reqwest::Url::parse(&url).unwrap();
-        if let Some(s) = sign {
-            let resp = self.client.delete(req_url).headers(s).send().await?;
-            if resp.status().is_success() {
-                Ok(resp)
-            } else if resp.status().is_server_error() {
-                Ok(resp)
-            } else {
-                Ok(resp)
-            }
-        } else {
-            panic!("Unsigned DELETE request...")
-        }
+        let req_url = parse_url(&url)?;
+        let sign = sign.ok_or_else(|| APIError::MissingCredentials(req_url.path().to_string()))?;
+        self.send_limited(self.client.delete(req_url).headers(sign), true)
+            .await
     }
 
+    /// Signs a request to a private endpoint, failing when the client has no credentials.
     pub fn sign_headers(
         &self,
         endpoint: String,
         params: Option<&HashMap<String, String>>,
         query: Option<String>,
         method: Method,
-    ) -> Result<HeaderMap, failure::Error> {
-        let mut headers = HeaderMap::new();
-        let nonce = get_time().to_string();
-        let mut api_key: &str = "";
-        let mut secret_key: &str = "";
-        let mut passphrase: &str = "";
+    ) -> Result<HeaderMap, APIError> {
+        let credentials = self
+            .credentials
+            .as_ref()
+            .ok_or_else(|| APIError::MissingCredentials(endpoint.clone()))?;
+        let nonce = self.time_sync.now_ms().to_string();
         let mut str_to_sign: String = String::new();
-        match &self.credentials {
-            Some(c) => {
-                api_key = &c.api_key;
-                secret_key = &c.secret_key;
-                passphrase = &c.passphrase;
-            }
-            None => (),
-        }
         match method {
             Method::GET => {
                 let meth = "GET";
                 if let Some(q) = query {
-                    // let query = format_query(&p);
                     str_to_sign = format!("{}{}{}{}", nonce, meth, endpoint, q);
                 } else {
                     str_to_sign = format!("{}{}{}", nonce, meth, endpoint)
@@ -196,35 +367,181 @@ impl Kucoin {
             Method::DELETE => {
                 let meth = "DELETE";
                 if let Some(q) = query {
-                    // let query = format_query(&p);
                     str_to_sign = format!("{}{}{}{}", nonce, meth, endpoint, q);
                 } else {
                     str_to_sign = format!("{}{}{}", nonce, meth, endpoint)
                 }
             }
         }
-        let mut mac =
-            HmacSha256::new_varkey(secret_key.as_bytes()).expect("HMAC can take key of any size");
-        mac.input(str_to_sign.as_bytes());
-        let result = mac.result();
-        let code_bytes = result.code();
-        let digest = encode(&code_bytes);
-        headers.insert(
-            HeaderName::from_static("kc-api-key"),
-            HeaderValue::from_str(&api_key).unwrap(),
-        );
-        headers.insert(
-            HeaderName::from_static("kc-api-sign"),
-            HeaderValue::from_str(&digest).unwrap(),
-        );
-        headers.insert(
-            HeaderName::from_static("kc-api-timestamp"),
-            HeaderValue::from_str(&nonce).unwrap(),
-        );
-        headers.insert(
-            HeaderName::from_static("kc-api-passphrase"),
-            HeaderValue::from_str(&passphrase).unwrap(),
+        signed_headers(credentials, &nonce, &str_to_sign)
+    }
+
+    /// Signs a POST whose body is arbitrary JSON, e.g. a list of orders, rather than flat params.
+    /// The body must be sent as is with `post_json` for the signature to match.
+    pub fn sign_json_headers(&self, endpoint: String, body: &Value) -> Result<HeaderMap, APIError> {
+        let credentials = self
+            .credentials
+            .as_ref()
+            .ok_or_else(|| APIError::MissingCredentials(endpoint.clone()))?;
+        let nonce = self.time_sync.now_ms().to_string();
+        let str_to_sign = format!("{}POST{}{}", nonce, endpoint, body);
+        signed_headers(credentials, &nonce, &str_to_sign)
+    }
+}
+
+fn signed_headers(
+    credentials: &Credentials,
+    nonce: &str,
+    str_to_sign: &str,
+) -> Result<HeaderMap, APIError> {
+    let mut headers = HeaderMap::new();
+    let mut mac = HmacSha256::new_varkey(credentials.secret_key.as_bytes())
+        .map_err(|_| APIError::Other("Invalid HMAC key".to_string()))?;
+    mac.input(str_to_sign.as_bytes());
+    let digest = encode(mac.result().code());
+    for (name, value) in [
+        ("kc-api-key", credentials.api_key.as_str()),
+        ("kc-api-sign", digest.as_str()),
+        ("kc-api-timestamp", nonce),
+        ("kc-api-passphrase", credentials.passphrase.as_str()),
+    ] {
+        let value = HeaderValue::from_str(value).map_err(|_| APIError::InvalidHeader(name))?;
+        headers.insert(HeaderName::from_static(name), value);
+    }
+    Ok(headers)
+}
+
+fn parse_url(url: &str) -> Result<reqwest::Url, APIError> {
+    reqwest::Url::parse(url).map_err(|source| APIError::InvalidUrl {
+        url: url.to_string(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::kucoin::client::{Credentials, Kucoin, KucoinEnv};
+    use crate::kucoin::error::APIError;
+    use crate::kucoin::error::KucoinCode;
+    use crate::kucoin::model::Method;
+    use crate::kucoin::transport::test::mock_client;
+    use crate::kucoin::transport::MockTransport;
+
+    #[test]
+    fn debug_redacts_credentials() {
+        let creds = Credentials::new("my-key", "my-secret", "my-pass");
+        let printed = format!("{:?}", creds);
+        assert!(!printed.contains("my-key"));
+        assert!(!printed.contains("my-secret"));
+        assert!(!printed.contains("my-pass"));
+    }
+
+    #[test]
+    fn signs_with_the_corrected_time() {
+        let creds = Credentials::new("my-key", "my-secret", "my-pass");
+        let client = Kucoin::new(KucoinEnv::Sandbox, Some(creds)).unwrap();
+        client.time_sync().set_offset_ms(-3_600_000);
+        let headers = client
+            .sign_headers("/api/v1/accounts".to_string(), None, None, Method::GET)
+            .unwrap();
+        let stamp: i64 = headers["kc-api-timestamp"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let expected = client.time_sync().now_ms();
+        assert!((expected - stamp).abs() < 1_000);
+        assert!(!client.time_sync().is_stale());
+    }
+
+    #[tokio::test]
+    async fn fails_without_panicking() {
+        let mock = MockTransport::new();
+        let mut anon =
+            Kucoin::new(KucoinEnv::Custom("http://mock.test".to_string()), None).unwrap();
+        anon.set_transport(Arc::new(mock.clone()));
+        match anon.get_accounts_list(None, None).await {
+            Err(APIError::MissingCredentials(endpoint)) => assert_eq!(endpoint, "/api/v1/accounts"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        let url = "http://mock.test/api/v1/orders".to_string();
+        assert!(matches!(
+            anon.post(url.clone(), None, None).await,
+            Err(APIError::MissingCredentials(_))
+        ));
+        assert!(matches!(
+            anon.delete(url, None).await,
+            Err(APIError::MissingCredentials(_))
+        ));
+        assert!(mock.requests().is_empty());
+
+        let broken = Kucoin::new(KucoinEnv::Custom("not a url".to_string()), None).unwrap();
+        match broken.get_server_time().await {
+            Err(APIError::InvalidUrl { url, .. }) => assert_eq!(url, "not a url/api/v1/timestamp"),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let pasted = Credentials::new("my-key\n", "my-secret", "my-pass");
+        let client = Kucoin::new(KucoinEnv::Sandbox, Some(pasted)).unwrap();
+        let err = client
+            .sign_headers("/api/v1/accounts".to_string(), None, None, Method::GET)
+            .unwrap_err();
+        assert!(matches!(err, APIError::InvalidHeader("kc-api-key")));
+        assert!(!err.to_string().contains("my-key"));
+    }
+
+    #[tokio::test]
+    async fn turns_failed_responses_into_errors() {
+        let mock = MockTransport::new();
+        mock.on_status(
+            reqwest::Method::GET,
+            "/api/v1/accounts",
+            502,
+            "<html><body>502 Bad Gateway</body></html>",
+        )
+        .on_status(
+            reqwest::Method::DELETE,
+            "/api/v1/orders/5c35c02703aa673ceec2a168",
+            400,
+            r#"{"code":"400002","msg":"KC-API-TIMESTAMP Invalid"}"#,
+        )
+        .on(
+            reqwest::Method::GET,
+            "/api/v1/timestamp",
+            r#"{"code":"200000","data":1546837113087}"#,
         );
-        Ok(headers)
+        let client = mock_client(&mock);
+        match client.get_accounts_list(None, None).await {
+            Err(APIError::Status {
+                status,
+                method,
+                endpoint,
+                code,
+                body,
+                ..
+            }) => {
+                assert_eq!((status, method.as_str()), (502, "GET"));
+                assert_eq!(endpoint, "/api/v1/accounts");
+                assert_eq!(code, None);
+                assert!(body.contains("502 Bad Gateway"));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let err = client
+            .cancel_order("5c35c02703aa673ceec2a168")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Some(&KucoinCode::InvalidTimestamp));
+        assert!(err
+            .to_string()
+            .starts_with("DELETE /api/v1/orders/5c35c02703aa673ceec2a168 returned HTTP 400"));
+        // The timestamp rejection resynced the clock.
+        assert!(mock
+            .requests()
+            .iter()
+            .any(|r| r.path == "/api/v1/timestamp"));
     }
 }
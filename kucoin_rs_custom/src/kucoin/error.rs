@@ -1,16 +1,126 @@
-#[derive(Fail, Debug)]
+use std::fmt;
+
+#[derive(thiserror::Error, Debug)]
 pub enum APIError {
-    #[fail(display = "Serde issue parsing error {}", _0)]
-    Serde(#[fail(cause)] serde_json::Error),
-    #[fail(display = "Websocket error {}", _0)]
-    Websocket(#[fail(cause)] tokio_tungstenite::tungstenite::Error),
-    #[fail(display = "REST Call error {}", _0)]
-    HTTP(#[fail(cause)] reqwest::Error),
-    #[fail(display = "Other issue {}", _0)]
+    #[error("Serde issue parsing error {0}")]
+    Serde(#[source] serde_json::Error),
+    #[error("Websocket error {0}")]
+    Websocket(#[source] Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("REST Call error {0}")]
+    HTTP(#[source] reqwest::Error),
+    #[error("Kucoin error {code}: {msg}")]
+    Kucoin { code: KucoinCode, msg: String },
+    /// A response outside 2xx, with Kucoin's code and message when the body carries them and
+    /// the start of the body as it came, e.g. a gateway's HTML error page.
+    #[error("{method} {endpoint} returned HTTP {status}: {}", status_detail(.code, .msg, .body))]
+    Status {
+        status: u16,
+        method: String,
+        endpoint: String,
+        code: Option<KucoinCode>,
+        msg: Option<String>,
+        body: String,
+    },
+    #[error("Private endpoint {0} needs API credentials")]
+    MissingCredentials(String),
+    #[error("Invalid URL {url}: {source}")]
+    InvalidUrl {
+        url: String,
+        #[source]
+        source: url::ParseError,
+    },
+    /// A header value with characters HTTP doesn't allow, e.g. a pasted key with a newline.
+    /// Only the header name is kept, the value may be a secret.
+    #[error("Invalid value for the {0} header")]
+    InvalidHeader(&'static str),
+    #[error("Websocket has no subscribed streams")]
+    NotSubscribed,
+    /// Order parameters Kucoin would reject, caught before sending.
+    #[error("Invalid order: {0}")]
+    InvalidOrder(String),
+    #[error("Other issue {0}")]
     Other(String),
 }
 
-impl APIError {}
+impl APIError {
+    /// The Kucoin business code when the error came back from the exchange itself.
+    pub fn code(&self) -> Option<&KucoinCode> {
+        match self {
+            APIError::Kucoin { code, .. } => Some(code),
+            APIError::Status { code, .. } => code.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Whether the same request may succeed if sent again: timeouts and connection failures,
+    /// server errors and rate limiting. Rejections such as an insufficient balance, an unknown
+    /// symbol or a bad signature will fail the same way every time.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            APIError::HTTP(e) => match e.status() {
+                Some(status) => status.is_server_error() || status.as_u16() == 429,
+                None => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            },
+            APIError::Websocket(_) => true,
+            APIError::Kucoin { code, .. } => code.is_retryable(),
+            APIError::Status { status, code, .. } => {
+                *status >= 500 || *status == 429 || code.as_ref().is_some_and(|c| c.is_retryable())
+            }
+            APIError::Serde(_)
+            | APIError::MissingCredentials(_)
+            | APIError::InvalidUrl { .. }
+            | APIError::InvalidHeader(_)
+            | APIError::NotSubscribed
+            | APIError::InvalidOrder(_)
+            | APIError::Other(_) => false,
+        }
+    }
+}
+
+/// How much of a failed response's body an `APIError::Status` keeps.
+pub const MAX_ERROR_BODY: usize = 512;
+
+impl APIError {
+    /// The error for a non 2xx response to `method` `endpoint`.
+    pub fn from_response(method: &str, endpoint: &str, status: u16, body: &[u8]) -> Self {
+        #[derive(serde_derive::Deserialize)]
+        struct ErrorBody {
+            code: Option<String>,
+            msg: Option<String>,
+        }
+        let parsed = serde_json::from_slice::<ErrorBody>(body).ok();
+        let (code, msg) = match parsed {
+            Some(b) => (b.code.map(|c| KucoinCode::from_code(&c)), b.msg),
+            None => (None, None),
+        };
+        let mut body = String::from_utf8_lossy(body).trim().to_string();
+        if body.len() > MAX_ERROR_BODY {
+            let mut end = MAX_ERROR_BODY;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+            body.push_str("...");
+        }
+        APIError::Status {
+            status,
+            method: method.to_string(),
+            endpoint: endpoint.to_string(),
+            code,
+            msg,
+            body,
+        }
+    }
+}
+
+fn status_detail(code: &Option<KucoinCode>, msg: &Option<String>, body: &str) -> String {
+    match (code, msg) {
+        (Some(code), Some(msg)) => format!("Kucoin error {}: {}", code, msg),
+        (Some(code), None) => format!("Kucoin error {}", code),
+        _ if body.is_empty() => "empty body".to_string(),
+        _ => body.to_string(),
+    }
+}
 
 impl From<reqwest::Error> for APIError {
     fn from(err: reqwest::Error) -> Self {
@@ -26,6 +136,177 @@ impl From<serde_json::Error> for APIError {
 
 impl From<tokio_tungstenite::tungstenite::Error> for APIError {
     fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
-        APIError::Websocket(err)
+        APIError::Websocket(Box::new(err))
+    }
+}
+
+/// Business codes sent back by Kucoin in the `code` field of every REST response.
+/// Codes without a dedicated variant are kept as `Unknown`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KucoinCode {
+    Success,
+    MissingAuthHeaders,
+    InvalidTimestamp,
+    InvalidApiKey,
+    InvalidPassphrase,
+    InvalidSignature,
+    IpNotWhitelisted,
+    AccessDenied,
+    UrlNotFound,
+    InvalidParameter,
+    AccountFrozen,
+    InsufficientBalance,
+    TooManyRequests,
+    InternalError,
+    UnknownSymbol,
+    Unknown(String),
+}
+
+impl KucoinCode {
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            "200000" => KucoinCode::Success,
+            "400001" => KucoinCode::MissingAuthHeaders,
+            "400002" => KucoinCode::InvalidTimestamp,
+            "400003" => KucoinCode::InvalidApiKey,
+            "400004" => KucoinCode::InvalidPassphrase,
+            "400005" => KucoinCode::InvalidSignature,
+            "400006" => KucoinCode::IpNotWhitelisted,
+            "400007" => KucoinCode::AccessDenied,
+            "404000" => KucoinCode::UrlNotFound,
+            "400100" => KucoinCode::InvalidParameter,
+            "411100" => KucoinCode::AccountFrozen,
+            "200004" | "230003" => KucoinCode::InsufficientBalance,
+            "429000" => KucoinCode::TooManyRequests,
+            "500000" => KucoinCode::InternalError,
+            "900001" => KucoinCode::UnknownSymbol,
+            other => KucoinCode::Unknown(other.to_string()),
+        }
+    }
+
+    /// The code behind a message sent without one, as in the per order results of a batch.
+    /// `None` when the message isn't one of those Kucoin is known to send.
+    pub fn from_message(msg: &str) -> Option<Self> {
+        let msg = msg.to_lowercase();
+        if msg.contains("too many request") || msg.contains("frequency") {
+            Some(KucoinCode::TooManyRequests)
+        } else if msg.contains("insufficient") {
+            Some(KucoinCode::InsufficientBalance)
+        } else if msg.contains("system busy")
+            || msg.contains("internal")
+            || msg.contains("server error")
+            || msg.contains("timeout")
+        {
+            Some(KucoinCode::InternalError)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        *self == KucoinCode::Success
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            KucoinCode::TooManyRequests | KucoinCode::InternalError | KucoinCode::InvalidTimestamp
+        )
+    }
+}
+
+impl fmt::Display for KucoinCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KucoinCode::Unknown(code) => write!(f, "Unknown({})", code),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::kucoin::error::{APIError, KucoinCode, MAX_ERROR_BODY};
+    use std::error::Error;
+
+    #[test]
+    fn maps_known_and_unknown_codes() {
+        assert_eq!(KucoinCode::from_code("200000"), KucoinCode::Success);
+        assert_eq!(KucoinCode::from_code("429000"), KucoinCode::TooManyRequests);
+        assert_eq!(KucoinCode::from_code("900001"), KucoinCode::UnknownSymbol);
+        assert_eq!(
+            KucoinCode::from_code("200004"),
+            KucoinCode::InsufficientBalance
+        );
+        assert_eq!(
+            KucoinCode::from_code("123456"),
+            KucoinCode::Unknown("123456".to_string())
+        );
+    }
+
+    #[test]
+    fn maps_batch_failure_messages() {
+        assert_eq!(
+            KucoinCode::from_message("Too Many Requests"),
+            Some(KucoinCode::TooManyRequests)
+        );
+        assert_eq!(
+            KucoinCode::from_message("Balance insufficient!"),
+            Some(KucoinCode::InsufficientBalance)
+        );
+        assert_eq!(
+            KucoinCode::from_message("Internal Server Error"),
+            Some(KucoinCode::InternalError)
+        );
+        assert_eq!(KucoinCode::from_message("Price increment invalid."), None);
+    }
+
+    #[test]
+    fn classifies_retryable_codes() {
+        assert!(KucoinCode::TooManyRequests.is_retryable());
+        assert!(KucoinCode::InternalError.is_retryable());
+        assert!(!KucoinCode::InsufficientBalance.is_retryable());
+        assert!(!KucoinCode::UnknownSymbol.is_retryable());
+        assert!(!KucoinCode::InvalidSignature.is_retryable());
+    }
+
+    #[test]
+    fn exposes_underlying_error_as_source() {
+        let parse_err = serde_json::from_str::<u32>("nope").unwrap_err();
+        let err = APIError::from(parse_err);
+        assert!(err.source().is_some());
+        let err = APIError::Other("no cause".to_string());
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn describes_failed_responses() {
+        let err = APIError::from_response(
+            "POST",
+            "/api/v1/orders",
+            400,
+            br#"{"code":"200004","msg":"Balance insufficient!"}"#,
+        );
+        assert_eq!(err.code(), Some(&KucoinCode::InsufficientBalance));
+        assert!(!err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "POST /api/v1/orders returned HTTP 400: Kucoin error InsufficientBalance: Balance insufficient!"
+        );
+
+        let page = format!("<html>{}</html>", "Bad Gateway ".repeat(100));
+        let err = APIError::from_response("GET", "/api/v1/accounts", 502, page.as_bytes());
+        assert!(err.code().is_none() && err.is_retryable());
+        match &err {
+            APIError::Status { status, body, .. } => {
+                assert_eq!(*status, 502);
+                assert!(body.starts_with("<html>Bad Gateway"));
+                assert_eq!(body.len(), MAX_ERROR_BODY + 3);
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert!(err
+            .to_string()
+            .starts_with("GET /api/v1/accounts returned HTTP 502: <html>"));
     }
 }
@@ -0,0 +1,338 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use reqwest::{Request, Response};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::error::APIError;
+use super::transport::Transport;
+
+/// Fields whose values never make it into a fixture, in JSON bodies and query strings.
+/// Request headers, which carry the API key, passphrase and signature, aren't recorded at all.
+pub const REDACTED_FIELDS: &[&str] = &[
+    "token",
+    "apiKey",
+    "secret",
+    "passphrase",
+    "sign",
+    "signature",
+];
+
+/// What redacted values are replaced with.
+pub const REDACTED: &str = "<redacted>";
+
+/// One request and the response Kucoin gave it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// The JSON request body, for reference only, replay doesn't match on it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Value>,
+    pub status: u16,
+    /// The JSON response body. A body that isn't JSON, e.g. a proxy's error page, is kept
+    /// as a string and replayed as is.
+    pub response: Value,
+}
+
+/// A recorded session, saved as pretty JSON so fixtures diff and edit well.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Fixture {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Fixture {
+    pub fn from_json(json: &str) -> Result<Self, APIError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, APIError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            APIError::Other(format!("Could not read fixture {}: {}", path.display(), e))
+        })?;
+        Fixture::from_json(&json)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), APIError> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json + "\n").map_err(|e| {
+            APIError::Other(format!("Could not write fixture {}: {}", path.display(), e))
+        })
+    }
+}
+
+/// Sends requests through another transport and records every exchange, with secrets
+/// redacted, to be saved as a `Fixture`.
+#[derive(Clone)]
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    fixture: Arc<Mutex<Fixture>>,
+}
+
+impl fmt::Debug for RecordingTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingTransport")
+            .field("inner", &self.inner)
+            .field("recorded", &self.fixture().interactions.len())
+            .finish()
+    }
+}
+
+impl RecordingTransport {
+    /// Records what goes through `inner`, usually the client's current `transport()`.
+    pub fn new(inner: Arc<dyn Transport>) -> Self {
+        RecordingTransport {
+            inner,
+            fixture: Arc::new(Mutex::new(Fixture::default())),
+        }
+    }
+
+    /// Everything recorded so far.
+    pub fn fixture(&self) -> Fixture {
+        self.fixture
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), APIError> {
+        self.fixture().save(path)
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn execute(&self, request: Request) -> BoxFuture<'static, Result<Response, APIError>> {
+        let method = request.method().to_string();
+        let path = request.url().path().to_string();
+        let query = request.url().query().map(redact_query);
+        let body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .map(|b| redact(parse_body(b)));
+        let sent = self.inner.execute(request);
+        let fixture = self.fixture.clone();
+        Box::pin(async move {
+            let response = sent.await?;
+            let status = response.status();
+            let headers = response.headers().clone();
+            let bytes = response.bytes().await?;
+            fixture
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .interactions
+                .push(Interaction {
+                    method,
+                    path,
+                    query,
+                    request: body,
+                    status: status.as_u16(),
+                    response: redact(parse_body(&bytes)),
+                });
+            let mut rebuilt = http::Response::new(bytes.to_vec());
+            *rebuilt.status_mut() = status;
+            *rebuilt.headers_mut() = headers;
+            Ok(Response::from(rebuilt))
+        })
+    }
+}
+
+/// Serves a `Fixture` back. A request gets the first recorded response for its method, path
+/// and query it hasn't been given yet, so polling an endpoint replays the recorded sequence,
+/// then keeps getting the last one. Anything not recorded gets a 404 from Kucoin.
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+    interactions: Arc<Vec<Interaction>>,
+    served: Arc<Mutex<Vec<bool>>>,
+}
+
+impl ReplayTransport {
+    pub fn new(fixture: Fixture) -> Self {
+        let served = vec![false; fixture.interactions.len()];
+        ReplayTransport {
+            interactions: Arc::new(fixture.interactions),
+            served: Arc::new(Mutex::new(served)),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, APIError> {
+        Ok(ReplayTransport::new(Fixture::load(path)?))
+    }
+
+    /// Whether every recorded interaction has been replayed.
+    pub fn is_exhausted(&self) -> bool {
+        self.served
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .all(|s| *s)
+    }
+
+    fn respond(&self, request: &Request) -> Option<&Interaction> {
+        let method = request.method().as_str();
+        let path = request.url().path();
+        let query = request.url().query().map(redact_query);
+        let matching: Vec<usize> = (0..self.interactions.len())
+            .filter(|i| {
+                let recorded = &self.interactions[*i];
+                recorded.method == method && recorded.path == path && recorded.query == query
+            })
+            .collect();
+        let mut served = self.served.lock().unwrap_or_else(|e| e.into_inner());
+        let next = matching
+            .iter()
+            .copied()
+            .find(|i| !served[*i])
+            .or_else(|| matching.last().copied())?;
+        served[next] = true;
+        Some(&self.interactions[next])
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn execute(&self, request: Request) -> BoxFuture<'static, Result<Response, APIError>> {
+        let (status, body) = match self.respond(&request) {
+            Some(recorded) => {
+                let body = match &recorded.response {
+                    Value::String(raw) => raw.clone(),
+                    json => json.to_string(),
+                };
+                (recorded.status, body)
+            }
+            None => (
+                404,
+                format!(
+                    r#"{{"code":"404000","msg":"No recorded response for {} {}"}}"#,
+                    request.method(),
+                    request.url().path()
+                ),
+            ),
+        };
+        let response = http::Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(body)
+            .map(Response::from)
+            .map_err(|e| APIError::Other(e.to_string()));
+        Box::pin(async move { response })
+    }
+}
+
+fn parse_body(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).to_string()))
+}
+
+fn is_secret(field: &str) -> bool {
+    REDACTED_FIELDS
+        .iter()
+        .any(|f| f.eq_ignore_ascii_case(field))
+}
+
+/// Replaces the values of `REDACTED_FIELDS` anywhere in `value`.
+pub fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| {
+                    let v = if is_secret(&k) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        redact(v)
+                    };
+                    (k, v)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact).collect()),
+        other => other,
+    }
+}
+
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((k, _)) if is_secret(k) => format!("{}={}", k, REDACTED),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use reqwest::Method;
+
+    use super::{Fixture, RecordingTransport, ReplayTransport, REDACTED};
+    use crate::kucoin::error::KucoinCode;
+    use crate::kucoin::transport::test::mock_client;
+    use crate::kucoin::transport::MockTransport;
+
+    #[tokio::test]
+    async fn records_without_secrets_and_replays() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::POST,
+            "/api/v1/bullet-private",
+            r#"{"code":"200000","data":{"token":"private-token","instanceServers":[{"endpoint":"wss://push1-v2.kucoin.com/endpoint","protocol":"websocket","encrypt":true,"pingInterval":50000,"pingTimeout":10000}]}}"#,
+        )
+        .on_status(Method::GET, "/api/v1/timestamp", 503, "<html>Service Unavailable</html>");
+        let mut client = mock_client(&mock);
+        let recorder = RecordingTransport::new(client.transport());
+        client.set_transport(Arc::new(recorder.clone()));
+        let bullet = client.ws_bullet_private().await.unwrap();
+        assert_eq!(bullet.token, "private-token");
+        assert!(client.get_server_time().await.is_err());
+
+        let fixture = recorder.fixture();
+        let json = serde_json::to_string(&fixture).unwrap();
+        assert!(!json.contains("private-token"));
+        assert!(!json.contains("api-key") && !json.contains("passphrase\":\"passphrase"));
+        assert_eq!(fixture.interactions[0].response["data"]["token"], REDACTED);
+        assert_eq!(fixture.interactions[1].status, 503);
+
+        let mut replayed = mock_client(&MockTransport::new());
+        let replay = ReplayTransport::new(Fixture::from_json(&json).unwrap());
+        replayed.set_transport(Arc::new(replay.clone()));
+        let bullet = replayed.ws_bullet_private().await.unwrap();
+        assert_eq!(bullet.token, REDACTED);
+        assert_eq!(bullet.instance_servers[0].ping_interval, 50000);
+        assert!(replayed.get_server_time().await.is_err());
+        assert!(replay.is_exhausted());
+        let err = replayed.get_market_list().await.unwrap_err();
+        assert_eq!(err.code(), Some(&KucoinCode::UrlNotFound));
+    }
+
+    // Payloads pinned from the exchange: null change fields on fresh listings and a null
+    // `data` on deletes.
+    #[tokio::test]
+    async fn replays_pinned_payloads() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/odd_payloads.json");
+        let mut client = mock_client(&MockTransport::new());
+        client.set_transport(Arc::new(ReplayTransport::load(path).unwrap()));
+
+        let tickers = client.get_all_tickers().await.unwrap();
+        let fresh = tickers
+            .ticker
+            .iter()
+            .find(|t| t.symbol == "NEW-USDT")
+            .unwrap();
+        assert!(fresh.change_rate.is_none() && fresh.high.is_none());
+        let stats = client.get_daily_stats("NEW-USDT").await.unwrap();
+        assert_eq!(stats.change_price, None);
+        assert_eq!(stats.last, "0.1");
+        client
+            .cancel_withdrawal("5bffb63303aa675e8bbe18f9")
+            .await
+            .unwrap();
+    }
+}
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 
+use futures::stream::BoxStream;
+
 use super::client::Kucoin;
 use super::error::APIError;
 use super::model::margin::{
@@ -8,31 +10,30 @@ use super::model::margin::{
     RepaymentRecord,
 };
 use super::model::{APIData, APIDatum, Method, Pagination};
+use super::paginate::{paginate, Paging};
 use super::utils::format_query;
 
 impl Kucoin {
-    pub async fn get_mark_price(&self, symbol: &str) -> Result<APIDatum<MarkPrice>, APIError> {
+    pub async fn get_mark_price(&self, symbol: &str) -> Result<MarkPrice, APIError> {
         let endpoint = format!("/api/v1/mark-price/{}/current", symbol);
         let url = format!("{}{}", &self.prefix, endpoint);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<MarkPrice> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_margin_config_info(&self) -> Result<APIDatum<MarginInfo>, APIError> {
+    pub async fn get_margin_config_info(&self) -> Result<MarginInfo, APIError> {
         let endpoint = String::from("/api/v1/margin/config");
         let url = format!("{}{}", &self.prefix, endpoint);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<MarginInfo> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_margin_accounts(&self) -> Result<APIDatum<MarginAccounts>, APIError> {
+    pub async fn get_margin_accounts(&self) -> Result<MarginAccounts, APIError> {
         let endpoint = String::from("/api/v1/margin/account");
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, None, Method::GET)?;
+        let resp: APIDatum<MarginAccounts> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     /// Term param is comma delimited. Avaialble terms are 7,14,28
@@ -43,7 +44,7 @@ impl Kucoin {
         size: f64,
         max_rate: Option<f64>,
         term: Option<&str>,
-    ) -> Result<APIDatum<BorrowOrderId>, APIError> {
+    ) -> Result<BorrowOrderId, APIError> {
         let endpoint = String::from("/api/v1/margin/borrow");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
@@ -56,28 +57,24 @@ impl Kucoin {
         if let Some(t) = term {
             params.insert(String::from("term"), t.to_string());
         }
-        let headers = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let headers = self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<BorrowOrderId> = self
             .post(url, Some(headers), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_result()
     }
 
-    pub async fn get_borrow_order(
-        &self,
-        order_id: &str,
-    ) -> Result<APIDatum<BorrowOrder>, APIError> {
-        let endpoint = format!("/api/v1/margin/borrow?orderId={}", order_id);
-        let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+    pub async fn get_borrow_order(&self, order_id: &str) -> Result<BorrowOrder, APIError> {
+        let endpoint = String::from("/api/v1/margin/borrow");
+        let mut params: HashMap<String, String> = HashMap::new();
+        params.insert(String::from("orderId"), order_id.to_string());
+        let query = format_query(&params);
+        let url = format!("{}{}{}", &self.prefix, endpoint, query);
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIDatum<BorrowOrder> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_repay_record(
@@ -85,7 +82,7 @@ impl Kucoin {
         currency: Option<&str>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<RepayRecord>>, APIError> {
+    ) -> Result<Pagination<RepayRecord>, APIError> {
         let endpoint = String::from("/api/v1/margin/borrow/outstanding");
         let mut params: HashMap<String, String> = HashMap::new();
         if let Some(c) = currency {
@@ -99,11 +96,10 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIDatum<Pagination<RepayRecord>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_repayment_record(
@@ -111,7 +107,7 @@ impl Kucoin {
         currency: Option<&str>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<RepaymentRecord>>, APIError> {
+    ) -> Result<Pagination<RepaymentRecord>, APIError> {
         let endpoint = String::from("/api/v1/margin/borrow/repaid");
         let mut params: HashMap<String, String> = HashMap::new();
         if let Some(c) = currency {
@@ -125,11 +121,10 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIDatum<Pagination<RepaymentRecord>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn one_click_repayment(
@@ -137,22 +132,20 @@ impl Kucoin {
         currency: &str,
         sequence: &str,
         size: f64,
-    ) -> Result<APIDatum<String>, APIError> {
+    ) -> Result<(), APIError> {
         let endpoint = String::from("/api/v1/margin/repay/all");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
         params.insert(String::from("currency"), currency.to_string());
         params.insert(String::from("sequence"), sequence.to_string());
         params.insert(String::from("size"), size.to_string());
-        let headers = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let headers = self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<serde_json::Value> = self
             .post(url, Some(headers), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_empty_result()
     }
 
     pub async fn repay_single_order(
@@ -160,22 +153,20 @@ impl Kucoin {
         currency: &str,
         trade_id: &str,
         size: f64,
-    ) -> Result<APIDatum<String>, APIError> {
+    ) -> Result<(), APIError> {
         let endpoint = String::from("/api/v1/margin/repay/single");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
         params.insert(String::from("currency"), currency.to_string());
         params.insert(String::from("trade_id"), trade_id.to_string());
         params.insert(String::from("size"), size.to_string());
-        let headers = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let headers = self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<serde_json::Value> = self
             .post(url, Some(headers), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_empty_result()
     }
 
     pub async fn post_lend_order(
@@ -184,7 +175,7 @@ impl Kucoin {
         size: f32,
         daily_int_rate: f32,
         term: i32,
-    ) -> Result<APIDatum<MarginOrderId>, APIError> {
+    ) -> Result<MarginOrderId, APIError> {
         let endpoint = String::from("/api/v1/margin/lend");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
@@ -192,25 +183,22 @@ impl Kucoin {
         params.insert(String::from("size"), size.to_string());
         params.insert(String::from("dailyIntRate"), daily_int_rate.to_string());
         params.insert(String::from("term"), term.to_string());
-        let headers = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let headers = self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<MarginOrderId> = self
             .post(url, Some(headers), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_result()
     }
 
-    pub async fn cancel_lend_order(&self, order_id: &str) -> Result<APIDatum<String>, APIError> {
+    pub async fn cancel_lend_order(&self, order_id: &str) -> Result<(), APIError> {
         let endpoint = format!("/api/v1/margin/lend/{}", order_id);
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::DELETE)
-            .unwrap();
-        let resp = self.delete(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, None, Method::DELETE)?;
+        let resp: APIDatum<serde_json::Value> =
+            self.delete(url, Some(headers)).await?.json().await?;
+        resp.into_empty_result()
     }
 
     pub async fn set_auto_lend(
@@ -220,7 +208,7 @@ impl Kucoin {
         retain_size: Option<f32>,
         daily_int_rate: Option<f32>,
         term: Option<i32>,
-    ) -> Result<APIDatum<String>, APIError> {
+    ) -> Result<(), APIError> {
         let endpoint = String::from("/api/v1/margin/toggle-auto-lend");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
@@ -235,15 +223,13 @@ impl Kucoin {
         if let Some(t) = term {
             params.insert(String::from("term"), t.to_string());
         }
-        let headers = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let headers = self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<serde_json::Value> = self
             .post(url, Some(headers), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_empty_result()
     }
 
     pub async fn get_active_order(
@@ -251,7 +237,7 @@ impl Kucoin {
         currency: &str,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<MarginOrder>>, APIError> {
+    ) -> Result<Pagination<MarginOrder>, APIError> {
         let endpoint = String::from("/api/v1/margin/lend/active");
         let mut params: HashMap<String, String> = HashMap::new();
         params.insert(String::from("currency"), currency.to_string());
@@ -263,11 +249,10 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIDatum<Pagination<MarginOrder>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_lend_history(
@@ -275,7 +260,7 @@ impl Kucoin {
         currency: Option<&str>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<MarginHistory>>, APIError> {
+    ) -> Result<Pagination<MarginHistory>, APIError> {
         let endpoint = String::from("/api/v1/margin/lend/done");
         let mut params: HashMap<String, String> = HashMap::new();
         if let Some(c) = currency {
@@ -289,11 +274,19 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIDatum<Pagination<MarginHistory>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    /// Every settled lend order, fetched page by page as the stream is read.
+    pub fn get_lend_history_stream<'a>(
+        &'a self,
+        currency: Option<&'a str>,
+        paging: Paging,
+    ) -> BoxStream<'a, Result<MarginHistory, APIError>> {
+        paginate(move |page| self.get_lend_history(currency, Some(page), Some(paging.page_size)))
     }
 
     pub async fn get_active_lend(
@@ -301,7 +294,7 @@ impl Kucoin {
         currency: Option<&str>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<LendOrder>>, APIError> {
+    ) -> Result<Pagination<LendOrder>, APIError> {
         let endpoint = String::from("/api/v1/margin/lend/trade/unsettled");
         let mut params: HashMap<String, String> = HashMap::new();
         if let Some(c) = currency {
@@ -315,11 +308,10 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIDatum<Pagination<LendOrder>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_settled_lend(
@@ -327,7 +319,7 @@ impl Kucoin {
         currency: Option<&str>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<LendHistory>>, APIError> {
+    ) -> Result<Pagination<LendHistory>, APIError> {
         let endpoint = String::from("/api/v1/margin/lend/trade/settled");
         let mut params: HashMap<String, String> = HashMap::new();
         if let Some(c) = currency {
@@ -341,56 +333,172 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIDatum<Pagination<LendHistory>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    /// Every settled lending trade, fetched page by page as the stream is read.
+    pub fn get_settled_lend_stream<'a>(
+        &'a self,
+        currency: Option<&'a str>,
+        paging: Paging,
+    ) -> BoxStream<'a, Result<LendHistory, APIError>> {
+        paginate(move |page| self.get_settled_lend(currency, Some(page), Some(paging.page_size)))
     }
 
     pub async fn get_lend_record(
         &self,
         currency: Option<&str>,
-    ) -> Result<APIData<LendRecord>, APIError> {
-        let mut endpoint = String::from("/api/v1/margin/lend/assets");
+    ) -> Result<Vec<LendRecord>, APIError> {
+        let endpoint = String::from("/api/v1/margin/lend/assets");
+        let mut params: HashMap<String, String> = HashMap::new();
         if let Some(c) = currency {
-            endpoint.push_str(&format!("?currency={}", c));
+            params.insert(String::from("currency"), c.to_string());
         }
-        let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let query = format_query(&params);
+        let url = format!("{}{}{}", &self.prefix, endpoint, query);
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIData<LendRecord> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_lend_market_data(
         &self,
         currency: &str,
         term: Option<i32>,
-    ) -> Result<APIData<LendMarketData>, APIError> {
-        let mut endpoint = format!("/api/v1/margin/market?currency={}", currency);
+    ) -> Result<Vec<LendMarketData>, APIError> {
+        let endpoint = String::from("/api/v1/margin/market");
+        let mut params: HashMap<String, String> = HashMap::new();
+        params.insert(String::from("currency"), currency.to_string());
         if let Some(t) = term {
-            endpoint.push_str(&format!("term={}", t));
+            params.insert(String::from("term"), t.to_string());
         }
-        let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let query = format_query(&params);
+        let url = format!("{}{}{}", &self.prefix, endpoint, query);
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIData<LendMarketData> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_margin_trade_data(
         &self,
         currency: &str,
-    ) -> Result<APIData<MarginTradeData>, APIError> {
-        let endpoint = format!("/api/v1/margin/trade/last?currency={}", currency);
-        let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::GET)
+    ) -> Result<Vec<MarginTradeData>, APIError> {
+        let endpoint = String::from("/api/v1/margin/trade/last");
+        let mut params: HashMap<String, String> = HashMap::new();
+        params.insert(String::from("currency"), currency.to_string());
+        let query = format_query(&params);
+        let url = format!("{}{}{}", &self.prefix, endpoint, query);
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIData<MarginTradeData> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::Method;
+
+    use crate::kucoin::transport::test::{expected_sign, mock_client};
+    use crate::kucoin::transport::MockTransport;
+
+    #[tokio::test]
+    async fn gets_the_mark_price_unsigned() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/mark-price/USDT-BTC/current",
+            r#"{"code":"200000","data":{"symbol":"USDT-BTC","granularity":5000,"timePoint":1568701710000,"value":0.00009807}}"#,
+        );
+        let price = mock_client(&mock).get_mark_price("USDT-BTC").await.unwrap();
+        assert_eq!(price.granularity, 5000);
+        assert!(!mock.requests()[0].is_signed());
+    }
+
+    #[tokio::test]
+    async fn gets_the_margin_config() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/margin/config",
+            r#"{"code":"200000","data":{"currencyList":["XEM","MATIC"],"warningDebtRatio":"0.8","liqDebtRatio":"0.9","maxLeverage":3}}"#,
+        );
+        let info = mock_client(&mock).get_margin_config_info().await.unwrap();
+        assert_eq!(info.max_leverage, 3);
+        assert_eq!(info.currency_list.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn posts_and_gets_a_borrow_order() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::POST,
+            "/api/v1/margin/borrow",
+            r#"{"code":"200000","data":{"orderId":"a2111213","currency":"USDT"}}"#,
+        )
+        .on(
+            Method::GET,
+            "/api/v1/margin/borrow",
+            r#"{"code":"200000","data":{"currency":"USDT","filled":"1.009","matchList":[],"orderId":"a2111213","size":"1.009","status":"DONE"}}"#,
+        );
+        let client = mock_client(&mock);
+        client
+            .post_borrow_order("USDT", "FOK", 1.009, None, Some("7,14"))
+            .await
+            .unwrap();
+        let order = client.get_borrow_order("a2111213").await.unwrap();
+        assert_eq!(order.status, "DONE");
+        let requests = mock.requests();
+        let body = requests[0].json();
+        assert_eq!(body["type"], "FOK");
+        assert_eq!(body["term"], "7,14");
+        assert_eq!(requests[1].query.as_deref(), Some("orderId=a2111213"));
+        assert!(requests.iter().all(|r| r.is_signed()));
+    }
+
+    #[tokio::test]
+    async fn signs_the_lend_queries_it_sends() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/margin/market",
+            r#"{"code":"200000","data":[{"dailyIntRate":"0.0001","term":7,"size":"1.02"}]}"#,
+        )
+        .on(
+            Method::GET,
+            "/api/v1/margin/lend/assets",
+            r#"{"code":"200000","data":[{"currency":"BTC","outstanding":"1.02","filledSize":"0.91","accruedInterest":"0.00000001","realizedProfit":"0.000045","isAutoLend":false}]}"#,
+        );
+        let client = mock_client(&mock);
+        let data = client.get_lend_market_data("BTC", Some(7)).await.unwrap();
+        assert_eq!(data[0].term, 7);
+        let records = client.get_lend_record(Some("BTC")).await.unwrap();
+        assert_eq!(records[0].filled_size, "0.91");
+        let requests = mock.requests();
+        assert_eq!(requests[0].query.as_deref(), Some("currency=BTC&term=7"));
+        assert_eq!(requests[1].query.as_deref(), Some("currency=BTC"));
+        for request in &requests {
+            assert_eq!(
+                request.headers["kc-api-sign"].to_str().unwrap(),
+                expected_sign(request).as_str()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn cancels_a_lend_order() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::DELETE,
+            "/api/v1/margin/lend/5da5a4f0f943c040c2f8501e",
+            r#"{"code":"200000"}"#,
+        );
+        mock_client(&mock)
+            .cancel_lend_order("5da5a4f0f943c040c2f8501e")
+            .await
             .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        assert_eq!(mock.requests()[0].method, Method::DELETE);
     }
 }
@@ -10,52 +10,49 @@ use super::model::{APIData, APIDatum};
 use super::utils::format_query;
 
 impl Kucoin {
-    pub async fn get_symbol_list(
-        &self,
-        market: Option<&str>,
-    ) -> Result<APIData<SymbolList>, APIError> {
+    pub async fn get_symbol_list(&self, market: Option<&str>) -> Result<Vec<SymbolList>, APIError> {
         let endpoint = String::from("/api/v1/symbols");
         let url = match market {
             Some(m) => format!("{}{}?market={}", &self.prefix, endpoint, m),
             None => format!("{}{}", &self.prefix, endpoint),
         };
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIData<SymbolList> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_ticker(&self, symbol: &str) -> Result<APIDatum<Ticker>, APIError> {
+    pub async fn get_ticker(&self, symbol: &str) -> Result<Ticker, APIError> {
         let endpoint = String::from("/api/v1/market/orderbook/level1");
         let url = format!("{}{}?symbol={}", &self.prefix, endpoint, symbol);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<Ticker> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_all_tickers(&self) -> Result<APIDatum<AllTickers>, APIError> {
+    pub async fn get_all_tickers(&self) -> Result<AllTickers, APIError> {
         let endpoint = String::from("/api/v1/market/allTickers");
         let url = format!("{}{}", &self.prefix, endpoint);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<AllTickers> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_daily_stats(&self, symbol: &str) -> Result<APIDatum<DailyStats>, APIError> {
+    pub async fn get_daily_stats(&self, symbol: &str) -> Result<DailyStats, APIError> {
         let endpoint = String::from("/api/v1/market/stats");
         let url = format!("{}{}?symbol={}", &self.prefix, endpoint, symbol);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<DailyStats> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_market_list(&self) -> Result<APIData<String>, APIError> {
+    pub async fn get_market_list(&self) -> Result<Vec<String>, APIError> {
         let endpoint = String::from("/api/v1/markets");
         let url = format!("{}{}", &self.prefix, endpoint);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIData<String> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_orderbook(
         &self,
         symbol: &str,
         amount: OrderBookType,
-    ) -> Result<APIDatum<OrderBook>, APIError> {
+    ) -> Result<OrderBook, APIError> {
         let endpoint = match amount {
             OrderBookType::L20 => format!("/api/v1/market/orderbook/level2_20?symbol={}", symbol),
             OrderBookType::L100 => format!("/api/v1/market/orderbook/level2_100?symbol={}", symbol),
@@ -64,17 +61,14 @@ impl Kucoin {
         };
         let url = format!("{}{}", &self.prefix, endpoint);
         let resp: APIDatum<OrderBook> = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        resp.into_result()
     }
 
-    pub async fn get_trade_histories(
-        &self,
-        symbol: &str,
-    ) -> Result<APIData<TradeHistories>, APIError> {
+    pub async fn get_trade_histories(&self, symbol: &str) -> Result<Vec<TradeHistories>, APIError> {
         let endpoint = format!("/api/v1/market/histories?symbol={}", symbol);
         let url = format!("{}{}", &self.prefix, endpoint);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIData<TradeHistories> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_klines(
@@ -83,7 +77,7 @@ impl Kucoin {
         symbol: &str,
         start_at: Option<i64>,
         end_at: Option<i64>,
-    ) -> Result<APIData<Vec<String>>, APIError> {
+    ) -> Result<Vec<Vec<String>>, APIError> {
         let mut endpoint = String::from("/api/v1/market/candles?");
         match klines {
             Klines::K1min => endpoint.push_str("type=1min"),
@@ -108,22 +102,22 @@ impl Kucoin {
             endpoint.push_str(&format!("&endAt={}", t.to_string()));
         }
         let url = format!("{}{}", &self.prefix, endpoint);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIData<Vec<String>> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_currencies(&self) -> Result<APIData<Currency>, APIError> {
+    pub async fn get_currencies(&self) -> Result<Vec<Currency>, APIError> {
         let endpoint = String::from("/api/v1/currencies");
         let url = format!("{}{}", &self.prefix, endpoint);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIData<Currency> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_currency(
         &self,
         currency: &str,
         chain: Option<Chain>,
-    ) -> Result<APIDatum<Currency>, APIError> {
+    ) -> Result<Currency, APIError> {
         let mut endpoint = format!("/api/v1/currencies/{}", currency);
         if let Some(c) = chain {
             match c {
@@ -133,15 +127,15 @@ impl Kucoin {
             }
         }
         let url = format!("{}{}", &self.prefix, endpoint);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<Currency> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_fiat_prices(
         &self,
         base: Option<&str>,
         currencies: Option<&str>,
-    ) -> Result<APIDatum<HashMap<String, String>>, APIError> {
+    ) -> Result<HashMap<String, String>, APIError> {
         let endpoint = String::from("/api/v1/prices");
         let mut params: HashMap<String, String> = HashMap::new();
         let url: String;
@@ -157,14 +151,98 @@ impl Kucoin {
         } else {
             url = format!("{}{}", &self.prefix, endpoint);
         }
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<HashMap<String, String>> = self.get(url, None).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_server_time(&self) -> Result<APIDatum<i64>, APIError> {
+    pub async fn get_server_time(&self) -> Result<i64, APIError> {
         let endpoint = String::from("/api/v1/timestamp");
         let url = format!("{}{}", &self.prefix, endpoint);
-        let resp = self.get(url, None).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<i64> = self.get(url, None).await?.json().await?;
+        resp.into_result()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::Method;
+
+    use crate::kucoin::model::market::{Chain, Klines};
+    use crate::kucoin::transport::test::mock_client;
+    use crate::kucoin::transport::MockTransport;
+
+    #[tokio::test]
+    async fn gets_a_ticker() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/market/orderbook/level1",
+            r#"{"code":"200000","data":{"sequence":"1550467636704","bestAsk":"0.03715004","size":"0.17","price":"0.03715005","bestBidSize":"3.803","bestBid":"0.03710768","bestAskSize":"1.788","time":1550653727731}}"#,
+        );
+        let ticker = mock_client(&mock).get_ticker("ETH-BTC").await.unwrap();
+        assert_eq!(ticker.best_ask, "0.03715004");
+        assert_eq!(ticker.time, 1550653727731);
+        let request = &mock.requests()[0];
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(request.query.as_deref(), Some("symbol=ETH-BTC"));
+        assert!(!request.is_signed());
+    }
+
+    #[tokio::test]
+    async fn gets_klines_for_a_range() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/market/candles",
+            r#"{"code":"200000","data":[["1545904980","0.058","0.049","0.058","0.049","0.018","0.000945"]]}"#,
+        );
+        let klines = mock_client(&mock)
+            .get_klines(Klines::K1hour, "ETH-BTC", Some(1), Some(2))
+            .await
+            .unwrap();
+        assert_eq!(klines[0][0], "1545904980");
+        assert_eq!(
+            mock.requests()[0].query.as_deref(),
+            Some("type=1hour&symbol=ETH-BTC&startAt=1&endAt=2")
+        );
+    }
+
+    #[tokio::test]
+    async fn gets_a_currency_on_a_chain() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/currencies/USDT",
+            r#"{"code":"200000","data":{"currency":"USDT","name":"USDT","fullName":"Tether","precision":8,"withdrawalMinSize":"10","withdrawalMinFee":"1","isWithdrawalEnabled":true,"isDepositEnabled":true,"isMarginEnabled":true,"isDebitEnabled":true}}"#,
+        );
+        mock_client(&mock)
+            .get_currency("USDT", Some(Chain::TRC20))
+            .await
+            .unwrap();
+        assert_eq!(mock.requests()[0].query.as_deref(), Some("chain=TRC20"));
+    }
+
+    #[tokio::test]
+    async fn gets_fiat_prices_and_market_list() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/prices",
+            r#"{"code":"200000","data":{"BTC":"3911.28000000","ETH":"144.55492453"}}"#,
+        )
+        .on(
+            Method::GET,
+            "/api/v1/markets",
+            r#"{"code":"200000","data":["BTC","ETH","USDS"]}"#,
+        );
+        let client = mock_client(&mock);
+        let prices = client
+            .get_fiat_prices(Some("USD"), Some("BTC"))
+            .await
+            .unwrap();
+        assert_eq!(prices["ETH"], "144.55492453");
+        assert_eq!(client.get_market_list().await.unwrap().len(), 3);
+        let query = mock.requests()[0].query.clone().unwrap();
+        assert!(query.contains("base=USD") && query.contains("currencies=BTC"));
     }
 }
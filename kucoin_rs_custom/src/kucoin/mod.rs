@@ -1,11 +1,21 @@
 /// Main Kucoin API Client w/ All Endpoints
 pub mod client;
 pub mod error;
+/// Recording and Replay of HTTP Fixtures
+pub mod fixture;
 pub mod margin;
 pub mod market;
 /// API Response Strucs
 pub mod model;
+/// Auto Paginating Streams over Paged Endpoints
+pub mod paginate;
+/// Client Side Rate Limiting
+pub mod rate_limit;
+/// Server Time Synchronisation
+pub mod time_sync;
 pub mod trade;
+/// Pluggable HTTP Transport w/ Mock for Tests
+pub mod transport;
 pub mod user;
 /// Utility Functions
 pub mod utils;
@@ -5,6 +5,20 @@ pub mod trade;
 pub mod user;
 pub mod websocket;
 
+use super::error::{APIError, KucoinCode};
+
+fn check_code(code: &str, msg: Option<String>) -> Result<(), APIError> {
+    let code = KucoinCode::from_code(code);
+    if code.is_success() {
+        Ok(())
+    } else {
+        Err(APIError::Kucoin {
+            code,
+            msg: msg.unwrap_or_default(),
+        })
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct APIData<T> {
@@ -13,6 +27,15 @@ pub struct APIData<T> {
     pub msg: Option<String>,
 }
 
+impl<T> APIData<T> {
+    /// Returns the data of a successful response, or the Kucoin error carried by the response.
+    /// A successful response without data is an empty list.
+    pub fn into_result(self) -> Result<Vec<T>, APIError> {
+        check_code(&self.code, self.msg)?;
+        Ok(self.data.unwrap_or_default())
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct APIDatum<T> {
@@ -21,6 +44,20 @@ pub struct APIDatum<T> {
     pub msg: Option<String>,
 }
 
+impl<T> APIDatum<T> {
+    /// Returns the data of a successful response, or the Kucoin error carried by the response.
+    pub fn into_result(self) -> Result<T, APIError> {
+        check_code(&self.code, self.msg)?;
+        self.data
+            .ok_or_else(|| APIError::Other("Kucoin response contained no data".to_string()))
+    }
+
+    /// For endpoints that answer with a null `data` field: only checks the response code.
+    pub fn into_empty_result(self) -> Result<(), APIError> {
+        check_code(&self.code, self.msg)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Method {
@@ -39,3 +76,28 @@ pub struct Pagination<T> {
     pub total_page: i32,
     pub items: Vec<T>,
 }
+
+#[cfg(test)]
+mod test {
+    use crate::kucoin::error::{APIError, KucoinCode};
+    use crate::kucoin::model::{APIData, APIDatum};
+
+    #[test]
+    fn into_result_unwraps_data_and_maps_codes() {
+        let ok: APIDatum<i64> = serde_json::from_str(r#"{"code":"200000","data":5}"#).unwrap();
+        assert_eq!(ok.into_result().unwrap(), 5);
+
+        let err: APIDatum<i64> =
+            serde_json::from_str(r#"{"code":"900001","msg":"symbol not exists"}"#).unwrap();
+        match err.into_result() {
+            Err(APIError::Kucoin { code, msg }) => {
+                assert_eq!(code, KucoinCode::UnknownSymbol);
+                assert_eq!(msg, "symbol not exists");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let empty: APIData<i64> = serde_json::from_str(r#"{"code":"200000"}"#).unwrap();
+        assert!(empty.into_result().unwrap().is_empty());
+    }
+}
@@ -1,9 +1,216 @@
+use std::fmt;
+
+use crate::kucoin::error::{APIError, KucoinCode};
+
+/// Side of an order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How long an order stays on the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    /// Good till canceled.
+    Gtc,
+    /// Good till time, see `cancel_after`.
+    Gtt,
+    /// Immediate or cancel.
+    Ioc,
+    /// Fill or kill.
+    Fok,
+}
+
+impl TimeInForce {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeInForce::Gtc => "GTC",
+            TimeInForce::Gtt => "GTT",
+            TimeInForce::Ioc => "IOC",
+            TimeInForce::Fok => "FOK",
+        }
+    }
+}
+
+impl fmt::Display for TimeInForce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Self trade prevention, what happens when an order would match one of the same account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Stp {
+    #[serde(rename = "CN")]
+    CancelNewest,
+    #[serde(rename = "CO")]
+    CancelOldest,
+    #[serde(rename = "CB")]
+    CancelBoth,
+    #[serde(rename = "DC")]
+    DecreaseAndCancel,
+}
+
+impl Stp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stp::CancelNewest => "CN",
+            Stp::CancelOldest => "CO",
+            Stp::CancelBoth => "CB",
+            Stp::DecreaseAndCancel => "DC",
+        }
+    }
+}
+
+impl fmt::Display for Stp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The account an order trades from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TradeType {
+    Trade,
+    MarginTrade,
+    MarginIsolatedTrade,
+}
+
+impl TradeType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TradeType::Trade => "TRADE",
+            TradeType::MarginTrade => "MARGIN_TRADE",
+            TradeType::MarginIsolatedTrade => "MARGIN_ISOLATED_TRADE",
+        }
+    }
+}
+
+impl fmt::Display for TradeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Whether a stop triggers when the price falls to the stop price or rises to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StopKind {
+    Loss,
+    Entry,
+}
+
+impl StopKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StopKind::Loss => "loss",
+            StopKind::Entry => "entry",
+        }
+    }
+}
+
+impl fmt::Display for StopKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which orders a listing returns, the ones still open or the finished ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    Active,
+    Done,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Active => "active",
+            OrderStatus::Done => "done",
+        }
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct OrderResp {
     pub order_id: String,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiOrderResp {
+    pub data: Vec<BatchOrderResult>,
+}
+
+/// Outcome of one order of a batch, Kucoin accepts or rejects each one on its own.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchOrderResult {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub client_oid: String,
+    pub symbol: String,
+    pub side: String,
+    #[serde(default)]
+    pub price: Option<String>,
+    #[serde(default)]
+    pub size: Option<String>,
+    /// `success` or `fail`.
+    pub status: String,
+    #[serde(default)]
+    pub fail_msg: Option<String>,
+}
+
+impl BatchOrderResult {
+    pub fn is_success(&self) -> bool {
+        self.status == "success"
+    }
+
+    /// Why the order wasn't placed, typed from `fail_msg` so `is_retryable` can judge it.
+    /// `None` for an order that was placed.
+    pub fn error(&self) -> Option<APIError> {
+        if self.is_success() {
+            return None;
+        }
+        let msg = self
+            .fail_msg
+            .clone()
+            .unwrap_or_else(|| String::from("rejected"));
+        Some(match KucoinCode::from_message(&msg) {
+            Some(code) => APIError::Kucoin { code, msg },
+            None => APIError::Other(msg),
+        })
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CancelResp {
@@ -85,3 +292,73 @@ pub struct FillsInfo {
     pub created_at: i64,
     pub trade_type: String,
 }
+
+/// A stop order as the stop order endpoints return it, before or after it triggered.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StopOrderInfo {
+    pub id: String,
+    pub symbol: String,
+    pub user_id: Option<String>,
+    /// `NEW` until the stop price is hit, `TRIGGERED` after.
+    pub status: String,
+    pub r#type: String,
+    pub side: String,
+    pub price: Option<String>,
+    pub size: Option<String>,
+    pub funds: Option<String>,
+    pub stp: Option<String>,
+    pub time_in_force: Option<String>,
+    pub cancel_after: Option<i64>,
+    pub post_only: Option<bool>,
+    pub hidden: Option<bool>,
+    pub iceberg: Option<bool>,
+    pub visible_size: Option<String>,
+    pub channel: Option<String>,
+    pub client_oid: Option<String>,
+    pub remark: Option<String>,
+    pub tags: Option<String>,
+    pub order_time: Option<i64>,
+    pub domain_id: Option<String>,
+    pub trade_source: Option<String>,
+    pub trade_type: Option<String>,
+    pub fee_currency: Option<String>,
+    pub taker_fee_rate: Option<String>,
+    pub maker_fee_rate: Option<String>,
+    pub created_at: i64,
+    /// `loss` or `entry`.
+    pub stop: String,
+    pub stop_trigger_time: Option<i64>,
+    pub stop_price: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OrderStatus, Side, StopKind, Stp, TimeInForce, TradeType};
+
+    #[test]
+    fn serializes_as_kucoin_spells_it() {
+        let values = (
+            Side::Sell,
+            TimeInForce::Ioc,
+            Stp::DecreaseAndCancel,
+            TradeType::MarginIsolatedTrade,
+            StopKind::Entry,
+            OrderStatus::Active,
+        );
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!(
+            json,
+            r#"["sell","IOC","DC","MARGIN_ISOLATED_TRADE","entry","active"]"#
+        );
+        assert_eq!(
+            serde_json::from_str::<(Side, TimeInForce, Stp, TradeType, StopKind, OrderStatus)>(
+                &json
+            )
+            .unwrap(),
+            values
+        );
+        assert_eq!(TradeType::MarginTrade.to_string(), "MARGIN_TRADE");
+        assert!(serde_json::from_str::<Side>("\"BUY\"").is_err());
+    }
+}
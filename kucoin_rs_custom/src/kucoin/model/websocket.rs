@@ -30,7 +30,7 @@ pub enum WSTopic {
     IndexPrice(Vec<String>),
     MarketPrice(Vec<String>),
     OrderBookChange(Vec<String>),
-    StopOrder(Vec<String>),
+    StopOrder,
     Balances,
     DebtRatio,
     PositionChange,
@@ -346,15 +346,22 @@ pub struct BookChange {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StopOrder {
-    pub sequence: String,
-    pub symbol: String,
-    pub side: String,
+    pub created_at: i64,
     pub order_id: String,
-    pub stop_entry: String,
-    pub funds: String,
-    pub time: String,
+    pub order_price: Option<String>,
+    pub order_type: String,
+    pub side: String,
+    pub size: Option<String>,
+    /// `loss` or `entry`.
+    pub stop: String,
+    pub stop_price: String,
+    pub symbol: String,
+    pub trade_type: String,
+    pub trigger_success: Option<bool>,
+    /// Nanoseconds.
+    pub ts: i64,
+    /// `open`, `triggered` or `cancel`.
     pub r#type: String,
-    pub reason: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
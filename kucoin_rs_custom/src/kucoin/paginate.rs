@@ -0,0 +1,224 @@
+use std::future::Future;
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+
+use super::error::APIError;
+use super::model::Pagination;
+
+/// Largest page Kucoin serves on the paged endpoints.
+pub const MAX_PAGE_SIZE: i32 = 500;
+
+/// Longest date range Kucoin accepts in one order, fill or account history query.
+pub const MAX_WINDOW: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How a stream walks a paged endpoint.
+///
+/// Example:
+/// ``` rust
+/// use kucoin_rs_custom::kucoin::paginate::{Paging, MAX_WINDOW};
+///
+///     let paging = Paging::new().page_size(100).window(MAX_WINDOW).build();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Paging {
+    pub page_size: i32,
+    /// When set, a `startAt`/`endAt` range is queried in consecutive windows of at most this
+    /// length, up to now when there is no end. Without it the range is sent as given.
+    pub window: Option<Duration>,
+}
+
+impl Default for Paging {
+    fn default() -> Self {
+        Paging {
+            page_size: MAX_PAGE_SIZE,
+            window: None,
+        }
+    }
+}
+
+impl Paging {
+    pub fn new() -> Self {
+        Paging::default()
+    }
+
+    pub fn page_size(&mut self, s: i32) -> &mut Self {
+        self.page_size = s.clamp(1, MAX_PAGE_SIZE);
+        self
+    }
+
+    pub fn window(&mut self, d: Duration) -> &mut Self {
+        self.window = Some(d);
+        self
+    }
+
+    pub fn build(&self) -> Self {
+        *self
+    }
+}
+
+/// Yields the items of every page in order, fetching a page only once the previous one is
+/// used up. `fetch` is given the page number, starting at 1. Each page is an ordinary request,
+/// so it waits on the client's rate limits like any other. The stream ends after the last page
+/// or after the first error.
+pub fn paginate<'a, T, F, Fut>(mut fetch: F) -> BoxStream<'a, Result<T, APIError>>
+where
+    T: Send + 'a,
+    F: FnMut(i32) -> Fut + Send + 'a,
+    Fut: Future<Output = Result<Pagination<T>, APIError>> + Send + 'a,
+{
+    stream::unfold(Some(1), move |page| {
+        let next = page.map(|p| (p, fetch(p)));
+        async move {
+            let (current, fut) = next?;
+            match fut.await {
+                Ok(page) => {
+                    let more = current < page.total_page && !page.items.is_empty();
+                    Some((Ok(page.items), if more { Some(current + 1) } else { None }))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        }
+    })
+    .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+    .try_flatten()
+    .boxed()
+}
+
+/// Same as `paginate` over a `startAt`/`endAt` range, split into `paging.window` long windows
+/// when set. `fetch` is given the start, end and page number of each request, `now_ms` closes
+/// an open range.
+pub fn paginate_range<'a, T, F, Fut>(
+    start_at: Option<i64>,
+    end_at: Option<i64>,
+    paging: Paging,
+    now_ms: i64,
+    fetch: F,
+) -> BoxStream<'a, Result<T, APIError>>
+where
+    T: Send + 'a,
+    F: Fn(Option<i64>, Option<i64>, i32) -> Fut + Clone + Send + 'a,
+    Fut: Future<Output = Result<Pagination<T>, APIError>> + Send + 'a,
+{
+    let ranges = match (start_at, paging.window) {
+        (Some(start), Some(window)) => date_windows(start, end_at.unwrap_or(now_ms), window)
+            .into_iter()
+            .map(|(s, e)| (Some(s), Some(e)))
+            .collect(),
+        _ => vec![(start_at, end_at)],
+    };
+    stream::iter(ranges)
+        .flat_map(move |(start, end)| {
+            let fetch = fetch.clone();
+            paginate(move |page| fetch(start, end, page))
+        })
+        .boxed()
+}
+
+/// Splits `start..=end`, in milliseconds, into consecutive inclusive windows no longer than
+/// `window`. An empty range still gives one window so the query is made.
+pub fn date_windows(start: i64, end: i64, window: Duration) -> Vec<(i64, i64)> {
+    let len = (window.as_millis() as i64).max(1);
+    let mut windows = vec![];
+    let mut from = start;
+    loop {
+        let to = from.saturating_add(len - 1).min(end);
+        windows.push((from, to.max(from)));
+        if to >= end {
+            return windows;
+        }
+        from = to + 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::TryStreamExt;
+
+    use super::{date_windows, paginate, paginate_range, Paging};
+    use crate::kucoin::error::APIError;
+    use crate::kucoin::model::Pagination;
+
+    fn page(current: i32, total_page: i32, items: Vec<i32>) -> Pagination<i32> {
+        Pagination {
+            current_page: current,
+            page_size: 2,
+            total_num: 0,
+            total_page,
+            items,
+        }
+    }
+
+    #[tokio::test]
+    async fn walks_pages_lazily_and_stops_at_errors() {
+        let asked = Arc::new(Mutex::new(vec![]));
+        let log = asked.clone();
+        let items: Vec<i32> = paginate(move |p| {
+            log.lock().unwrap().push(p);
+            async move { Ok(page(p, 3, vec![p * 10, p * 10 + 1])) }
+        })
+        .try_collect()
+        .await
+        .unwrap();
+        assert_eq!(items, vec![10, 11, 20, 21, 30, 31]);
+        assert_eq!(*asked.lock().unwrap(), vec![1, 2, 3]);
+
+        let failed: Result<Vec<i32>, APIError> = paginate(|p| async move {
+            if p == 1 {
+                Ok(page(1, 3, vec![1]))
+            } else {
+                Err(APIError::Other("down".to_string()))
+            }
+        })
+        .try_collect()
+        .await;
+        assert!(failed.is_err());
+    }
+
+    #[tokio::test]
+    async fn splits_long_ranges() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let day_ms = day.as_millis() as i64;
+        assert_eq!(
+            date_windows(0, 2 * day_ms, day),
+            vec![
+                (0, day_ms - 1),
+                (day_ms, 2 * day_ms - 1),
+                (2 * day_ms, 2 * day_ms)
+            ]
+        );
+        assert_eq!(date_windows(5, 5, day), vec![(5, 5)]);
+
+        let asked = Arc::new(Mutex::new(vec![]));
+        let log = asked.clone();
+        let paging = Paging::new().window(day).build();
+        let items: Vec<i32> = paginate_range(Some(0), None, paging, day_ms + 5, move |s, e, p| {
+            log.lock().unwrap().push((s, e, p));
+            async move { Ok(page(p, 1, vec![s.unwrap() as i32])) }
+        })
+        .try_collect()
+        .await
+        .unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            *asked.lock().unwrap(),
+            vec![
+                (Some(0), Some(day_ms - 1), 1),
+                (Some(day_ms), Some(day_ms + 5), 1)
+            ]
+        );
+
+        // Without a window the range goes out as given.
+        let ranges: Vec<i32> = paginate_range(Some(0), None, Paging::new(), day_ms, |s, e, p| {
+            assert_eq!((s, e), (Some(0), None));
+            async move { Ok(page(p, 1, vec![1])) }
+        })
+        .try_collect()
+        .await
+        .unwrap();
+        assert_eq!(ranges, vec![1]);
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Kucoin enforces separate limits for public market data, private account endpoints
+/// and order placement. Every request made through the client is counted against one of these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EndpointClass {
+    Public,
+    Private,
+    Order,
+}
+
+impl EndpointClass {
+    /// Classifies a request by its method, path and whether it is signed.
+    pub fn classify(method: &reqwest::Method, path: &str, signed: bool) -> Self {
+        let is_order_path =
+            path.starts_with("/api/v1/orders") || path.starts_with("/api/v1/margin/order");
+        if *method == reqwest::Method::POST && is_order_path {
+            EndpointClass::Order
+        } else if signed {
+            EndpointClass::Private
+        } else {
+            EndpointClass::Public
+        }
+    }
+}
+
+/// Number of requests allowed within a window. Tokens are refilled continuously
+/// so a bucket never allows more than `requests` in any window of length `per`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub requests: u32,
+    pub per: Duration,
+}
+
+impl Quota {
+    pub fn new(requests: u32, per: Duration) -> Self {
+        Quota { requests, per }
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        self.requests as f64 / self.per.as_secs_f64()
+    }
+}
+
+/// Quotas per endpoint class. Defaults follow the published Kucoin spot limits.
+///
+/// Example:
+/// ``` rust
+/// use std::time::Duration;
+/// use kucoin_rs_custom::kucoin::rate_limit::{Quota, RateLimits};
+///
+///     let limits = RateLimits::new()
+///         .order(Quota::new(20, Duration::from_secs(3)))
+///         .build();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimits {
+    pub public: Quota,
+    pub private: Quota,
+    pub order: Quota,
+    /// Wait applied after a 429 response when Kucoin doesn't say how long to back off.
+    pub throttle_backoff: Duration,
+    /// Number of times a rate limited request is re-sent before the 429 is handed back.
+    pub max_throttle_retries: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            public: Quota::new(30, Duration::from_secs(3)),
+            private: Quota::new(30, Duration::from_secs(3)),
+            order: Quota::new(45, Duration::from_secs(3)),
+            throttle_backoff: Duration::from_secs(1),
+            max_throttle_retries: 5,
+        }
+    }
+}
+
+impl RateLimits {
+    pub fn new() -> Self {
+        RateLimits::default()
+    }
+
+    pub fn public(&mut self, q: Quota) -> &mut Self {
+        self.public = q;
+        self
+    }
+
+    pub fn private(&mut self, q: Quota) -> &mut Self {
+        self.private = q;
+        self
+    }
+
+    pub fn order(&mut self, q: Quota) -> &mut Self {
+        self.order = q;
+        self
+    }
+
+    pub fn throttle_backoff(&mut self, d: Duration) -> &mut Self {
+        self.throttle_backoff = d;
+        self
+    }
+
+    pub fn max_throttle_retries(&mut self, r: u32) -> &mut Self {
+        self.max_throttle_retries = r;
+        self
+    }
+
+    pub fn build(&self) -> Self {
+        self.clone()
+    }
+
+    fn quota(&self, class: EndpointClass) -> Quota {
+        match class {
+            EndpointClass::Public => self.public,
+            EndpointClass::Private => self.private,
+            EndpointClass::Order => self.order,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    quota: Quota,
+    tokens: f64,
+    last_refill: Instant,
+    blocked_until: Option<Instant>,
+}
+
+impl TokenBucket {
+    fn new(quota: Quota, now: Instant) -> Self {
+        TokenBucket {
+            quota,
+            tokens: quota.requests as f64,
+            last_refill: now,
+            blocked_until: None,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.quota.refill_per_sec()).min(self.quota.requests as f64);
+        self.last_refill = now;
+    }
+
+    /// Takes a token if one is available, otherwise returns how long to wait before trying again.
+    fn try_acquire(&mut self, now: Instant) -> Option<Duration> {
+        if let Some(until) = self.blocked_until {
+            if now < until {
+                return Some(until - now);
+            }
+            // Let one request probe the endpoint as soon as the back off is over.
+            self.blocked_until = None;
+            self.last_refill = until;
+            self.tokens = self.tokens.max(1.0);
+        }
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            let missing = 1.0 - self.tokens;
+            Some(Duration::from_secs_f64(
+                missing / self.quota.refill_per_sec(),
+            ))
+        }
+    }
+
+    fn throttle(&mut self, now: Instant, backoff: Duration) {
+        self.tokens = 0.0;
+        let until = now + backoff;
+        match self.blocked_until {
+            Some(b) if b >= until => {}
+            _ => self.blocked_until = Some(until),
+        }
+    }
+}
+
+/// Shared token buckets for one Kucoin account. Cloning is cheap and clones draw from the same
+/// buckets, so several clients for the same account can share a limiter.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Arc<Mutex<HashMap<EndpointClass, TokenBucket>>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(RateLimits::default())
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            limits,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn limits(&self) -> &RateLimits {
+        &self.limits
+    }
+
+    fn with_bucket<T>(
+        &self,
+        class: EndpointClass,
+        f: impl FnOnce(&mut TokenBucket, Instant) -> T,
+    ) -> T {
+        let now = Instant::now();
+        let quota = self.limits.quota(class);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets
+            .entry(class)
+            .or_insert_with(|| TokenBucket::new(quota, now));
+        f(bucket, now)
+    }
+
+    /// Waits until a request of the given class is allowed to go out.
+    pub async fn acquire(&self, class: EndpointClass) {
+        while let Some(wait) = self.with_bucket(class, |b, now| b.try_acquire(now)) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Empties the bucket and blocks the class for `backoff`, used when Kucoin answers with 429.
+    pub fn throttle(&self, class: EndpointClass, backoff: Duration) {
+        self.with_bucket(class, |b, now| b.throttle(now, backoff))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EndpointClass, Quota, TokenBucket};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn bucket_allows_burst_then_waits() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(Quota::new(3, Duration::from_secs(3)), now);
+        assert_eq!(bucket.try_acquire(now), None);
+        assert_eq!(bucket.try_acquire(now), None);
+        assert_eq!(bucket.try_acquire(now), None);
+        let wait = bucket.try_acquire(now).expect("bucket should be empty");
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        assert_eq!(bucket.try_acquire(now + Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn throttled_bucket_blocks_until_backoff_passes() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(Quota::new(10, Duration::from_secs(1)), now);
+        bucket.throttle(now, Duration::from_secs(2));
+        assert_eq!(
+            bucket.try_acquire(now + Duration::from_secs(1)),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(bucket.try_acquire(now + Duration::from_secs(2)), None);
+    }
+
+    #[test]
+    fn classify_endpoints() {
+        let post = reqwest::Method::POST;
+        let get = reqwest::Method::GET;
+        assert_eq!(
+            EndpointClass::classify(&post, "/api/v1/orders", true),
+            EndpointClass::Order
+        );
+        assert_eq!(
+            EndpointClass::classify(&get, "/api/v1/orders", true),
+            EndpointClass::Private
+        );
+        assert_eq!(
+            EndpointClass::classify(&get, "/api/v1/market/orderbook/level1", false),
+            EndpointClass::Public
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::utils::get_time;
+
+/// One round trip to the server time endpoint, all in milliseconds since the epoch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeSample {
+    pub sent: i64,
+    pub server: i64,
+    pub received: i64,
+}
+
+impl TimeSample {
+    pub fn round_trip(&self) -> i64 {
+        self.received - self.sent
+    }
+
+    /// Assumes the server stamped its answer halfway through the round trip.
+    pub fn offset(&self) -> i64 {
+        self.server - (self.sent + self.round_trip() / 2)
+    }
+}
+
+/// Estimates the offset from the sample with the shortest round trip, the one where the
+/// halfway assumption can be off by the least.
+pub fn estimate_offset(samples: &[TimeSample]) -> Option<i64> {
+    samples
+        .iter()
+        .filter(|s| s.round_trip() >= 0)
+        .min_by_key(|s| s.round_trip())
+        .map(TimeSample::offset)
+}
+
+/// Whether a response body carries Kucoin's invalid timestamp code, 400002.
+pub(crate) fn is_timestamp_error(body: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Value>(body)
+        .map(|v| v["code"] == "400002")
+        .unwrap_or(false)
+}
+
+/// Kucoin's clock as estimated from the local one. Cloning is cheap and clones share the
+/// estimate, so every client of one account signs with the same corrected time.
+#[derive(Debug, Clone)]
+pub struct TimeSync {
+    offset_ms: Arc<AtomicI64>,
+    last_sync: Arc<Mutex<Option<Instant>>>,
+    syncing: Arc<AtomicBool>,
+    refresh_every: Duration,
+}
+
+impl Default for TimeSync {
+    fn default() -> Self {
+        TimeSync::new(Duration::from_secs(300))
+    }
+}
+
+impl TimeSync {
+    pub fn new(refresh_every: Duration) -> Self {
+        TimeSync {
+            offset_ms: Arc::new(AtomicI64::new(0)),
+            last_sync: Arc::new(Mutex::new(None)),
+            syncing: Arc::new(AtomicBool::new(false)),
+            refresh_every,
+        }
+    }
+
+    /// Milliseconds to add to the local clock to get Kucoin's.
+    pub fn offset_ms(&self) -> i64 {
+        self.offset_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn set_offset_ms(&self, offset: i64) {
+        self.offset_ms.store(offset, Ordering::Relaxed);
+        *self.last_sync.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+    }
+
+    /// Kucoin's time in milliseconds since the epoch.
+    pub fn now_ms(&self) -> i64 {
+        get_time() as i64 + self.offset_ms()
+    }
+
+    /// Whether the offset was never measured or is older than the refresh interval.
+    pub fn is_stale(&self) -> bool {
+        match *self.last_sync.lock().unwrap_or_else(|e| e.into_inner()) {
+            Some(at) => at.elapsed() >= self.refresh_every,
+            None => true,
+        }
+    }
+
+    /// Forgets the last measurement so the next request resyncs.
+    pub fn invalidate(&self) {
+        *self.last_sync.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Claims the right to run a resync, false while another one is under way.
+    pub(crate) fn begin_sync(&self) -> bool {
+        !self.syncing.swap(true, Ordering::AcqRel)
+    }
+
+    pub(crate) fn end_sync(&self) {
+        self.syncing.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{estimate_offset, is_timestamp_error, TimeSample, TimeSync};
+    use std::time::Duration;
+
+    #[test]
+    fn picks_the_fastest_round_trip() {
+        let samples = [
+            TimeSample {
+                sent: 1_000,
+                server: 6_300,
+                received: 1_400,
+            },
+            TimeSample {
+                sent: 2_000,
+                server: 7_020,
+                received: 2_040,
+            },
+            TimeSample {
+                sent: 3_000,
+                server: 9_000,
+                received: 2_990,
+            },
+        ];
+        // 7_020 - (2_000 + 20)
+        assert_eq!(estimate_offset(&samples), Some(5_000));
+        assert_eq!(estimate_offset(&[]), None);
+    }
+
+    #[test]
+    fn spots_timestamp_errors() {
+        assert!(is_timestamp_error(
+            br#"{"code":"400002","msg":"KC-API-TIMESTAMP Invalid"}"#
+        ));
+        assert!(!is_timestamp_error(
+            br#"{"code":"400005","msg":"Invalid KC-API-SIGN"}"#
+        ));
+        assert!(!is_timestamp_error(b"<html>bad gateway</html>"));
+    }
+
+    #[test]
+    fn goes_stale_and_can_be_invalidated() {
+        let sync = TimeSync::new(Duration::from_secs(60));
+        assert!(sync.is_stale());
+        sync.set_offset_ms(-1_500);
+        assert!(!sync.is_stale());
+        assert_eq!(sync.offset_ms(), -1_500);
+        sync.invalidate();
+        assert!(sync.is_stale());
+        assert!(sync.begin_sync());
+        assert!(!sync.begin_sync());
+        sync.end_sync();
+        assert!(sync.begin_sync());
+    }
+}
@@ -1,12 +1,17 @@
+use futures::stream::BoxStream;
 use reqwest::header;
+use serde_json::json;
 use std::collections::HashMap;
 
 use super::client::Kucoin;
 use super::error::APIError;
 use super::model::trade::{
-    CancelByClientOidResp, CancelResp, FillsInfo, HistoricalOrder, OrderInfo, OrderResp,
+    BatchOrderResult, CancelByClientOidResp, CancelResp, FillsInfo, HistoricalOrder,
+    MultiOrderResp, OrderInfo, OrderResp, OrderStatus, Side, StopKind, StopOrderInfo, Stp,
+    TimeInForce, TradeType,
 };
 use super::model::{APIData, APIDatum, Method, Pagination};
+use super::paginate::{paginate_range, Paging};
 use super::utils::format_query;
 
 impl Kucoin {
@@ -16,11 +21,11 @@ impl Kucoin {
         &self,
         client_oid: &str,
         symbol: &str,
-        side: &str,
+        side: Side,
         price: &str,
         size: &str,
         optionals: Option<OrderOptionals<'_>>,
-    ) -> Result<APIDatum<OrderResp>, APIError> {
+    ) -> Result<OrderResp, APIError> {
         let endpoint = String::from("/api/v1/orders");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
@@ -30,18 +35,17 @@ impl Kucoin {
         params.insert(String::from("price"), price.to_string());
         params.insert(String::from("size"), size.to_string());
         if let Some(opt) = optionals {
-            let opts = parse_order(opt);
+            let opts = parse_order(opt)?;
             params.extend(opts);
         };
-        let headers: header::HeaderMap = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let headers: header::HeaderMap =
+            self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<OrderResp> = self
             .post(url, Some(headers), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_result()
     }
 
     /// Places a market order. Takes required inputs directly and a Some<OrderOptionals> type, or None for
@@ -53,11 +57,11 @@ impl Kucoin {
         &self,
         client_oid: &str,
         symbol: &str,
-        side: &str,
+        side: Side,
         size: Option<f32>,
         funds: Option<String>,
         optionals: Option<OrderOptionals<'_>>,
-    ) -> Result<APIDatum<OrderResp>, APIError> {
+    ) -> Result<OrderResp, APIError> {
         let endpoint = String::from("/api/v1/orders");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
@@ -72,51 +76,77 @@ impl Kucoin {
             params.insert(String::from("funds"), f);
         };
         if let Some(opt) = optionals {
-            let opts = parse_order(opt);
+            let opts = parse_order(opt)?;
             params.extend(opts);
         };
-        let headers: header::HeaderMap = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let headers: header::HeaderMap =
+            self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<OrderResp> = self
             .post(url, Some(headers), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_result()
+    }
+
+    /// Places up to `MAX_BATCH_ORDERS` limit orders on one symbol in a single request. Kucoin accepts
+    /// or rejects each order on its own, so the call only fails when the whole request does and the
+    /// results, in the order given, say which orders were placed.
+    pub async fn post_multi_orders(
+        &self,
+        symbol: &str,
+        orders: &[BatchOrder<'_>],
+    ) -> Result<Vec<BatchOrderResult>, APIError> {
+        let endpoint = String::from("/api/v1/orders/multi");
+        let url = format!("{}{}", &self.prefix, endpoint);
+        let mut order_list: Vec<HashMap<String, String>> = Vec::with_capacity(orders.len());
+        for order in orders {
+            let mut params: HashMap<String, String> = HashMap::new();
+            params.insert(String::from("clientOid"), order.client_oid.to_string());
+            params.insert(String::from("side"), order.side.to_string());
+            params.insert(String::from("type"), String::from("limit"));
+            params.insert(String::from("price"), order.price.to_string());
+            params.insert(String::from("size"), order.size.to_string());
+            if let Some(opt) = order.optionals.clone() {
+                params.extend(parse_order(opt)?);
+            };
+            order_list.push(params);
+        }
+        let body = json!({ "symbol": symbol, "orderList": order_list });
+        let headers = self.sign_json_headers(endpoint, &body)?;
+        let resp: APIDatum<MultiOrderResp> =
+            self.post_json(url, headers, &body).await?.json().await?;
+        Ok(resp.into_result()?.data)
     }
 
     /// Cancels an order based on the provided order id (required).
-    pub async fn cancel_order(&self, order_id: &str) -> Result<APIDatum<CancelResp>, APIError> {
+    pub async fn cancel_order(&self, order_id: &str) -> Result<CancelResp, APIError> {
         let endpoint = format!("/api/v1/orders/{}", order_id);
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers: header::HeaderMap = self
-            .sign_headers(endpoint, None, None, Method::DELETE)
-            .unwrap();
-        let resp = self.delete(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers: header::HeaderMap = self.sign_headers(endpoint, None, None, Method::DELETE)?;
+        let resp: APIDatum<CancelResp> = self.delete(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     /// Cancels an order based on the provided order id (required).
     pub async fn cancel_order_by_client_oid(
         &self,
         client_oid: &str,
-    ) -> Result<APIDatum<CancelByClientOidResp>, APIError> {
+    ) -> Result<CancelByClientOidResp, APIError> {
         let endpoint = format!("/api/v1/order/client-order/{}", client_oid);
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers: header::HeaderMap = self
-            .sign_headers(endpoint, None, None, Method::DELETE)
-            .unwrap();
-        let resp = self.delete(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers: header::HeaderMap = self.sign_headers(endpoint, None, None, Method::DELETE)?;
+        let resp: APIDatum<CancelByClientOidResp> =
+            self.delete(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     // Cancels all orders of a given symbol (optional) or trade type (optional).
     pub async fn cancel_all_orders(
         &self,
         symbol: Option<&str>,
-        trade_type: Option<&str>,
-    ) -> Result<APIDatum<CancelResp>, APIError> {
+        trade_type: Option<TradeType>,
+    ) -> Result<CancelResp, APIError> {
         let endpoint = String::from("/api/v1/orders");
         let url: String;
         let headers: header::HeaderMap;
@@ -125,29 +155,25 @@ impl Kucoin {
             params.insert(String::from("symbol"), s.to_owned());
         };
         if let Some(t) = trade_type {
-            params.insert(String::from("tradeType"), t.to_owned());
+            params.insert(String::from("tradeType"), t.to_string());
         };
         if !params.is_empty() {
             let query = format_query(&params);
             url = format!("{}{}{}", &self.prefix, endpoint, query);
-            headers = self
-                .sign_headers(endpoint, Some(&params), None, Method::DELETE)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, Some(query), Method::DELETE)?;
         } else {
             url = format!("{}{}", &self.prefix, endpoint);
-            headers = self
-                .sign_headers(endpoint, None, None, Method::DELETE)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, None, Method::DELETE)?;
         };
-        let resp = self.delete(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<CancelResp> = self.delete(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     // Consider list orders
     pub async fn get_orders(
         &self,
         optionals: Option<OrderInfoOptionals<'_>>,
-    ) -> Result<APIDatum<Pagination<OrderInfo>>, APIError> {
+    ) -> Result<Pagination<OrderInfo>, APIError> {
         let endpoint = String::from("/api/v1/orders");
         let url: String;
         let headers: header::HeaderMap;
@@ -184,17 +210,14 @@ impl Kucoin {
         if !params.is_empty() {
             let query = format_query(&params);
             url = format!("{}{}{}", &self.prefix, endpoint, query);
-            headers = self
-                .sign_headers(endpoint, None, Some(query), Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         } else {
             url = format!("{}{}", &self.prefix, endpoint);
-            headers = self
-                .sign_headers(endpoint, None, None, Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, None, Method::GET)?;
         }
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<Pagination<OrderInfo>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_v1_historical_orders(
@@ -202,10 +225,10 @@ impl Kucoin {
         symbol: Option<&str>,
         start_at: Option<i64>,
         end_at: Option<i64>,
-        side: Option<&str>,
+        side: Option<Side>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<HistoricalOrder>>, APIError> {
+    ) -> Result<Pagination<HistoricalOrder>, APIError> {
         let endpoint = String::from("/api/v1/orders");
         let url: String;
         let headers: header::HeaderMap;
@@ -231,43 +254,77 @@ impl Kucoin {
         if !params.is_empty() {
             let query = format_query(&params);
             url = format!("{}{}{}", &self.prefix, endpoint, query);
-            headers = self
-                .sign_headers(endpoint, None, Some(query), Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         } else {
             url = format!("{}{}", &self.prefix, endpoint);
-            headers = self
-                .sign_headers(endpoint, None, None, Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, None, Method::GET)?;
         }
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<Pagination<HistoricalOrder>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    /// Every order matching `optionals`, fetched page by page as the stream is read.
+    /// `current_page` and `page_size` in `optionals` are ignored, see `Paging`.
+    pub fn get_orders_stream<'a>(
+        &'a self,
+        optionals: OrderInfoOptionals<'a>,
+        paging: Paging,
+    ) -> BoxStream<'a, Result<OrderInfo, APIError>> {
+        let (start_at, end_at) = (optionals.start_at, optionals.end_at);
+        let now_ms = self.time_sync().now_ms();
+        paginate_range(
+            start_at,
+            end_at,
+            paging,
+            now_ms,
+            move |start_at, end_at, page| {
+                let mut opts = optionals.clone();
+                opts.start_at = start_at;
+                opts.end_at = end_at;
+                opts.current_page = Some(page);
+                opts.page_size = Some(paging.page_size);
+                self.get_orders(Some(opts))
+            },
+        )
     }
 
-    pub async fn get_recent_orders(&self) -> Result<APIData<OrderInfo>, APIError> {
+    pub async fn get_recent_orders(&self) -> Result<Vec<OrderInfo>, APIError> {
         let endpoint = String::from("/api/v1/limit/orders");
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers: header::HeaderMap = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers: header::HeaderMap = self.sign_headers(endpoint, None, None, Method::GET)?;
+        let resp: APIData<OrderInfo> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_order(&self, order_id: &str) -> Result<APIDatum<OrderInfo>, APIError> {
+    pub async fn get_order(&self, order_id: &str) -> Result<OrderInfo, APIError> {
         let endpoint = format!("/api/v1/orders/{}", order_id);
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers: header::HeaderMap = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers: header::HeaderMap = self.sign_headers(endpoint, None, None, Method::GET)?;
+        let resp: APIDatum<OrderInfo> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    /// The order placed with `client_oid`, `None` when Kucoin has no such order. Lets a caller
+    /// find out whether a placement that timed out went through before sending it again.
+    pub async fn get_order_by_client_oid(
+        &self,
+        client_oid: &str,
+    ) -> Result<Option<OrderInfo>, APIError> {
+        let endpoint = format!("/api/v1/order/client-order/{}", client_oid);
+        let url = format!("{}{}", &self.prefix, endpoint);
+        let headers: header::HeaderMap = self.sign_headers(endpoint, None, None, Method::GET)?;
+        let resp: APIDatum<OrderInfo> = self.get(url, Some(headers)).await?.json().await?;
+        if resp.data.is_none() {
+            return resp.into_empty_result().map(|_| None);
+        }
+        resp.into_result().map(Some)
     }
 
     pub async fn get_fills(
         &self,
         optionals: Option<FillsOptionals<'_>>,
-    ) -> Result<APIDatum<Pagination<FillsInfo>>, APIError> {
+    ) -> Result<Pagination<FillsInfo>, APIError> {
         let endpoint = String::from("/api/v1/fills");
         let url: String;
         let headers: header::HeaderMap;
@@ -304,31 +361,292 @@ impl Kucoin {
         if !params.is_empty() {
             let query = format_query(&params);
             url = format!("{}{}{}", &self.prefix, endpoint, query);
-            headers = self
-                .sign_headers(endpoint, None, Some(query), Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        } else {
+            url = format!("{}{}", &self.prefix, endpoint);
+            headers = self.sign_headers(endpoint, None, None, Method::GET)?;
+        };
+        let resp: APIDatum<Pagination<FillsInfo>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    /// Every fill matching `optionals`, fetched page by page as the stream is read.
+    /// `current_page` and `page_size` in `optionals` are ignored, see `Paging`.
+    pub fn get_fills_stream<'a>(
+        &'a self,
+        optionals: FillsOptionals<'a>,
+        paging: Paging,
+    ) -> BoxStream<'a, Result<FillsInfo, APIError>> {
+        let (start_at, end_at) = (optionals.start_at, optionals.end_at);
+        let now_ms = self.time_sync().now_ms();
+        paginate_range(
+            start_at,
+            end_at,
+            paging,
+            now_ms,
+            move |start_at, end_at, page| {
+                let mut opts = optionals.clone();
+                opts.start_at = start_at;
+                opts.end_at = end_at;
+                opts.current_page = Some(page);
+                opts.page_size = Some(paging.page_size);
+                self.get_fills(Some(opts))
+            },
+        )
+    }
+
+    /// Places a stop limit order, sent once the last price crosses `stop_price`. The stop is a
+    /// `loss` by default, see `OrderOptionals::stop`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn post_stop_limit_order(
+        &self,
+        client_oid: &str,
+        symbol: &str,
+        side: Side,
+        stop_price: &str,
+        price: &str,
+        size: &str,
+        optionals: Option<OrderOptionals<'_>>,
+    ) -> Result<OrderResp, APIError> {
+        let endpoint = String::from("/api/v1/stop-order");
+        let url = format!("{}{}", &self.prefix, endpoint);
+        let mut params: HashMap<String, String> = HashMap::new();
+        if let Some(opt) = optionals {
+            let opts = parse_order(opt)?;
+            params.extend(opts);
+        };
+        params.insert(String::from("clientOid"), client_oid.to_string());
+        params.insert(String::from("symbol"), symbol.to_string());
+        params.insert(String::from("side"), side.to_string());
+        params.insert(String::from("type"), String::from("limit"));
+        params.insert(String::from("stopPrice"), stop_price.to_string());
+        params.insert(String::from("price"), price.to_string());
+        params.insert(String::from("size"), size.to_string());
+        let headers: header::HeaderMap =
+            self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<OrderResp> = self
+            .post(url, Some(headers), Some(params))
+            .await?
+            .json()
+            .await?;
+        resp.into_result()
+    }
+
+    /// Places a stop market order, sent once the last price crosses `stop_price`. As with
+    /// `post_market_order`, give either the size or the funds.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn post_stop_market_order(
+        &self,
+        client_oid: &str,
+        symbol: &str,
+        side: Side,
+        stop_price: &str,
+        size: Option<&str>,
+        funds: Option<&str>,
+        optionals: Option<OrderOptionals<'_>>,
+    ) -> Result<OrderResp, APIError> {
+        let endpoint = String::from("/api/v1/stop-order");
+        let url = format!("{}{}", &self.prefix, endpoint);
+        let mut params: HashMap<String, String> = HashMap::new();
+        if let Some(opt) = optionals {
+            let opts = parse_order(opt)?;
+            params.extend(opts);
+        };
+        params.insert(String::from("clientOid"), client_oid.to_string());
+        params.insert(String::from("symbol"), symbol.to_string());
+        params.insert(String::from("side"), side.to_string());
+        params.insert(String::from("type"), String::from("market"));
+        params.insert(String::from("stopPrice"), stop_price.to_string());
+        if let Some(s) = size {
+            params.insert(String::from("size"), s.to_string());
+        };
+        if let Some(f) = funds {
+            params.insert(String::from("funds"), f.to_string());
+        };
+        let headers: header::HeaderMap =
+            self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<OrderResp> = self
+            .post(url, Some(headers), Some(params))
+            .await?
+            .json()
+            .await?;
+        resp.into_result()
+    }
+
+    pub async fn cancel_stop_order(&self, order_id: &str) -> Result<CancelResp, APIError> {
+        let endpoint = format!("/api/v1/stop-order/{}", order_id);
+        let url = format!("{}{}", &self.prefix, endpoint);
+        let headers: header::HeaderMap = self.sign_headers(endpoint, None, None, Method::DELETE)?;
+        let resp: APIDatum<CancelResp> = self.delete(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    pub async fn cancel_stop_order_by_client_oid(
+        &self,
+        client_oid: &str,
+        symbol: Option<&str>,
+    ) -> Result<CancelByClientOidResp, APIError> {
+        let endpoint = String::from("/api/v1/stop-order/cancelOrderByClientOid");
+        let mut params: HashMap<String, String> = HashMap::new();
+        params.insert(String::from("clientOid"), client_oid.to_string());
+        if let Some(s) = symbol {
+            params.insert(String::from("symbol"), s.to_string());
+        };
+        let query = format_query(&params);
+        let url = format!("{}{}{}", &self.prefix, endpoint, query);
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::DELETE)?;
+        let resp: APIDatum<CancelByClientOidResp> =
+            self.delete(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    /// Cancels the untriggered stop orders of a symbol (optional), trade type (optional) or with
+    /// the given ids (optional), all of them when no filter is given.
+    pub async fn cancel_all_stop_orders(
+        &self,
+        symbol: Option<&str>,
+        trade_type: Option<TradeType>,
+        order_ids: Option<&[&str]>,
+    ) -> Result<CancelResp, APIError> {
+        let endpoint = String::from("/api/v1/stop-order/cancel");
+        let url: String;
+        let headers: header::HeaderMap;
+        let mut params: HashMap<String, String> = HashMap::new();
+        if let Some(s) = symbol {
+            params.insert(String::from("symbol"), s.to_string());
+        };
+        if let Some(t) = trade_type {
+            params.insert(String::from("tradeType"), t.to_string());
+        };
+        if let Some(ids) = order_ids {
+            params.insert(String::from("orderIds"), ids.join(","));
+        };
+        if !params.is_empty() {
+            let query = format_query(&params);
+            url = format!("{}{}{}", &self.prefix, endpoint, query);
+            headers = self.sign_headers(endpoint, None, Some(query), Method::DELETE)?;
         } else {
             url = format!("{}{}", &self.prefix, endpoint);
-            headers = self
-                .sign_headers(endpoint, None, None, Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, None, Method::DELETE)?;
+        }
+        let resp: APIDatum<CancelResp> = self.delete(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    /// Lists the untriggered stop orders.
+    pub async fn get_stop_orders(
+        &self,
+        optionals: Option<StopOrderOptionals<'_>>,
+    ) -> Result<Pagination<StopOrderInfo>, APIError> {
+        let endpoint = String::from("/api/v1/stop-order");
+        let url: String;
+        let headers: header::HeaderMap;
+        let mut params: HashMap<String, String> = HashMap::new();
+        if let Some(opts) = optionals {
+            if let Some(o) = opts.symbol {
+                params.insert("symbol".to_string(), o.to_string());
+            };
+            if let Some(o) = opts.side {
+                params.insert("side".to_string(), o.to_string());
+            };
+            if let Some(o) = opts.r#type {
+                params.insert("type".to_string(), o.to_string());
+            };
+            if let Some(o) = opts.trade_type {
+                params.insert("tradeType".to_string(), o.to_string());
+            };
+            if let Some(o) = opts.order_ids {
+                params.insert("orderIds".to_string(), o.to_string());
+            };
+            if let Some(o) = opts.start_at {
+                params.insert("startAt".to_string(), o.to_string());
+            };
+            if let Some(o) = opts.end_at {
+                params.insert("endAt".to_string(), o.to_string());
+            };
+            if let Some(o) = opts.current_page {
+                params.insert("currentPage".to_string(), o.to_string());
+            };
+            if let Some(o) = opts.page_size {
+                params.insert("pageSize".to_string(), o.to_string());
+            };
         };
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        if !params.is_empty() {
+            let query = format_query(&params);
+            url = format!("{}{}{}", &self.prefix, endpoint, query);
+            headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        } else {
+            url = format!("{}{}", &self.prefix, endpoint);
+            headers = self.sign_headers(endpoint, None, None, Method::GET)?;
+        }
+        let resp: APIDatum<Pagination<StopOrderInfo>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    /// Every untriggered stop order matching `optionals`, fetched page by page as the stream is
+    /// read. `current_page` and `page_size` in `optionals` are ignored, see `Paging`.
+    pub fn get_stop_orders_stream<'a>(
+        &'a self,
+        optionals: StopOrderOptionals<'a>,
+        paging: Paging,
+    ) -> BoxStream<'a, Result<StopOrderInfo, APIError>> {
+        let (start_at, end_at) = (optionals.start_at, optionals.end_at);
+        let now_ms = self.time_sync().now_ms();
+        paginate_range(
+            start_at,
+            end_at,
+            paging,
+            now_ms,
+            move |start_at, end_at, page| {
+                let mut opts = optionals.clone();
+                opts.start_at = start_at;
+                opts.end_at = end_at;
+                opts.current_page = Some(page);
+                opts.page_size = Some(paging.page_size);
+                self.get_stop_orders(Some(opts))
+            },
+        )
     }
 
-    pub async fn get_recent_fills(&self) -> Result<APIData<FillsInfo>, APIError> {
+    pub async fn get_stop_order(&self, order_id: &str) -> Result<StopOrderInfo, APIError> {
+        let endpoint = format!("/api/v1/stop-order/{}", order_id);
+        let url = format!("{}{}", &self.prefix, endpoint);
+        let headers: header::HeaderMap = self.sign_headers(endpoint, None, None, Method::GET)?;
+        let resp: APIDatum<StopOrderInfo> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    pub async fn get_stop_order_by_client_oid(
+        &self,
+        client_oid: &str,
+        symbol: Option<&str>,
+    ) -> Result<Vec<StopOrderInfo>, APIError> {
+        let endpoint = String::from("/api/v1/stop-order/queryOrderByClientOid");
+        let mut params: HashMap<String, String> = HashMap::new();
+        params.insert(String::from("clientOid"), client_oid.to_string());
+        if let Some(s) = symbol {
+            params.insert(String::from("symbol"), s.to_string());
+        };
+        let query = format_query(&params);
+        let url = format!("{}{}{}", &self.prefix, endpoint, query);
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIData<StopOrderInfo> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    pub async fn get_recent_fills(&self) -> Result<Vec<FillsInfo>, APIError> {
         let endpoint = String::from("/api/v1/limit/fills");
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, None, Method::GET)?;
+        let resp: APIData<FillsInfo> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 }
 
-fn parse_order(optionals: OrderOptionals) -> HashMap<String, String> {
+fn parse_order(optionals: OrderOptionals) -> Result<HashMap<String, String>, APIError> {
+    optionals.validate()?;
     let mut params: HashMap<String, String> = HashMap::new();
 
     if let Some(o) = optionals.remark {
@@ -340,6 +658,12 @@ fn parse_order(optionals: OrderOptionals) -> HashMap<String, String> {
     if let Some(o) = optionals.stop_price {
         params.insert(String::from("stopPrice"), o.to_string());
     };
+    if let Some(o) = optionals.stp {
+        params.insert(String::from("stp"), o.to_string());
+    };
+    if let Some(o) = optionals.trade_type {
+        params.insert(String::from("tradeType"), o.to_string());
+    };
     if let Some(o) = optionals.time_in_force {
         params.insert(String::from("timeInForce"), o.to_string());
     };
@@ -359,31 +683,35 @@ fn parse_order(optionals: OrderOptionals) -> HashMap<String, String> {
         params.insert(String::from("visibleSize"), o.to_string());
     };
 
-    params
+    Ok(params)
 }
 
 /// OrderOptionals contains a builder pattern that can be used to more easily take advantage of optional inputs.
 ///
 /// Example:
 /// ``` rust
+/// use kucoin_rs_custom::kucoin::model::trade::Stp;
 /// use kucoin_rs_custom::kucoin::trade::OrderOptionals;
 ///
 ///     let options = OrderOptionals::new()
 ///         .remark("Example of OrderOptionals builder pattern")
-///         .stp("CO")
+///         .stp(Stp::CancelOldest)
 ///         .hidden(true)
-///         .build();
+///         .build()
+///         .unwrap();
 /// ```
 ///
+/// `build` checks the combinations Kucoin would reject anyway, see `validate`.
+///
 /// See the Kucoin documentation for full list of options relative to market and limit orders.
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct OrderOptionals<'a> {
     pub remark: Option<&'a str>,
-    pub stop: Option<&'a str>,
+    pub stop: Option<StopKind>,
     pub stop_price: Option<&'a str>,
-    pub stp: Option<&'a str>,
-    pub trade_type: Option<&'a str>,
-    pub time_in_force: Option<&'a str>,
+    pub stp: Option<Stp>,
+    pub trade_type: Option<TradeType>,
+    pub time_in_force: Option<TimeInForce>,
     pub cancel_after: Option<i64>,
     pub post_only: Option<bool>,
     pub hidden: Option<bool>,
@@ -415,7 +743,7 @@ impl<'a> OrderOptionals<'a> {
         self
     }
 
-    pub fn stop(&mut self, s: &'a str) -> &mut Self {
+    pub fn stop(&mut self, s: StopKind) -> &mut Self {
         self.stop = Some(s);
         self
     }
@@ -425,17 +753,17 @@ impl<'a> OrderOptionals<'a> {
         self
     }
 
-    pub fn stp(&mut self, s: &'a str) -> &mut Self {
+    pub fn stp(&mut self, s: Stp) -> &mut Self {
         self.stp = Some(s);
         self
     }
 
-    pub fn time_in_force(&mut self, t: &'a str) -> &mut Self {
+    pub fn time_in_force(&mut self, t: TimeInForce) -> &mut Self {
         self.time_in_force = Some(t);
         self
     }
 
-    pub fn trade_type(&mut self, t: &'a str) -> &mut Self {
+    pub fn trade_type(&mut self, t: TradeType) -> &mut Self {
         self.trade_type = Some(t);
         self
     }
@@ -469,8 +797,9 @@ impl<'a> OrderOptionals<'a> {
     /// to be used with posting orders. Only contains optional inputs
     /// the post order functions require specific required inputs.
     /// See those functions' documentation for details.
-    pub fn build(&self) -> Self {
-        Self {
+    pub fn build(&self) -> Result<Self, APIError> {
+        self.validate()?;
+        Ok(Self {
             remark: self.remark,
             stop: self.stop,
             stop_price: self.stop_price,
@@ -482,30 +811,100 @@ impl<'a> OrderOptionals<'a> {
             hidden: self.hidden,
             iceberg: self.iceberg,
             visible_size: self.visible_size,
+        })
+    }
+
+    /// Fails on combinations Kucoin rejects: `post_only` with IOC or FOK, an order both
+    /// `hidden` and `iceberg`, or a `visible_size` on an order that isn't an iceberg.
+    pub fn validate(&self) -> Result<(), APIError> {
+        let immediate = matches!(
+            self.time_in_force,
+            Some(TimeInForce::Ioc) | Some(TimeInForce::Fok)
+        );
+        if self.post_only == Some(true) && immediate {
+            return Err(APIError::InvalidOrder(
+                "post_only can't be used with IOC or FOK".to_string(),
+            ));
+        }
+        if self.hidden == Some(true) && self.iceberg == Some(true) {
+            return Err(APIError::InvalidOrder(
+                "an order can't be both hidden and iceberg".to_string(),
+            ));
+        }
+        if self.visible_size.is_some() && self.iceberg != Some(true) {
+            return Err(APIError::InvalidOrder(
+                "visible_size is only used with iceberg".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Most orders Kucoin takes in one `post_multi_orders` request.
+pub const MAX_BATCH_ORDERS: usize = 5;
+
+/// One limit order of a `post_multi_orders` batch.
+///
+/// Example:
+/// ``` rust
+/// use kucoin_rs_custom::kucoin::model::trade::Side;
+/// use kucoin_rs_custom::kucoin::trade::{BatchOrder, OrderOptionals};
+///
+///     let order = BatchOrder::new("oid-1", Side::Sell, "0.052", "1.5")
+///         .optionals(OrderOptionals::new().remark("ladder").build().unwrap())
+///         .build();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchOrder<'a> {
+    pub client_oid: &'a str,
+    pub side: Side,
+    pub price: &'a str,
+    pub size: &'a str,
+    pub optionals: Option<OrderOptionals<'a>>,
+}
+
+impl<'a> BatchOrder<'a> {
+    pub fn new(client_oid: &'a str, side: Side, price: &'a str, size: &'a str) -> Self {
+        BatchOrder {
+            client_oid,
+            side,
+            price,
+            size,
+            optionals: None,
         }
     }
+
+    pub fn optionals(&mut self, o: OrderOptionals<'a>) -> &mut Self {
+        self.optionals = Some(o);
+        self
+    }
+
+    pub fn build(&self) -> Self {
+        self.clone()
+    }
 }
 
 /// OrderInfoOptionals contains a builder pattern that can be used to more easily take advantage of optional inputs.
 ///
 /// Example:
 /// ``` rust
+/// use kucoin_rs_custom::kucoin::model::trade::Side;
 /// use kucoin_rs_custom::kucoin::trade::OrderInfoOptionals;
 ///
 ///     let options = OrderInfoOptionals::new()
 ///         .symbol("BTC-USDT")
-///         .side("buy")
+///         .side(Side::Buy)
 ///         .build();
 /// ```
 ///
 /// See the Kucoin documentation for full list of options relative to market and limit orders.
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct OrderInfoOptionals<'a> {
-    pub status: Option<&'a str>,
+    pub status: Option<OrderStatus>,
     pub symbol: Option<&'a str>,
-    pub side: Option<&'a str>,
+    pub side: Option<Side>,
     pub r#type: Option<&'a str>,
-    pub trade_type: Option<&'a str>,
+    pub trade_type: Option<TradeType>,
     pub start_at: Option<i64>,
     pub end_at: Option<i64>,
     pub current_page: Option<i32>,
@@ -527,7 +926,7 @@ impl<'a> OrderInfoOptionals<'a> {
         }
     }
 
-    pub fn status(&mut self, s: &'a str) -> &mut Self {
+    pub fn status(&mut self, s: OrderStatus) -> &mut Self {
         self.status = Some(s);
         self
     }
@@ -537,7 +936,7 @@ impl<'a> OrderInfoOptionals<'a> {
         self
     }
 
-    pub fn side(&mut self, s: &'a str) -> &mut Self {
+    pub fn side(&mut self, s: Side) -> &mut Self {
         self.side = Some(s);
         self
     }
@@ -547,7 +946,7 @@ impl<'a> OrderInfoOptionals<'a> {
         self
     }
 
-    pub fn trade_type(&mut self, s: &'a str) -> &mut Self {
+    pub fn trade_type(&mut self, s: TradeType) -> &mut Self {
         self.trade_type = Some(s);
         self
     }
@@ -591,14 +990,97 @@ impl<'a> OrderInfoOptionals<'a> {
     }
 }
 
+/// StopOrderOptionals contains a builder pattern that can be used to more easily take advantage of optional inputs.
+///
+/// Example:
+/// ``` rust
+/// use kucoin_rs_custom::kucoin::model::trade::Side;
+/// use kucoin_rs_custom::kucoin::trade::StopOrderOptionals;
+///     let options = StopOrderOptionals::new()
+///         .symbol("BTC-USDT")
+///         .side(Side::Sell)
+///         .build();
+/// ```
+///
+/// See the Kucoin documentation for full list of options relative to stop orders.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StopOrderOptionals<'a> {
+    pub symbol: Option<&'a str>,
+    pub side: Option<Side>,
+    pub r#type: Option<&'a str>,
+    pub trade_type: Option<TradeType>,
+    /// Comma separated order ids.
+    pub order_ids: Option<&'a str>,
+    pub start_at: Option<i64>,
+    pub end_at: Option<i64>,
+    pub current_page: Option<i32>,
+    pub page_size: Option<i32>,
+}
+
+impl<'a> StopOrderOptionals<'a> {
+    pub fn new() -> Self {
+        StopOrderOptionals::default()
+    }
+
+    pub fn symbol(&mut self, s: &'a str) -> &mut Self {
+        self.symbol = Some(s);
+        self
+    }
+
+    pub fn side(&mut self, s: Side) -> &mut Self {
+        self.side = Some(s);
+        self
+    }
+
+    pub fn order_type(&mut self, s: &'a str) -> &mut Self {
+        self.r#type = Some(s);
+        self
+    }
+
+    pub fn trade_type(&mut self, s: TradeType) -> &mut Self {
+        self.trade_type = Some(s);
+        self
+    }
+
+    pub fn order_ids(&mut self, s: &'a str) -> &mut Self {
+        self.order_ids = Some(s);
+        self
+    }
+
+    pub fn start_at(&mut self, i: i64) -> &mut Self {
+        self.start_at = Some(i);
+        self
+    }
+
+    pub fn end_at(&mut self, i: i64) -> &mut Self {
+        self.end_at = Some(i);
+        self
+    }
+
+    pub fn current_page(&mut self, i: i32) -> &mut Self {
+        self.current_page = Some(i);
+        self
+    }
+
+    pub fn page_size(&mut self, i: i32) -> &mut Self {
+        self.page_size = Some(i);
+        self
+    }
+
+    pub fn build(&self) -> Self {
+        self.clone()
+    }
+}
+
 /// FillsOptionals contains a builder pattern that can be used to more easily take advantage of optional inputs.
 ///
 /// Example:
 /// ``` rust
+/// use kucoin_rs_custom::kucoin::model::trade::Side;
 /// use kucoin_rs_custom::kucoin::trade::FillsOptionals;
 ///     let options = FillsOptionals::new()
 ///         .symbol("BTC-USDT")
-///         .side("buy")
+///         .side(Side::Buy)
 ///         .build();
 /// ```
 ///
@@ -607,11 +1089,11 @@ impl<'a> OrderInfoOptionals<'a> {
 pub struct FillsOptionals<'a> {
     pub order_id: Option<&'a str>,
     pub symbol: Option<&'a str>,
-    pub side: Option<&'a str>,
+    pub side: Option<Side>,
     pub r#type: Option<&'a str>,
     pub start_at: Option<i64>,
     pub end_at: Option<i64>,
-    pub trade_type: Option<&'a str>,
+    pub trade_type: Option<TradeType>,
     pub current_page: Option<i32>,
     pub page_size: Option<i32>,
 }
@@ -641,7 +1123,7 @@ impl<'a> FillsOptionals<'a> {
         self
     }
 
-    pub fn side(&mut self, s: &'a str) -> &mut Self {
+    pub fn side(&mut self, s: Side) -> &mut Self {
         self.side = Some(s);
         self
     }
@@ -651,7 +1133,7 @@ impl<'a> FillsOptionals<'a> {
         self
     }
 
-    pub fn trade_type(&mut self, s: &'a str) -> &mut Self {
+    pub fn trade_type(&mut self, s: TradeType) -> &mut Self {
         self.trade_type = Some(s);
         self
     }
@@ -697,36 +1179,52 @@ impl<'a> FillsOptionals<'a> {
 
 #[cfg(test)]
 mod test {
-    use crate::kucoin::trade::{FillsOptionals, OrderInfoOptionals, OrderOptionals};
+    use std::sync::Arc;
+
+    use futures::TryStreamExt;
+    use reqwest::Method;
+
+    use crate::kucoin::error::{APIError, KucoinCode};
+    use crate::kucoin::fixture::{Fixture, Interaction, ReplayTransport};
+    use crate::kucoin::model::trade::{OrderStatus, Side, StopKind, Stp, TimeInForce, TradeType};
+    use crate::kucoin::paginate::Paging;
+    use crate::kucoin::trade::{
+        BatchOrder, FillsOptionals, OrderInfoOptionals, OrderOptionals, StopOrderOptionals,
+    };
+    use crate::kucoin::transport::test::{expected_sign, mock_client};
+    use crate::kucoin::transport::MockTransport;
+
+    const ORDER: &str = r#"{"id":"5c35c02703aa673ceec2a168","symbol":"BTC-USDT","opType":"DEAL","type":"limit","side":"buy","price":"10","size":"2","funds":"0","dealFunds":"0.166","dealSize":"2","fee":"0","feeCurrency":"USDT","stp":"","stop":"","stopTriggered":false,"stopPrice":"0","timeInForce":"GTC","postOnly":false,"hidden":false,"iceberg":false,"visibleSize":"0","cancelAfter":0,"channel":"IOS","clientOid":"","remark":"","tags":"","isActive":false,"cancelExist":false,"createdAt":1547026471000,"tradeType":"TRADE"}"#;
     #[test]
     fn use_build_pattern_all_order_optionals() {
         let options = OrderOptionals {
             remark: Some("Test build pattern"),
-            stop: Some("loss"),
+            stop: Some(StopKind::Loss),
             stop_price: Some("12.321"),
-            stp: Some("CO"),
-            time_in_force: Some("GTT"),
-            trade_type: Some("TRADE"),
+            stp: Some(Stp::CancelOldest),
+            time_in_force: Some(TimeInForce::Gtt),
+            trade_type: Some(TradeType::Trade),
             cancel_after: Some(1_231_231_321_321),
             post_only: Some(true),
-            hidden: Some(true),
-            iceberg: Some(false),
+            hidden: Some(false),
+            iceberg: Some(true),
             visible_size: Some("1.23"),
         };
 
         let builder_options = OrderOptionals::new()
             .remark("Test build pattern")
-            .stop("loss")
+            .stop(StopKind::Loss)
             .stop_price("12.321")
-            .stp("CO")
-            .time_in_force("GTT")
-            .trade_type("TRADE")
+            .stp(Stp::CancelOldest)
+            .time_in_force(TimeInForce::Gtt)
+            .trade_type(TradeType::Trade)
             .cancel_after(1_231_231_321_321)
             .post_only(true)
-            .hidden(true)
-            .iceberg(false)
+            .hidden(false)
+            .iceberg(true)
             .visible_size("1.23")
-            .build();
+            .build()
+            .unwrap();
 
         assert_eq!(builder_options, options)
     }
@@ -737,9 +1235,9 @@ mod test {
             remark: Some("Test build pattern"),
             stop: None,
             stop_price: None,
-            stp: Some("CO"),
-            time_in_force: Some("GTT"),
-            trade_type: Some("TRADE"),
+            stp: Some(Stp::CancelOldest),
+            time_in_force: Some(TimeInForce::Gtt),
+            trade_type: Some(TradeType::Trade),
             cancel_after: Some(1_231_231_321_321),
             post_only: Some(true),
             hidden: None,
@@ -749,12 +1247,13 @@ mod test {
 
         let builder_options = OrderOptionals::new()
             .remark("Test build pattern")
-            .stp("CO")
-            .time_in_force("GTT")
-            .trade_type("TRADE")
+            .stp(Stp::CancelOldest)
+            .time_in_force(TimeInForce::Gtt)
+            .trade_type(TradeType::Trade)
             .cancel_after(1_231_231_321_321)
             .post_only(true)
-            .build();
+            .build()
+            .unwrap();
 
         assert_eq!(builder_options, options)
     }
@@ -762,11 +1261,11 @@ mod test {
     #[test]
     fn use_build_pattern_all_order_info_optionals() {
         let options = OrderInfoOptionals {
-            status: Some("active"),
+            status: Some(OrderStatus::Active),
             symbol: Some("BTC-USDT"),
-            side: Some("buy"),
+            side: Some(Side::Buy),
             r#type: Some("limit"),
-            trade_type: Some("TRADE"),
+            trade_type: Some(TradeType::Trade),
             start_at: Some(1_580_683_419_725),
             end_at: Some(1_580_683_800_000),
             current_page: Some(1),
@@ -774,11 +1273,11 @@ mod test {
         };
 
         let build_options = OrderInfoOptionals::new()
-            .status("active")
+            .status(OrderStatus::Active)
             .symbol("BTC-USDT")
-            .side("buy")
+            .side(Side::Buy)
             .order_type("limit")
-            .trade_type("TRADE")
+            .trade_type(TradeType::Trade)
             .start_at(1_580_683_419_725)
             .end_at(1_580_683_800_000)
             .current_page(1)
@@ -816,9 +1315,9 @@ mod test {
         let options = FillsOptionals {
             order_id: Some("asdasd-sadasda-asxsaxs"),
             symbol: Some("BTC-USDT"),
-            side: Some("buy"),
+            side: Some(Side::Buy),
             r#type: Some("limit"),
-            trade_type: Some("TRADE"),
+            trade_type: Some(TradeType::Trade),
             start_at: Some(1_580_683_419_725),
             end_at: Some(1_580_683_800_000),
             current_page: Some(1),
@@ -828,9 +1327,9 @@ mod test {
         let build_options = FillsOptionals::new()
             .order_id("asdasd-sadasda-asxsaxs")
             .symbol("BTC-USDT")
-            .side("buy")
+            .side(Side::Buy)
             .order_type("limit")
-            .trade_type("TRADE")
+            .trade_type(TradeType::Trade)
             .start_at(1_580_683_419_725)
             .end_at(1_580_683_800_000)
             .current_page(1)
@@ -862,4 +1361,380 @@ mod test {
 
         assert_eq!(options, build_options)
     }
+
+    #[tokio::test]
+    async fn posts_a_signed_limit_order() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::POST,
+            "/api/v1/orders",
+            r#"{"code":"200000","data":{"orderId":"5bd6e9286d99522a52e458de"}}"#,
+        );
+        let options = OrderOptionals::new()
+            .time_in_force(TimeInForce::Ioc)
+            .build()
+            .unwrap();
+        let resp = mock_client(&mock)
+            .post_limit_order("oid-1", "BTC-USDT", Side::Buy, "10", "2", Some(options))
+            .await
+            .unwrap();
+        assert_eq!(resp.order_id, "5bd6e9286d99522a52e458de");
+        let request = &mock.requests()[0];
+        assert!(request.is_signed());
+        let body = request.json();
+        assert_eq!(body["clientOid"], "oid-1");
+        assert_eq!(body["price"], "10");
+        assert_eq!(body["timeInForce"], "IOC");
+        assert_eq!(body["side"], "buy");
+    }
+
+    #[tokio::test]
+    async fn rejects_conflicting_options_before_sending() {
+        let post_only_ioc = OrderOptionals::new()
+            .post_only(true)
+            .time_in_force(TimeInForce::Ioc)
+            .build();
+        assert!(matches!(post_only_ioc, Err(APIError::InvalidOrder(_))));
+        let hidden_iceberg = OrderOptionals::new().hidden(true).iceberg(true).build();
+        assert!(matches!(hidden_iceberg, Err(APIError::InvalidOrder(_))));
+        let visible_only = OrderOptionals::new().visible_size("1").build();
+        assert!(matches!(visible_only, Err(APIError::InvalidOrder(_))));
+        let iceberg = OrderOptionals::new()
+            .post_only(true)
+            .time_in_force(TimeInForce::Gtc)
+            .iceberg(true)
+            .visible_size("1")
+            .build();
+        assert!(iceberg.is_ok());
+
+        let mock = MockTransport::new();
+        let options = OrderOptionals {
+            time_in_force: Some(TimeInForce::Fok),
+            post_only: Some(true),
+            ..OrderOptionals::default()
+        };
+        let err = mock_client(&mock)
+            .post_limit_order("oid-1", "BTC-USDT", Side::Buy, "10", "2", Some(options))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, APIError::InvalidOrder(_)));
+        assert!(!err.is_retryable());
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn posts_a_batch_with_results_per_order() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::POST,
+            "/api/v1/orders/multi",
+            r#"{"code":"200000","data":{"data":[
+                {"symbol":"ETH-BTC","type":"limit","side":"sell","price":"0.06","size":"1","stp":"","stop":"","timeInForce":"GTC","channel":"API","id":"611a6a7b","status":"success","failMsg":null,"clientOid":"oid-1"},
+                {"symbol":"ETH-BTC","type":"limit","side":"sell","price":"0.07","size":"1","status":"fail","failMsg":"Balance insufficient!","clientOid":"oid-2"}
+            ]}}"#,
+        );
+        let client = mock_client(&mock);
+        let orders = [
+            BatchOrder::new("oid-1", Side::Sell, "0.06", "1"),
+            BatchOrder::new("oid-2", Side::Sell, "0.07", "1")
+                .optionals(
+                    OrderOptionals::new()
+                        .time_in_force(TimeInForce::Gtc)
+                        .build()
+                        .unwrap(),
+                )
+                .build(),
+        ];
+        let results = client.post_multi_orders("ETH-BTC", &orders).await.unwrap();
+        assert!(results[0].is_success());
+        assert_eq!(results[0].id.as_deref(), Some("611a6a7b"));
+        assert!(!results[1].is_success());
+        assert_eq!(
+            results[1].fail_msg.as_deref(),
+            Some("Balance insufficient!")
+        );
+        assert!(results[0].error().is_none());
+        let err = results[1].error().unwrap();
+        assert_eq!(err.code(), Some(&KucoinCode::InsufficientBalance));
+        assert!(!err.is_retryable());
+
+        let request = &mock.requests()[0];
+        assert_eq!(
+            request.headers["kc-api-sign"],
+            expected_sign(request).as_str()
+        );
+        let body = request.json();
+        assert_eq!(body["symbol"], "ETH-BTC");
+        assert_eq!(body["orderList"][0]["clientOid"], "oid-1");
+        assert_eq!(body["orderList"][1]["type"], "limit");
+        assert_eq!(body["orderList"][1]["timeInForce"], "GTC");
+    }
+
+    const STOP_ORDER: &str = r#"{"id":"vs8hoo8q2ceshiue003b67c0","symbol":"KCS-USDT","userId":"60fe4956c43cbc0006562c2c","status":"NEW","type":"limit","side":"buy","price":"0.01000000000000000000","size":"0.01000000000000000000","funds":null,"stp":null,"timeInForce":"GTC","cancelAfter":-1,"postOnly":false,"hidden":false,"iceberg":false,"visibleSize":null,"channel":"API","clientOid":"40e0eb9efe6311eb8e58acde48001122","remark":null,"tags":null,"orderTime":1629098781127530345,"domainId":"kucoin","tradeSource":"USER","tradeType":"TRADE","feeCurrency":"USDT","takerFeeRate":"0.00200000000000000000","makerFeeRate":"0.00200000000000000000","createdAt":1629098781128,"stop":"loss","stopTriggerTime":null,"stopPrice":"10.00000000000000000000"}"#;
+
+    #[tokio::test]
+    async fn manages_stop_orders() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::POST,
+            "/api/v1/stop-order",
+            r#"{"code":"200000","data":{"orderId":"vs8hoo8q2ceshiue003b67c0"}}"#,
+        )
+        .on(
+            Method::GET,
+            "/api/v1/stop-order",
+            &format!(
+                r#"{{"code":"200000","data":{{"currentPage":1,"pageSize":50,"totalNum":1,"totalPage":1,"items":[{}]}}}}"#,
+                STOP_ORDER
+            ),
+        )
+        .on(
+            Method::GET,
+            "/api/v1/stop-order/vs8hoo8q2ceshiue003b67c0",
+            &format!(r#"{{"code":"200000","data":{}}}"#, STOP_ORDER),
+        )
+        .on(
+            Method::GET,
+            "/api/v1/stop-order/queryOrderByClientOid",
+            &format!(r#"{{"code":"200000","data":[{}]}}"#, STOP_ORDER),
+        )
+        .on(
+            Method::DELETE,
+            "/api/v1/stop-order/vs8hoo8q2ceshiue003b67c0",
+            r#"{"code":"200000","data":{"cancelledOrderIds":["vs8hoo8q2ceshiue003b67c0"]}}"#,
+        )
+        .on(
+            Method::DELETE,
+            "/api/v1/stop-order/cancel",
+            r#"{"code":"200000","data":{"cancelledOrderIds":[]}}"#,
+        );
+        let client = mock_client(&mock);
+        let placed = client
+            .post_stop_limit_order(
+                "40e0eb9efe6311eb8e58acde48001122",
+                "KCS-USDT",
+                Side::Buy,
+                "10",
+                "0.01",
+                "0.01",
+                Some(OrderOptionals::new().stop(StopKind::Loss).build().unwrap()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(placed.order_id, "vs8hoo8q2ceshiue003b67c0");
+        let listed = client
+            .get_stop_orders(Some(StopOrderOptionals::new().symbol("KCS-USDT").build()))
+            .await
+            .unwrap();
+        assert_eq!(listed.items[0].status, "NEW");
+        let order = client
+            .get_stop_order("vs8hoo8q2ceshiue003b67c0")
+            .await
+            .unwrap();
+        assert_eq!(order.stop_price, "10.00000000000000000000");
+        assert_eq!(order.funds, None);
+        let by_oid = client
+            .get_stop_order_by_client_oid("40e0eb9efe6311eb8e58acde48001122", Some("KCS-USDT"))
+            .await
+            .unwrap();
+        assert_eq!(by_oid[0].id, "vs8hoo8q2ceshiue003b67c0");
+        let cancelled = client
+            .cancel_stop_order("vs8hoo8q2ceshiue003b67c0")
+            .await
+            .unwrap();
+        assert_eq!(cancelled.cancelled_order_ids.len(), 1);
+        client
+            .cancel_all_stop_orders(Some("KCS-USDT"), None, Some(&["a", "b"]))
+            .await
+            .unwrap();
+
+        let requests = mock.requests();
+        let body = requests[0].json();
+        assert_eq!(body["type"], "limit");
+        assert_eq!(body["stop"], "loss");
+        assert_eq!(body["stopPrice"], "10");
+        assert_eq!(
+            requests[3].query.as_deref(),
+            Some("clientOid=40e0eb9efe6311eb8e58acde48001122&symbol=KCS-USDT")
+        );
+        assert_eq!(
+            requests[5].query.as_deref(),
+            Some("orderIds=a%2Cb&symbol=KCS-USDT")
+        );
+        for request in &requests {
+            assert_eq!(
+                request.headers["kc-api-sign"],
+                expected_sign(request).as_str()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn cancels_an_order() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::DELETE,
+            "/api/v1/orders/5bd6e9286d99522a52e458de",
+            r#"{"code":"200000","data":{"cancelledOrderIds":["5bd6e9286d99522a52e458de"]}}"#,
+        );
+        let resp = mock_client(&mock)
+            .cancel_order("5bd6e9286d99522a52e458de")
+            .await
+            .unwrap();
+        assert_eq!(resp.cancelled_order_ids.len(), 1);
+        assert_eq!(mock.requests()[0].method, Method::DELETE);
+    }
+
+    #[tokio::test]
+    async fn lists_and_gets_orders() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/orders",
+            &format!(
+                r#"{{"code":"200000","data":{{"currentPage":1,"pageSize":50,"totalNum":1,"totalPage":1,"items":[{}]}}}}"#,
+                ORDER
+            ),
+        )
+        .on(
+            Method::GET,
+            "/api/v1/orders/5c35c02703aa673ceec2a168",
+            &format!(r#"{{"code":"200000","data":{}}}"#, ORDER),
+        );
+        let client = mock_client(&mock);
+        let options = OrderInfoOptionals::new()
+            .status(OrderStatus::Active)
+            .symbol("BTC-USDT")
+            .build();
+        let page = client.get_orders(Some(options)).await.unwrap();
+        assert_eq!(page.items[0].deal_size, "2");
+        let order = client.get_order("5c35c02703aa673ceec2a168").await.unwrap();
+        assert_eq!(order.created_at, 1547026471000);
+        let query = mock.requests()[0].query.clone().unwrap();
+        assert!(query.contains("status=active") && query.contains("symbol=BTC-USDT"));
+    }
+
+    #[tokio::test]
+    async fn looks_orders_up_by_client_oid() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/order/client-order/oid-1",
+            &format!(r#"{{"code":"200000","data":{}}}"#, ORDER),
+        )
+        .on(
+            Method::GET,
+            "/api/v1/order/client-order/oid-2",
+            r#"{"code":"200000","data":null}"#,
+        );
+        let client = mock_client(&mock);
+        let order = client.get_order_by_client_oid("oid-1").await.unwrap();
+        assert_eq!(order.unwrap().id, "5c35c02703aa673ceec2a168");
+        assert!(client
+            .get_order_by_client_oid("oid-2")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(mock.requests()[0].is_signed());
+    }
+
+    #[tokio::test]
+    async fn streams_every_page() {
+        let page = |n: i32| {
+            Interaction {
+            method: "GET".to_string(),
+            path: "/api/v1/orders".to_string(),
+            query: Some(format!("currentPage={}&pageSize=1&symbol=BTC-USDT", n)),
+            request: None,
+            status: 200,
+            response: serde_json::from_str(&format!(
+                r#"{{"code":"200000","data":{{"currentPage":{},"pageSize":1,"totalNum":2,"totalPage":2,"items":[{}]}}}}"#,
+                n, ORDER
+            ))
+            .unwrap(),
+        }
+        };
+        let replay = ReplayTransport::new(Fixture {
+            interactions: vec![page(1), page(2)],
+        });
+        let mut client = mock_client(&MockTransport::new());
+        client.set_transport(Arc::new(replay.clone()));
+        let options = OrderInfoOptionals::new().symbol("BTC-USDT").build();
+        let paging = Paging::new().page_size(1).build();
+        let orders: Vec<_> = client
+            .get_orders_stream(options, paging)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(orders.len(), 2);
+        assert!(replay.is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn surfaces_kucoin_errors() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::POST,
+            "/api/v1/orders",
+            r#"{"code":"200004","msg":"Balance insufficient!"}"#,
+        );
+        let err = mock_client(&mock)
+            .post_market_order(
+                "oid-2",
+                "BTC-USDT",
+                Side::Buy,
+                None,
+                Some("5".to_string()),
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Some(&KucoinCode::InsufficientBalance));
+        assert_eq!(mock.requests()[0].json()["type"], "market");
+    }
+
+    #[tokio::test]
+    async fn signs_the_query_it_sends() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/orders",
+            r#"{"code":"200000","data":{"currentPage":1,"pageSize":50,"totalNum":0,"totalPage":0,"items":[]}}"#,
+        )
+        .on(
+            Method::DELETE,
+            "/api/v1/orders",
+            r#"{"code":"200000","data":{"cancelledOrderIds":[]}}"#,
+        );
+        let client = mock_client(&mock);
+        let options = OrderInfoOptionals::new()
+            .status(OrderStatus::Done)
+            .symbol("BTC-USDT")
+            .side(Side::Buy)
+            .start_at(1600000000000)
+            .end_at(1600086400000)
+            .current_page(2)
+            .page_size(50)
+            .build();
+        client.get_orders(Some(options)).await.unwrap();
+        client
+            .cancel_all_orders(Some("BTC-USDT"), Some(TradeType::Trade))
+            .await
+            .unwrap();
+        let requests = mock.requests();
+        assert_eq!(
+            requests[0].query.as_deref(),
+            Some("currentPage=2&endAt=1600086400000&pageSize=50&side=buy&startAt=1600000000000&status=done&symbol=BTC-USDT")
+        );
+        assert_eq!(
+            requests[1].query.as_deref(),
+            Some("symbol=BTC-USDT&tradeType=TRADE")
+        );
+        for request in &requests {
+            assert_eq!(
+                request.headers["kc-api-sign"],
+                expected_sign(request).as_str()
+            );
+        }
+    }
 }
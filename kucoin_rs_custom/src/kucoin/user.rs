@@ -1,3 +1,4 @@
+use futures::stream::BoxStream;
 use reqwest::header;
 use std::collections::HashMap;
 
@@ -9,24 +10,23 @@ use super::model::user::{
     WithdrawalId, WithdrawalList, WithdrawalListV1, WithdrawalQuotas,
 };
 use super::model::{APIData, APIDatum, Method, Pagination};
+use super::paginate::{paginate_range, Paging};
 use super::utils::format_query;
 
 impl Kucoin {
-    pub async fn get_user_subaccount_info(&self) -> Result<APIData<UserInfo>, APIError> {
+    pub async fn get_user_subaccount_info(&self) -> Result<Vec<UserInfo>, APIError> {
         let endpoint = String::from("/api/v1/sub/user");
         let url = format!("{}{}", &self.prefix, endpoint);
-        let header = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(header)).await?.json().await?;
-        Ok(resp)
+        let header = self.sign_headers(endpoint, None, None, Method::GET)?;
+        let resp: APIData<UserInfo> = self.get(url, Some(header)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn create_account(
         &self,
         account_type: AccountType,
         currency: &str,
-    ) -> Result<APIDatum<AccountId>, APIError> {
+    ) -> Result<AccountId, APIError> {
         let endpoint = String::from("/api/v1/accounts");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
@@ -36,22 +36,20 @@ impl Kucoin {
             AccountType::Trade => params.insert(String::from("type"), String::from("trade")),
         };
         params.insert(String::from("currency"), currency.to_string());
-        let header = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let header = self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<AccountId> = self
             .post(url, Some(header), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_result()
     }
 
     pub async fn get_accounts_list(
         &self,
         currency: Option<&str>,
         acct_type: Option<&str>,
-    ) -> Result<APIData<Accounts>, APIError> {
+    ) -> Result<Vec<Accounts>, APIError> {
         let mut params: HashMap<String, String> = HashMap::new();
         let headers: header::HeaderMap;
         let url: String;
@@ -65,27 +63,21 @@ impl Kucoin {
         if !params.is_empty() {
             let query = format_query(&params);
             url = format!("{}{}{}", &self.prefix, endpoint, query);
-            headers = self
-                .sign_headers(endpoint, None, Some(query), Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         } else {
             url = format!("{}{}", &self.prefix, endpoint);
-            headers = self
-                .sign_headers(endpoint, None, None, Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, None, Method::GET)?;
         }
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let resp: APIData<Accounts> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_account(&self, account_id: &str) -> Result<APIDatum<SingleAccount>, APIError> {
+    pub async fn get_account(&self, account_id: &str) -> Result<SingleAccount, APIError> {
         let endpoint = format!("/api/v1/accounts/{}", account_id);
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, None, Method::GET)?;
+        let resp: APIDatum<SingleAccount> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_account_ledgers(
@@ -95,7 +87,7 @@ impl Kucoin {
         end_at: Option<i64>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<AccountInfo>>, APIError> {
+    ) -> Result<Pagination<AccountInfo>, APIError> {
         let endpoint = format!("/api/v1/accounts/{}/ledgers", account_id);
         let url: String;
         let headers: header::HeaderMap;
@@ -115,17 +107,40 @@ impl Kucoin {
         if !params.is_empty() {
             let query = format_query(&params);
             url = format!("{}{}{}", &self.prefix, endpoint, query);
-            headers = self
-                .sign_headers(endpoint, None, Some(query), Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         } else {
             url = format!("{}{}", &self.prefix, endpoint);
-            headers = self
-                .sign_headers(endpoint, None, None, Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, None, Method::GET)?;
         }
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<Pagination<AccountInfo>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
+    }
+
+    /// Every ledger entry of `account_id`, fetched page by page as the stream is read.
+    pub fn get_account_ledgers_stream<'a>(
+        &'a self,
+        account_id: &'a str,
+        start_at: Option<i64>,
+        end_at: Option<i64>,
+        paging: Paging,
+    ) -> BoxStream<'a, Result<AccountInfo, APIError>> {
+        let now_ms = self.time_sync().now_ms();
+        paginate_range(
+            start_at,
+            end_at,
+            paging,
+            now_ms,
+            move |start_at, end_at, page| {
+                self.get_account_ledgers(
+                    account_id,
+                    start_at,
+                    end_at,
+                    Some(page),
+                    Some(paging.page_size),
+                )
+            },
+        )
     }
 
     pub async fn get_hold(
@@ -133,7 +148,7 @@ impl Kucoin {
         account_id: &str,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<AccountHolds>>, APIError> {
+    ) -> Result<Pagination<AccountHolds>, APIError> {
         let endpoint = format!("/api/v1/accounts/{}/holds", account_id);
         let url: String;
         let headers: header::HeaderMap;
@@ -147,61 +162,54 @@ impl Kucoin {
         if !params.is_empty() {
             let query = format_query(&params);
             url = format!("{}{}{}", &self.prefix, endpoint, query);
-            headers = self
-                .sign_headers(endpoint, None, Some(query), Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         } else {
             url = format!("{}{}", &self.prefix, endpoint);
-            headers = self
-                .sign_headers(endpoint, None, None, Method::GET)
-                .unwrap();
+            headers = self.sign_headers(endpoint, None, None, Method::GET)?;
         }
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let resp: APIDatum<Pagination<AccountHolds>> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_subaccount_balances(
         &self,
         account_id: &str,
-    ) -> Result<APIDatum<SubAccountBalances>, APIError> {
+    ) -> Result<SubAccountBalances, APIError> {
         let endpoint = format!("/api/v1/sub-accounts/{}", account_id);
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, None, Method::GET)?;
+        let resp: APIDatum<SubAccountBalances> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
-    pub async fn get_all_subaccount_balances(
-        &self,
-    ) -> Result<APIData<SubAccountBalances>, APIError> {
+    pub async fn get_all_subaccount_balances(&self) -> Result<Vec<SubAccountBalances>, APIError> {
         let endpoint = String::from("/api/v1/sub-accounts");
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let headers = self.sign_headers(endpoint, None, None, Method::GET)?;
+        let resp: APIData<SubAccountBalances> = self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     pub async fn get_transferable_balance(
         &self,
         currency: &str,
         account_type: AccountType,
-    ) -> Result<APIDatum<TransferableBalance>, APIError> {
-        let mut endpoint = format! {"/api/v1/accounts/transferable?currency={}", currency};
+    ) -> Result<TransferableBalance, APIError> {
+        let endpoint = String::from("/api/v1/accounts/transferable");
+        let mut params: HashMap<String, String> = HashMap::new();
+        params.insert(String::from("currency"), currency.to_string());
         match account_type {
-            AccountType::Main => endpoint.push_str("&type=MAIN"),
-            AccountType::Margin => endpoint.push_str("&type=MARGIN"),
-            AccountType::Trade => endpoint.push_str("&type=TRADE"),
+            AccountType::Main => params.insert(String::from("type"), String::from("MAIN")),
+            AccountType::Margin => params.insert(String::from("type"), String::from("MARGIN")),
+            AccountType::Trade => params.insert(String::from("type"), String::from("TRADE")),
         };
-        let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::GET)
-            .unwrap();
-        let resp = self.get(url, Some(headers)).await?.json().await?;
-        Ok(resp)
+        let query = format_query(&params);
+        let url = format!("{}{}{}", &self.prefix, endpoint, query);
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
+        let resp: APIDatum<TransferableBalance> =
+            self.get(url, Some(headers)).await?.json().await?;
+        resp.into_result()
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -214,7 +222,7 @@ impl Kucoin {
         sub_user_id: &str,
         account_type: Option<&str>,
         sub_account_type: Option<&str>,
-    ) -> Result<APIDatum<OrderId>, APIError> {
+    ) -> Result<OrderId, APIError> {
         let endpoint = String::from("/api/v2/accounts/sub-transfer");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
@@ -229,15 +237,13 @@ impl Kucoin {
         if let Some(s) = sub_account_type {
             params.insert(String::from("subAccountType"), s.to_string());
         }
-        let headers = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let headers = self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<OrderId> = self
             .post(url, Some(headers), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_result()
     }
 
     pub async fn inner_transfer(
@@ -247,7 +253,7 @@ impl Kucoin {
         from: &str,
         to: &str,
         amount: &str,
-    ) -> Result<APIDatum<OrderId>, APIError> {
+    ) -> Result<OrderId, APIError> {
         let endpoint = String::from("/api/v2/accounts/inner-transfer");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
@@ -256,22 +262,20 @@ impl Kucoin {
         params.insert(String::from("from"), from.to_string());
         params.insert(String::from("to"), to.to_string());
         params.insert(String::from("amount"), amount.to_string());
-        let headers = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let headers = self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<OrderId> = self
             .post(url, Some(headers), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_result()
     }
 
     pub async fn create_deposit_address(
         &self,
         currency: &str,
         chain: Option<&str>,
-    ) -> Result<APIDatum<DepositAddress>, APIError> {
+    ) -> Result<DepositAddress, APIError> {
         let endpoint = String::from("/api/v1/deposit-addresses");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
@@ -279,22 +283,20 @@ impl Kucoin {
         if let Some(c) = chain {
             params.insert(String::from("chain"), c.to_string());
         }
-        let headers = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
-        let resp = self
+        let headers = self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
+        let resp: APIDatum<DepositAddress> = self
             .post(url, Some(headers), Some(params))
             .await?
             .json()
             .await?;
-        Ok(resp)
+        resp.into_result()
     }
 
     pub async fn get_deposit_address(
         &self,
         currency: &str,
         chain: Option<&str>,
-    ) -> Result<APIDatum<DepositAddress>, APIError> {
+    ) -> Result<DepositAddress, APIError> {
         let endpoint = String::from("/api/v1/deposit-addresses");
         let mut params: HashMap<String, String> = HashMap::new();
         params.insert(String::from("currency"), currency.to_string());
@@ -303,12 +305,10 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         let resp = self.get(url, Some(headers)).await?;
-        let api_data = resp.json().await?;
-        Ok(api_data)
+        let api_data: APIDatum<DepositAddress> = resp.json().await?;
+        api_data.into_result()
     }
 
     pub async fn get_deposit_list(
@@ -319,7 +319,7 @@ impl Kucoin {
         status: Option<&str>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<DepositList>>, APIError> {
+    ) -> Result<Pagination<DepositList>, APIError> {
         let endpoint = String::from("/api/v1/deposits");
         let mut params: HashMap<String, String> = HashMap::new();
         if let Some(c) = currency {
@@ -342,12 +342,38 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         let resp = self.get(url, Some(headers)).await?;
-        let api_data = resp.json().await?;
-        Ok(api_data)
+        let api_data: APIDatum<Pagination<DepositList>> = resp.json().await?;
+        api_data.into_result()
+    }
+
+    /// Every deposit matching the filters, fetched page by page as the stream is read.
+    pub fn get_deposit_list_stream<'a>(
+        &'a self,
+        currency: Option<&'a str>,
+        start_at: Option<i64>,
+        end_at: Option<i64>,
+        status: Option<&'a str>,
+        paging: Paging,
+    ) -> BoxStream<'a, Result<DepositList, APIError>> {
+        let now_ms = self.time_sync().now_ms();
+        paginate_range(
+            start_at,
+            end_at,
+            paging,
+            now_ms,
+            move |start_at, end_at, page| {
+                self.get_deposit_list(
+                    currency,
+                    start_at,
+                    end_at,
+                    status,
+                    Some(page),
+                    Some(paging.page_size),
+                )
+            },
+        )
     }
 
     pub async fn get_v1_deposit_list(
@@ -358,7 +384,7 @@ impl Kucoin {
         status: Option<&str>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<DepositListV1>>, APIError> {
+    ) -> Result<Pagination<DepositListV1>, APIError> {
         let endpoint = String::from("/api/v1/deposits");
         let mut params: HashMap<String, String> = HashMap::new();
         if let Some(c) = currency {
@@ -381,12 +407,10 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         let resp = self.get(url, Some(headers)).await?;
-        let api_data = resp.json().await?;
-        Ok(api_data)
+        let api_data: APIDatum<Pagination<DepositListV1>> = resp.json().await?;
+        api_data.into_result()
     }
 
     pub async fn get_withdrawals_list(
@@ -397,7 +421,7 @@ impl Kucoin {
         status: Option<&str>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<WithdrawalList>>, APIError> {
+    ) -> Result<Pagination<WithdrawalList>, APIError> {
         let endpoint = String::from("/api/v1/withdrawals");
         let mut params: HashMap<String, String> = HashMap::new();
         if let Some(c) = currency {
@@ -420,12 +444,38 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         let resp = self.get(url, Some(headers)).await?;
-        let api_data = resp.json().await?;
-        Ok(api_data)
+        let api_data: APIDatum<Pagination<WithdrawalList>> = resp.json().await?;
+        api_data.into_result()
+    }
+
+    /// Every withdrawal matching the filters, fetched page by page as the stream is read.
+    pub fn get_withdrawals_list_stream<'a>(
+        &'a self,
+        currency: Option<&'a str>,
+        start_at: Option<i64>,
+        end_at: Option<i64>,
+        status: Option<&'a str>,
+        paging: Paging,
+    ) -> BoxStream<'a, Result<WithdrawalList, APIError>> {
+        let now_ms = self.time_sync().now_ms();
+        paginate_range(
+            start_at,
+            end_at,
+            paging,
+            now_ms,
+            move |start_at, end_at, page| {
+                self.get_withdrawals_list(
+                    currency,
+                    start_at,
+                    end_at,
+                    status,
+                    Some(page),
+                    Some(paging.page_size),
+                )
+            },
+        )
     }
 
     pub async fn get_v1_withdrawals_list(
@@ -436,7 +486,7 @@ impl Kucoin {
         status: Option<&str>,
         current_page: Option<i32>,
         page_size: Option<i32>,
-    ) -> Result<APIDatum<Pagination<WithdrawalListV1>>, APIError> {
+    ) -> Result<Pagination<WithdrawalListV1>, APIError> {
         let endpoint = String::from("/api/v1/withdrawals");
         let mut params: HashMap<String, String> = HashMap::new();
         if let Some(c) = currency {
@@ -459,19 +509,17 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         let resp = self.get(url, Some(headers)).await?;
-        let api_data = resp.json().await?;
-        Ok(api_data)
+        let api_data: APIDatum<Pagination<WithdrawalListV1>> = resp.json().await?;
+        api_data.into_result()
     }
 
     pub async fn get_withdrawals_quotas(
         &self,
         currency: &str,
         chain: Option<&str>,
-    ) -> Result<APIDatum<WithdrawalQuotas>, APIError> {
+    ) -> Result<WithdrawalQuotas, APIError> {
         let endpoint = String::from("/api/v1/withdrawals/quotas");
         let mut params: HashMap<String, String> = HashMap::new();
         params.insert(String::from("currency"), currency.to_string());
@@ -480,12 +528,10 @@ impl Kucoin {
         }
         let query = format_query(&params);
         let url = format!("{}{}{}", &self.prefix, endpoint, query);
-        let headers = self
-            .sign_headers(endpoint, None, Some(query), Method::GET)
-            .unwrap();
+        let headers = self.sign_headers(endpoint, None, Some(query), Method::GET)?;
         let resp = self.get(url, Some(headers)).await?;
-        let api_data = resp.json().await?;
-        Ok(api_data)
+        let api_data: APIDatum<WithdrawalQuotas> = resp.json().await?;
+        api_data.into_result()
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -498,7 +544,7 @@ impl Kucoin {
         is_inner: Option<bool>,
         remark: Option<&str>,
         chain: Option<&str>,
-    ) -> Result<APIDatum<WithdrawalId>, APIError> {
+    ) -> Result<WithdrawalId, APIError> {
         let endpoint = String::from("/api/v1/withdrawals");
         let url = format!("{}{}", &self.prefix, endpoint);
         let mut params: HashMap<String, String> = HashMap::new();
@@ -517,22 +563,134 @@ impl Kucoin {
         if let Some(c) = chain {
             params.insert(String::from("chain"), c.to_string());
         }
-        let headers = self
-            .sign_headers(endpoint, Some(&params), None, Method::POST)
-            .unwrap();
+        let headers = self.sign_headers(endpoint, Some(&params), None, Method::POST)?;
         let resp = self.post(url, Some(headers), Some(params)).await?;
-        let api_data = resp.json().await?;
-        Ok(api_data)
+        let api_data: APIDatum<WithdrawalId> = resp.json().await?;
+        api_data.into_result()
     }
 
-    pub async fn cancel_withdrawal(&self, withdrawal_id: &str) -> Result<String, APIError> {
+    pub async fn cancel_withdrawal(&self, withdrawal_id: &str) -> Result<(), APIError> {
         let endpoint = format!("/api/v1/withdrawals/{}", withdrawal_id);
         let url = format!("{}{}", &self.prefix, endpoint);
-        let headers = self
-            .sign_headers(endpoint, None, None, Method::DELETE)
-            .unwrap();
+        let headers = self.sign_headers(endpoint, None, None, Method::DELETE)?;
         let resp = self.delete(url, Some(headers)).await?;
-        let api_data = resp.text().await?;
-        Ok(api_data)
+        let api_data: APIDatum<serde_json::Value> = resp.json().await?;
+        api_data.into_empty_result()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::Method;
+
+    use crate::kucoin::model::user::AccountType;
+    use crate::kucoin::transport::test::{expected_sign, mock_client};
+    use crate::kucoin::transport::MockTransport;
+
+    #[tokio::test]
+    async fn lists_accounts_with_a_signed_query() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/accounts",
+            r#"{"code":"200000","data":[{"id":"5bd6e9286d99522a52e458de","currency":"USDT","type":"trade","balance":"237582.04","available":"237582.03","holds":"0.01"}]}"#,
+        );
+        let accounts = mock_client(&mock)
+            .get_accounts_list(Some("USDT"), Some("trade"))
+            .await
+            .unwrap();
+        assert_eq!(accounts[0].available, "237582.03");
+        let request = &mock.requests()[0];
+        assert!(request.is_signed());
+        let query = request.query.clone().unwrap();
+        assert!(query.contains("currency=USDT") && query.contains("type=trade"));
+    }
+
+    #[tokio::test]
+    async fn gets_the_transferable_balance() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/accounts/transferable",
+            r#"{"code":"200000","data":{"currency":"KCS","balance":"0","available":"0","holds":"0","transferable":"0"}}"#,
+        );
+        let balance = mock_client(&mock)
+            .get_transferable_balance("KCS", AccountType::Main)
+            .await
+            .unwrap();
+        assert_eq!(balance.transferable, "0");
+        let request = &mock.requests()[0];
+        assert_eq!(request.query.as_deref(), Some("currency=KCS&type=MAIN"));
+        assert_eq!(
+            request.headers["kc-api-sign"].to_str().unwrap(),
+            expected_sign(request).as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn transfers_between_accounts() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::POST,
+            "/api/v2/accounts/inner-transfer",
+            r#"{"code":"200000","data":{"orderId":"5bd6e9286d99522a52e458de"}}"#,
+        );
+        let resp = mock_client(&mock)
+            .inner_transfer("oid-1", "USDT", "main", "trade", "12.5")
+            .await
+            .unwrap();
+        assert_eq!(resp.order_id, "5bd6e9286d99522a52e458de");
+        let body = mock.requests()[0].json();
+        assert_eq!(body["from"], "main");
+        assert_eq!(body["to"], "trade");
+        assert_eq!(body["amount"], "12.5");
+    }
+
+    #[tokio::test]
+    async fn cancels_a_withdrawal() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::DELETE,
+            "/api/v1/withdrawals/5bffb63303aa675e8bbe18f9",
+            r#"{"code":"200000","data":null}"#,
+        );
+        mock_client(&mock)
+            .cancel_withdrawal("5bffb63303aa675e8bbe18f9")
+            .await
+            .unwrap();
+        assert_eq!(mock.requests()[0].method, Method::DELETE);
+    }
+
+    #[tokio::test]
+    async fn signs_ledger_queries_as_sent() {
+        let mock = MockTransport::new();
+        mock.on(
+            Method::GET,
+            "/api/v1/accounts/5bd6e9286d99522a52e458de/ledgers",
+            r#"{"code":"200000","data":{"currentPage":1,"pageSize":10,"totalNum":0,"totalPage":0,"items":[]}}"#,
+        );
+        let client = mock_client(&mock);
+        for _ in 0..3 {
+            client
+                .get_account_ledgers(
+                    "5bd6e9286d99522a52e458de",
+                    Some(1600000000000),
+                    Some(1600086400000),
+                    Some(1),
+                    Some(10),
+                )
+                .await
+                .unwrap();
+        }
+        for request in mock.requests() {
+            assert_eq!(
+                request.query.as_deref(),
+                Some("currentPage=1&endAt=1600086400000&pageSize=10&startAt=1600000000000")
+            );
+            assert_eq!(
+                request.headers["kc-api-sign"],
+                expected_sign(&request).as_str()
+            );
+        }
     }
 }
@@ -2,28 +2,36 @@ use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn get_time() -> u128 {
-    let start = SystemTime::now();
-    let since_the_epoch = start
+    // A clock set before 1970 reads as 0 rather than taking the process down.
+    SystemTime::now()
         .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards");
-
-    since_the_epoch.as_millis()
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
 }
 
-/// Formats a query from a provided referenced hash map. Note, ordering is not assured.
+/// Formats the query string, `?` included, from the provided params, for both the URL and the string to sign.
+/// Keys are sorted and keys and values percent-encoded, so the same params always give the same string and
+/// the signature matches what is sent. Empty when there are no params.
 pub fn format_query<S: ::std::hash::BuildHasher>(params: &HashMap<String, String, S>) -> String {
+    let mut pairs: Vec<(&String, &String)> = params.iter().collect();
+    pairs.sort();
     let mut query = String::new();
-    for (key, val) in params.iter() {
-        let segment = format!("{}={}", key, val);
-        if query.is_empty() {
-            query = format!("?{}", segment);
-        } else {
-            query = format!("{}&{}", query, segment);
-        }
+    for (key, val) in pairs {
+        query.push(if query.is_empty() { '?' } else { '&' });
+        query.push_str(&encode_component(key));
+        query.push('=');
+        query.push_str(&encode_component(val));
     }
     query
 }
 
+// Form encoding writes spaces as `+`, a literal `+` comes out as `%2B`.
+fn encode_component(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20")
+}
+
 #[cfg(test)]
 mod test {
     use crate::kucoin::utils::format_query;
@@ -41,4 +49,20 @@ mod test {
         assert_eq!(query.contains("price=124.12"), true);
         assert_eq!(query.contains("quantity=0.51"), true);
     }
+
+    #[test]
+    fn format_query_is_sorted_and_encoded() {
+        let mut params: HashMap<String, String> = HashMap::new();
+        params.insert("symbol".to_string(), "BTC-USDT".to_string());
+        params.insert("currentPage".to_string(), "2".to_string());
+        params.insert("remark".to_string(), "a b&c=d+e/f".to_string());
+        params.insert("endAt".to_string(), "1600000000000".to_string());
+        let expected =
+            "?currentPage=2&endAt=1600000000000&remark=a%20b%26c%3Dd%2Be%2Ff&symbol=BTC-USDT";
+        for _ in 0..10 {
+            let reordered: HashMap<String, String> = params.clone().into_iter().collect();
+            assert_eq!(format_query(&reordered), expected);
+        }
+        assert_eq!(format_query(&HashMap::<String, String>::new()), "");
+    }
 }
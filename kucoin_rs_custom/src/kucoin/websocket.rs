@@ -11,7 +11,6 @@ use tokio::time;
 use tokio_tungstenite::{connect_async, tungstenite::Message, WebSocketStream};
 use url::Url;
 
-use failure;
 use serde_json;
 use std::{
     pin::Pin,
@@ -39,6 +38,7 @@ pub struct KucoinWebsocket {
     streams: StreamUnordered<StoredStream>,
 }
 
+/// Polling with nothing subscribed yields `APIError::NotSubscribed` rather than waiting forever.
 impl Stream for KucoinWebsocket {
     type Item = Result<KucoinWebsocketMsg, APIError>;
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
@@ -48,14 +48,14 @@ impl Stream for KucoinWebsocket {
                     // let heartbeat = self.heartbeats.get_mut(&token).unwrap();
                     Poll::Ready({
                         Some(
-                            item.map_err(APIError::Websocket)
+                            item.map_err(APIError::from)
                                 .and_then(|m| parse_message(m)),
                         )
                     })
                 }
                 StreamYield::Finished(_) => Poll::Pending,
             },
-            Poll::Ready(None) => panic!("No Stream Subscribed"),
+            Poll::Ready(None) => Poll::Ready(Some(Err(APIError::NotSubscribed))),
             Poll::Pending => Poll::Pending,
         }
     }
@@ -63,7 +63,7 @@ impl Stream for KucoinWebsocket {
 
 impl KucoinWebsocket {
     pub async fn subscribe(&mut self, url: String, ws_topic: Vec<WSTopic>) -> Result<(), APIError> {
-        let endpoint = Url::parse(&url).unwrap();
+        let endpoint = Url::parse(&url).map_err(|source| APIError::InvalidUrl { url, source })?;
         let (ws_stream, _) = connect_async(endpoint).await?;
 
         let (sink, read) = ws_stream.split();
@@ -75,7 +75,7 @@ impl KucoinWebsocket {
             sink_mutex
                 .lock()
                 .await
-                .send(Message::Text(serde_json::to_string(&sub).unwrap()))
+                .send(Message::Text(serde_json::to_string(&sub)?))
                 .await?;
         }
 
@@ -87,22 +87,21 @@ impl KucoinWebsocket {
                     id: get_time().to_string(),
                     r#type: "ping".to_string(),
                 };
+                let ping = match serde_json::to_string(&ping) {
+                    Ok(p) => p,
+                    Err(_) => break,
+                };
                 let resp = sink_mutex
                     .lock()
                     .await
-                    .send(Message::Text(serde_json::to_string(&ping).unwrap()))
-                    .map_err(APIError::Websocket)
+                    .send(Message::Text(ping))
+                    .map_err(APIError::from)
                     .await;
 
-                if let Err(e) = resp {
-                    match e {
-                        APIError::Websocket(e) => {
-                            format_err!("Error sending Ping: {}", e);
-                            break;
-                        }
-                        _ => format_err!("None websocket error sending Ping: {}", e),
-                    };
-                };
+                // A websocket error means the connection is gone, stop the heartbeat with it.
+                if let Err(APIError::Websocket(_)) = resp {
+                    break;
+                }
             }
         });
 
@@ -196,7 +195,7 @@ fn parse_message(msg: Message) -> Result<KucoinWebsocketMsg, APIError> {
                 Ok(KucoinWebsocketMsg::OrderBookChangeMsg(
                     serde_json::from_str(&msg)?,
                 ))
-            } else if msg.contains("\"type\":\"stop\"") || msg.contains("\"type\":\"activate\"") {
+            } else if msg.contains("\"subject\":\"stopOrder\"") {
                 Ok(KucoinWebsocketMsg::StopOrderMsg(serde_json::from_str(
                     &msg,
                 )?))
@@ -267,9 +266,10 @@ fn parse_message(msg: Message) -> Result<KucoinWebsocketMsg, APIError> {
 
 pub async fn close_socket(
     heartbeat: &mut tokio::task::JoinHandle<()>,
-) -> Result<(), failure::Error> {
-    heartbeat.await?;
-    Ok(())
+) -> Result<(), APIError> {
+    heartbeat
+        .await
+        .map_err(|e| APIError::Other(format!("Heartbeat task failed: {}", e)))
 }
 
 impl Kucoin {
@@ -277,48 +277,35 @@ impl Kucoin {
         KucoinWebsocket::default()
     }
 
-    pub async fn ws_bullet_private(&self) -> Result<APIDatum<InstanceServers>, APIError> {
+    pub async fn ws_bullet_private(&self) -> Result<InstanceServers, APIError> {
         let endpoint = String::from("/api/v1/bullet-private");
         let url: String = format!("{}{}", &self.prefix, endpoint);
-        let header: header::HeaderMap = self
-            .sign_headers(endpoint, None, None, Method::POST)
-            .unwrap();
+        let header: header::HeaderMap = self.sign_headers(endpoint, None, None, Method::POST)?;
         let resp = self.post(url, Some(header), None).await?;
         let api_data: APIDatum<InstanceServers> = resp.json().await?;
-        Ok(api_data)
+        api_data.into_result()
     }
 
-    pub async fn ws_bullet_public(&self) -> Result<APIDatum<InstanceServers>, APIError> {
-        let endpoint = String::from("/api/v1/bullet-public");
-        let url: String = format!("{}{}", &self.prefix, endpoint);
-        let header: header::HeaderMap = self
-            .sign_headers(endpoint, None, None, Method::POST)
-            .unwrap();
-        let resp = self.post(url, Some(header), None).await?;
+    pub async fn ws_bullet_public(&self) -> Result<InstanceServers, APIError> {
+        let url: String = format!("{}/api/v1/bullet-public", &self.prefix);
+        let resp = self.post_public(url).await?;
         let api_data: APIDatum<InstanceServers> = resp.json().await?;
-        Ok(api_data)
+        api_data.into_result()
     }
 
     pub async fn get_socket_endpoint(&self, ws_type: WSType) -> Result<String, APIError> {
-        let mut endpoint: String = String::new();
-        let mut token: String = String::new();
         let timestamp = get_time();
-        match ws_type {
-            WSType::Private => {
-                let resp = &self.ws_bullet_private().await?;
-                if let Some(r) = &resp.data {
-                    token = r.token.to_owned();
-                    endpoint = r.instance_servers[0].endpoint.to_owned();
-                }
-            }
-            WSType::Public => {
-                let resp = &self.ws_bullet_public().await?;
-                if let Some(r) = &resp.data {
-                    token = r.token.to_owned();
-                    endpoint = r.instance_servers[0].endpoint.to_owned();
-                }
-            }
-        }
+        let resp = match ws_type {
+            WSType::Private => self.ws_bullet_private().await?,
+            WSType::Public => self.ws_bullet_public().await?,
+        };
+        let token = resp.token;
+        let endpoint = resp
+            .instance_servers
+            .into_iter()
+            .next()
+            .map(|s| s.endpoint)
+            .ok_or_else(|| APIError::Other("Kucoin returned no instance servers".to_string()))?;
         let url = format!(
             "{}?token={}&[connectId={}]?acceptUserMessage=\"true\"",
             endpoint, token, timestamp
@@ -354,9 +341,9 @@ impl Subscribe {
                 private_channel = true;
                 String::from("/account/balance")
             }
-            WSTopic::StopOrder(ref symbols) => {
+            WSTopic::StopOrder => {
                 private_channel = true;
-                format!("/market/level3:{}", symbols.join(","))
+                String::from("/spotMarket/advancedOrders")
             }
             WSTopic::DebtRatio => {
                 private_channel = true;
@@ -385,3 +372,57 @@ impl Subscribe {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+    use reqwest::Method;
+
+    use crate::kucoin::client::{Kucoin, KucoinEnv};
+    use crate::kucoin::error::APIError;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use crate::kucoin::model::websocket::{KucoinWebsocketMsg, Subscribe, WSTopic, WSType};
+    use crate::kucoin::transport::MockTransport;
+    use crate::kucoin::websocket::{parse_message, KucoinWebsocket};
+
+    #[test]
+    fn subscribes_to_stop_orders() {
+        let sub = Subscribe::new(&WSTopic::StopOrder);
+        assert_eq!(sub.topic, "/spotMarket/advancedOrders");
+        assert!(sub.private_channel);
+
+        let msg = r#"{"type":"message","topic":"/spotMarket/advancedOrders","subject":"stopOrder","channelType":"private","data":{"createdAt":1589789942337,"orderId":"5ec244f6a8a75e0009958237","orderPrice":"0.00062","orderType":"stop","side":"sell","size":"1","stop":"entry","stopPrice":"0.00062","symbol":"KCS-BTC","tradeType":"TRADE","triggerSuccess":true,"ts":1589790121382281286,"type":"triggered"}}"#;
+        match parse_message(Message::Text(msg.to_string())).unwrap() {
+            KucoinWebsocketMsg::StopOrderMsg(resp) => {
+                assert_eq!(resp.data.order_id, "5ec244f6a8a75e0009958237");
+                assert_eq!(resp.data.r#type, "triggered");
+                assert_eq!(resp.data.trigger_success, Some(true));
+            }
+            other => panic!("parsed as {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_without_panicking() {
+        let mut ws = KucoinWebsocket::default();
+        assert!(matches!(ws.next().await, Some(Err(APIError::NotSubscribed))));
+
+        let mock = MockTransport::new();
+        mock.on(
+            Method::POST,
+            "/api/v1/bullet-public",
+            r#"{"code":"200000","data":{"token":"public-token","instanceServers":[]}}"#,
+        );
+        let mut anon =
+            Kucoin::new(KucoinEnv::Custom("http://mock.test".to_string()), None).unwrap();
+        anon.set_transport(Arc::new(mock.clone()));
+        assert_eq!(anon.ws_bullet_public().await.unwrap().token, "public-token");
+        assert!(!mock.requests()[0].is_signed());
+        assert!(anon.get_socket_endpoint(WSType::Public).await.is_err());
+        let subscribed = ws.subscribe("not a url".to_string(), vec![]).await;
+        assert!(matches!(subscribed, Err(APIError::InvalidUrl { .. })));
+    }
+}
@@ -54,6 +54,69 @@
 //! ```
 //! A non-authorized client can be used for accessing Public Endpoints by inputting a None: `Kucoin::new(KucoinEnv::Live, None);`
 //!
+//! ### Rate Limiting
+//!
+//! Every client carries a token bucket [`RateLimiter`](./kucoin/rate_limit/struct.RateLimiter.html) with separate
+//! quotas for public, private and order placement endpoints. Requests wait for a token before being sent, and a 429
+//! response from Kucoin blocks the endpoint class for the reported back off before the request is re-sent.
+//! Quotas can be tuned with [`RateLimits`](./kucoin/rate_limit/struct.RateLimits.html):
+//!
+//! ```
+//! use std::time::Duration;
+//! use kucoin_rs_custom::kucoin::client::{Kucoin, KucoinEnv};
+//! use kucoin_rs_custom::kucoin::rate_limit::{Quota, RateLimits};
+//!
+//! let limits = RateLimits::new()
+//!     .private(Quota::new(20, Duration::from_secs(3)))
+//!     .build();
+//! let api = Kucoin::new_with_rate_limits(KucoinEnv::Live, None, limits);
+//! ```
+//!
+//! ### Custom Endpoints and Testing
+//!
+//! `KucoinEnv::Custom` points the client at any other base URL, e.g. a local mock of the API. Requests go through a
+//! [`Transport`](./kucoin/transport/trait.Transport.html), reqwest by default, which can be swapped for a
+//! [`MockTransport`](./kucoin/transport/struct.MockTransport.html) answering with canned responses:
+//!
+//! ```
+//! use std::sync::Arc;
+//! use kucoin_rs_custom::kucoin::client::{Kucoin, KucoinEnv};
+//! use kucoin_rs_custom::kucoin::transport::MockTransport;
+//!
+//! let mock = MockTransport::new();
+//! mock.on(reqwest::Method::GET, "/api/v1/timestamp", r#"{"code":"200000","data":1546837113087}"#);
+//! let mut api = Kucoin::new(KucoinEnv::Custom("http://localhost:8080".to_string()), None).unwrap();
+//! api.set_transport(Arc::new(mock.clone()));
+//! let time = kucoin_rs_custom::tokio::runtime::Runtime::new()
+//!     .unwrap()
+//!     .block_on(api.get_server_time());
+//! assert_eq!(time.unwrap(), 1546837113087);
+//! assert_eq!(mock.requests()[0].path, "/api/v1/timestamp");
+//! ```
+//!
+//! A [`RecordingTransport`](./kucoin/fixture/struct.RecordingTransport.html) wraps the client's transport and saves
+//! every request and response to a fixture file, leaving out headers and redacting tokens and other secrets in bodies.
+//! A [`ReplayTransport`](./kucoin/fixture/struct.ReplayTransport.html) serves a fixture back, so tests can pin real
+//! exchange payloads and run offline:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use kucoin_rs_custom::kucoin::client::{Kucoin, KucoinEnv};
+//! use kucoin_rs_custom::kucoin::fixture::{RecordingTransport, ReplayTransport};
+//!
+//! # async fn run() -> Result<(), kucoin_rs_custom::kucoin::error::APIError> {
+//! let mut api = Kucoin::new(KucoinEnv::Live, None)?;
+//! let recorder = RecordingTransport::new(api.transport());
+//! api.set_transport(Arc::new(recorder.clone()));
+//! api.get_all_tickers().await?;
+//! recorder.save("fixtures/tickers.json")?;
+//!
+//! api.set_transport(Arc::new(ReplayTransport::load("fixtures/tickers.json")?));
+//! let replayed = api.get_all_tickers().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! ## Examples
 //!
 //! Below are some basic examples.
@@ -78,17 +141,14 @@
 //! extern crate kucoin_rs_custom;
 //!
 //! use kucoin_rs_custom::tokio;
-//! use kucoin_rs_custom::failure;
+//! use kucoin_rs_custom::kucoin::error::APIError;
 //! use kucoin_rs_custom::kucoin::client::{Kucoin, Credentials, KucoinEnv};
 //!
 //! #[tokio::main]
-//! async fn main() -> Result<(), failure::Error>  {
+//! async fn main() -> Result<(), APIError>  {
 //!     let api = Kucoin::new(KucoinEnv::Sandbox, None)?;
-//!     let result = api.get_ticker("BTC-USDT").await?;
-//!     match result.data {
-//!         Some(d) => println!("{:#?}", d),
-//!         None => println!("Code: {}, msg: {:#?}", result.code, result.msg),
-//!     }
+//!     let ticker = api.get_ticker("BTC-USDT").await?;
+//!     println!("{:#?}", ticker);
 //!     Ok(())
 //! }
 //! ```
@@ -99,31 +159,46 @@
 //! extern crate kucoin_rs_custom;
 //!
 //! use kucoin_rs_custom::tokio;
-//! use kucoin_rs_custom::failure;
 //! use kucoin_rs_custom::kucoin::client::{Kucoin, Credentials, KucoinEnv};
-//! use kucoin_rs_custom::kucoin::error::APIError;
+//! use kucoin_rs_custom::kucoin::error::{APIError, KucoinCode};
 //!
 //! #[tokio::main]
-//! async fn main() -> Result<(), failure::Error>  {
+//! async fn main() -> Result<(), APIError>  {
 //!    let result = api.get_server_time().await;
 //!    match result {
 //!        Err(e) => {
 //!            match e {
 //!                APIError::HTTP(e) => eprintln!("Reqwest Error: {}", e),
+//!                APIError::Status { status: 429, .. } => eprintln!("Slow down"),
+//!                _ if e.code() == Some(&KucoinCode::TooManyRequests) => eprintln!("Slow down"),
+//!                APIError::Kucoin { code, msg } => eprintln!("Kucoin Error {}: {}", code, msg),
 //!                _ => eprintln!("Non HTTP Error: {}", e),
 //!            }
 //!        },
-//!        Ok(s) => {
-//!            match s.data {
-//!                Some(d) => println!("{:#?}", d),
-//!                None => println!("Code: {}, msg: {:#?}", s.code, s.msg),
-//!            }
-//!        }
+//!        Ok(time) => println!("{:#?}", time),
 //!    }
 //!    Ok(())
 //! }
 //! ```
 //!
+//! Paged endpoints also come as streams, `get_orders_stream`, `get_fills_stream`, `get_account_ledgers_stream` and so on,
+//! which fetch the next page only when the current one is used up. Every page counts against the rate limits like any other
+//! request. With a [`Paging`](./kucoin/paginate/struct.Paging.html) window set, a long `startAt`/`endAt` range is split into
+//! ranges Kucoin accepts:
+//!
+//! ```ignore
+//! use futures::TryStreamExt;
+//! use kucoin_rs_custom::kucoin::paginate::{Paging, MAX_WINDOW};
+//! use kucoin_rs_custom::kucoin::trade::FillsOptionals;
+//!
+//! let options = FillsOptionals::new().symbol("BTC-USDT").start_at(1600000000000).build();
+//! let paging = Paging::new().window(MAX_WINDOW).build();
+//! let mut fills = api.get_fills_stream(options, paging);
+//! while let Some(fill) = fills.try_next().await? {
+//!     println!("{} {} @ {}", fill.side, fill.size, fill.price);
+//! }
+//! ```
+//!
 //!
 //! ### Websocket Usage
 //!
@@ -136,14 +211,14 @@
 //! extern crate kucoin_rs_custom;
 //!
 //! use kucoin_rs_custom::tokio;
-//! use kucoin_rs_custom::failure;
+//! use kucoin_rs_custom::kucoin::error::APIError;
 //! use kucoin_rs_custom::tokio::stream::StreamExt;
 //!
 //! use kucoin_rs_custom::kucoin::client::{Kucoin, Credentials, KucoinEnv};
 //! use kucoin_rs_custom::kucoin::model::websocket::{Subscribe, KucoinWebsocketMsg, WSType, WSTopic, WSResp};
 //!
 //! #[tokio::main]
-//! async fn main() -> Result<(), failure::Error>  {
+//! async fn main() -> Result<(), APIError>  {
 //!     // If credentials are needed, generate a new Credentials struct w/ the necessary keys
 //!     let credentials = Credentials::new(
 //!         "xxxxxxxxxxxxxXXXXXXxxx",
@@ -198,11 +273,19 @@
 //!
 //! ## Error Handling
 //!
-//! kucoin_rs_custom uses the [`failure crate`](https://crates.io/crates/failure) to propagate errors. Kucoin REST errors are
-//! passed as part of the response structs, however by default, reqwest errors panic. For websocket endpoints, similarly,
-//! by default most protocol and connection errors will panic. Use of `?` will result in panics as well. End users can however
-//! use the custom [`APIError`](./kucoin/error/enum.APIError.html) enum to match error responses which provide non panic
-//! alternatives allowing for specified error handling. Users can also implement their own more comprehensive solutions.
+//! Every call returns [`APIError`](./kucoin/error/enum.APIError.html), which implements `std::error::Error` and exposes the
+//! underlying reqwest, serde or websocket error through `source()`. REST endpoints return
+//! the response data directly; a non success Kucoin response code is returned as `APIError::Kucoin` carrying a typed
+//! [`KucoinCode`](./kucoin/error/enum.KucoinCode.html) and the message sent by Kucoin. A non 2xx HTTP response is returned as
+//! `APIError::Status` with the status, the endpoint, the Kucoin code and message when the body has them and the start of
+//! the raw body otherwise. `APIError::code()` gives the Kucoin code from either of them.
+//!
+//! Nothing in the client panics, problems found before a request goes out are errors too:
+//! `APIError::MissingCredentials` names the private endpoint called on a client built without credentials,
+//! `APIError::InvalidUrl` carries a URL that could not be parsed, e.g. from a bad `KucoinEnv::Custom`, and
+//! `APIError::InvalidHeader` names a header whose value HTTP doesn't allow, such as a key pasted with a newline.
+//! On websockets, polling a stream with nothing subscribed yields `APIError::NotSubscribed` and protocol or
+//! connection failures come back as `APIError::Websocket`.
 //!
 //! ## Contribution
 //!
@@ -239,8 +322,6 @@ pub extern crate serde_json;
 
 #[macro_use]
 pub extern crate serde_derive;
-#[macro_use]
-pub extern crate failure;
 
 /// Kucoin API Module
 pub mod kucoin;
@@ -0,0 +1,374 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::sync::watch;
+
+use crate::credentials::credentials::{read_passphrase, Vault};
+use crate::error::error::MintError;
+use crate::exchange::api_credentials::ApiCredentials;
+use crate::risk::risk::RiskLimits;
+
+const DEFAULT_CONFIG_PATH: &str = "settings.json";
+const CONFIG_PATH_ENV: &str = "KUCOIN_ARB_CONFIG";
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub port: u16,
+    /// Token expected in the `X-Admin-Token` header, admin endpoints are disabled without one.
+    /// An empty token counts as none.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub admin_token: Option<String>,
+    pub take_profit_perc: f32,
+    pub balance_perc: f32,
+    /// Most quote currency moved from the Main account into Trade before a signal is bought,
+    /// nothing is moved when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_fund_cap: Option<f32>,
+    /// Moves the proceeds of filled take profits back to the Main account.
+    #[serde(default)]
+    pub sweep_to_main: bool,
+    #[serde(default)]
+    pub risk: RiskLimits,
+    /// A signal with the same tokens as one taken within this many seconds is dropped, 0 turns it off.
+    #[serde(default = "default_dedup_window_secs")]
+    pub dedup_window_secs: u64,
+    /// Seconds a token is held back after a signal with it was taken, 0 turns it off.
+    #[serde(default)]
+    pub token_cooldown_secs: u64,
+    /// Where signals with an `execute_at` wait until they fire, only read on startup.
+    #[serde(default = "default_schedule_file")]
+    pub schedule_file: PathBuf,
+    /// Where tripped drawdown breakers are kept so a halt survives a restart, only read on startup.
+    #[serde(default = "default_drawdown_file")]
+    pub drawdown_file: PathBuf,
+    /// Where the kill switch is kept so it stays engaged over a restart, only read on startup.
+    #[serde(default = "default_kill_switch_file")]
+    pub kill_switch_file: PathBuf,
+    /// Encrypted file holding the API keys of `users`, see `kucoin_arb credentials`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials_file: Option<PathBuf>,
+    pub users: Vec<ApiCredentials>,
+}
+
+fn default_dedup_window_secs() -> u64 {
+    30
+}
+
+fn default_schedule_file() -> PathBuf {
+    PathBuf::from("scheduled.json")
+}
+
+fn default_drawdown_file() -> PathBuf {
+    PathBuf::from("drawdown.json")
+}
+
+fn default_kill_switch_file() -> PathBuf {
+    PathBuf::from("kill_switch.json")
+}
+
+/// Reads `""` as unset, the example files leave optional values blank.
+fn empty_as_none<'de, D>(deserializer: D) -> std::result::Result<Option<String>, D::Error>
+    where D: Deserializer<'de>
+{
+    Ok(Option::<String>::deserialize(deserializer)?.filter(|s| !s.is_empty()))
+}
+
+fn invalid(msg: String) -> anyhow::Error {
+    MintError::from_str(msg).into()
+}
+
+fn env_override<T>(name: &str, target: &mut T) -> Result<()>
+    where T: std::str::FromStr,
+          T::Err: std::error::Error + Send + Sync + 'static
+{
+    if let Ok(val) = std::env::var(name) {
+        *target = val.parse::<T>()
+            .with_context(|| format!("{} from the environment has a bad value {:?}", name, val))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn check_take_profit_perc(perc: f32) -> Result<()> {
+    if !perc.is_finite() || perc <= 0.0 {
+        return Err(invalid(format!("take_profit_perc must be above 0, got {}", perc)));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_auto_fund_cap(cap: f32) -> Result<()> {
+    if !cap.is_finite() || cap <= 0.0 {
+        return Err(invalid(format!("auto_fund_cap must be above 0, got {}", cap)));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_balance_perc(perc: f32) -> Result<()> {
+    if !perc.is_finite() || perc <= 0.0 || perc > 1.0 {
+        return Err(invalid(format!("balance_perc must be above 0 and at most 1, got {}", perc)));
+    }
+    Ok(())
+}
+
+impl Config {
+    pub fn from_json(json: &str) -> Result<Config> {
+        Ok(serde_json::from_str::<Config>(json)?)
+    }
+    fn apply_env_overrides(&mut self) -> Result<()> {
+        env_override("PORT", &mut self.port)?;
+        env_override("TAKE_PROFIT_PERC", &mut self.take_profit_perc)?;
+        env_override("BALANCE_PERC", &mut self.balance_perc)?;
+        match std::env::var("ADMIN_TOKEN") {
+            Ok(token) if !token.is_empty() => self.admin_token = Some(token),
+            _ => {}
+        }
+        Ok(())
+    }
+    pub fn validate(&self) -> Result<()> {
+        if self.port == 0 {
+            return Err(invalid("port must be set".to_string()));
+        }
+        check_take_profit_perc(self.take_profit_perc)?;
+        check_balance_perc(self.balance_perc)?;
+        if let Some(cap) = self.auto_fund_cap {
+            check_auto_fund_cap(cap)?;
+        }
+        self.risk.validate()?;
+        if let Some(token) = &self.admin_token {
+            if token.len() < 16 {
+                return Err(invalid("admin_token must be at least 16 characters".to_string()));
+            }
+        }
+        if self.users.is_empty() {
+            return Err(invalid("users must contain at least one account".to_string()));
+        }
+        let mut names = HashSet::new();
+        for (i, user) in self.users.iter().enumerate() {
+            user.validate().with_context(|| format!("users[{}] is invalid", i))?;
+            if !names.insert(&user.name) {
+                return Err(invalid(format!("users[{}] reuses the name {:?}", i, user.name)));
+            }
+        }
+        Ok(())
+    }
+    /// The file as written, without environment overrides or validation.
+    fn read_raw(path: &Path) -> Result<Config> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        Config::from_json(&json)
+            .with_context(|| format!("Config file {} does not match the expected schema", path.display()))
+    }
+    fn with_overrides(mut self, path: &Path) -> Result<Config> {
+        self.apply_env_overrides()?;
+        self.validate()
+            .with_context(|| format!("Config file {} is invalid", path.display()))?;
+        Ok(self)
+    }
+    /// Fills in the API keys of every user from the credentials file.
+    fn with_secrets(mut self, vault: Option<&Vault>) -> Result<Config> {
+        let vault = match (&self.credentials_file, vault) {
+            (None, _) => return Ok(self),
+            (Some(path), Some(vault)) if path == vault.path() => vault,
+            (Some(path), _) => return Err(invalid(format!("credentials_file {} can only be changed with a restart", path.display())))
+        };
+        for (i, user) in self.users.iter_mut().enumerate() {
+            if user.has_secrets() {
+                return Err(invalid(format!("users[{}] has API keys in the config file, they belong in {}", i, vault.path().display())));
+            }
+            let secrets = vault.get(&user.name)?
+                .ok_or_else(|| invalid(format!("No entry for {} in {}, add one with `kucoin_arb credentials add {}`", user.name, vault.path().display(), user.name)))?;
+            user.set_secrets(&secrets);
+        }
+        Ok(self)
+    }
+}
+
+/// The config path is taken from `--config <path>`, then the `KUCOIN_ARB_CONFIG` variable,
+/// then defaults to `settings.json` in the working directory.
+pub fn config_path_from_args() -> PathBuf {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            if let Some(path) = args.next() {
+                return PathBuf::from(path);
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            return PathBuf::from(path);
+        }
+    }
+    std::env::var(CONFIG_PATH_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_CONFIG_PATH))
+}
+
+/// Holds the current config and lets tasks wait for reloads.
+#[derive(Clone)]
+pub struct ConfigStore {
+    path: PathBuf,
+    send_config: Arc<watch::Sender<Arc<Config>>>,
+    // Kept so sending never fails for lack of subscribers.
+    recv_config: watch::Receiver<Arc<Config>>,
+    // Held while the file is rewritten so concurrent admin changes don't undo each other.
+    write_lock: Arc<Mutex<()>>,
+    vault: Option<Arc<Vault>>,
+}
+
+impl ConfigStore {
+    /// Loads the config, asking for the passphrase when the keys are in a credentials file.
+    pub fn load(path: PathBuf) -> Result<ConfigStore> {
+        let raw = Config::read_raw(&path)?;
+        let vault = match &raw.credentials_file {
+            Some(file) => {
+                let passphrase = read_passphrase("Credentials passphrase: ")?;
+                Some(Arc::new(Vault::open(file, &passphrase)?))
+            }
+            None => None
+        };
+        let config = raw.with_secrets(vault.as_deref())?.with_overrides(&path)?;
+        let (send_config, recv_config) = watch::channel(Arc::new(config));
+        Ok(ConfigStore {
+            path,
+            send_config: Arc::new(send_config),
+            recv_config,
+            write_lock: Arc::new(Mutex::new(())),
+            vault,
+        })
+    }
+    fn load_config(&self) -> Result<Config> {
+        Config::read_raw(&self.path)?
+            .with_secrets(self.vault.as_deref())?
+            .with_overrides(&self.path)
+    }
+    pub fn get(&self) -> Arc<Config> {
+        Arc::clone(&self.recv_config.borrow())
+    }
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Config>> {
+        self.recv_config.clone()
+    }
+    fn publish(&self, config: Config) -> Result<Arc<Config>> {
+        let config = Arc::new(config);
+        self.send_config.send(Arc::clone(&config))
+            .map_err(|_| MintError::from_str("No one is listening for config reloads".to_string()))?;
+        Ok(config)
+    }
+    /// Re-reads the file. An invalid file leaves the current config in place.
+    pub fn reload(&self) -> Result<Arc<Config>> {
+        let config = self.publish(self.load_config()?)?;
+        println!("Reloaded config from {}", self.path.display());
+        Ok(config)
+    }
+    /// Changes the users in the file and publishes the result, so admin changes survive a restart.
+    /// Environment overrides are left out of the file, and so are API keys when there is a
+    /// credentials file to keep them in.
+    pub fn update_users<F>(&self, f: F) -> Result<Arc<Config>>
+        where F: FnOnce(&mut Vec<ApiCredentials>) -> Result<()>
+    {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut raw = Config::read_raw(&self.path)?.with_secrets(self.vault.as_deref())?;
+        f(&mut raw.users)?;
+        let config = raw.clone().with_overrides(&self.path)?;
+        if let Some(vault) = &self.vault {
+            let entries: Vec<_> = raw.users.iter().map(|user| (user.name.clone(), user.secrets())).collect();
+            vault.put_all(&entries)?;
+            raw.users.iter_mut().for_each(|user| user.clear_secrets());
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(&raw)?)
+            .with_context(|| format!("Could not write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Could not replace config file {}", self.path.display()))?;
+        self.publish(config)
+    }
+}
+
+#[cfg(unix)]
+pub async fn reload_on_sighup(store: ConfigStore) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangups = signal(SignalKind::hangup())?;
+    while hangups.recv().await.is_some() {
+        if let Err(e) = store.reload() {
+            println!("Config reload failed, keeping the current config: {:?}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::config::{Config, ConfigStore};
+    use crate::credentials::credentials::{KdfParams, Secrets, Vault};
+
+    const VALID: &str = r#"{
+        "port": 5000,
+        "take_profit_perc": 0.05,
+        "balance_perc": 0.7,
+        "users": [{ "name": "a", "api_key": "k", "api_secret": "s", "api_pass": "p" }]
+    }"#;
+
+    #[test]
+    fn accepts_a_valid_config() {
+        let config = Config::from_json(VALID).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.admin_token, None);
+        let blank = Config::from_json(&VALID.replace("\"port\": 5000,", "\"port\": 5000, \"admin_token\": \"\",")).unwrap();
+        blank.validate().unwrap();
+        assert_eq!(blank.admin_token, None);
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_bad_values() {
+        assert!(Config::from_json(&VALID.replace("\"port\"", "\"prot\"")).is_err());
+        let config = Config::from_json(&VALID.replace("0.7", "1.5")).unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(format!("{}", err).contains("balance_perc"));
+        let config = Config::from_json(&VALID.replace("\"k\"", "\"\"")).unwrap();
+        assert!(format!("{:#}", config.validate().unwrap_err()).contains("users[0]"));
+    }
+
+    #[test]
+    fn update_users_persists_to_the_file() {
+        let path = std::env::temp_dir().join(format!("kucoin_arb_config_{}.json", std::process::id()));
+        std::fs::write(&path, VALID).unwrap();
+        let store = ConfigStore::load(path.clone()).unwrap();
+        let mut added = store.get().users[0].clone();
+        added.name = "b".to_string();
+        store.update_users(|users| {
+            users.push(added);
+            Ok(())
+        }).unwrap();
+        assert_eq!(store.get().users.len(), 2);
+        assert_eq!(ConfigStore::load(path.clone()).unwrap().get().users.len(), 2);
+        let res = store.update_users(|users| {
+            users.clear();
+            Ok(())
+        });
+        assert!(res.is_err());
+        assert_eq!(ConfigStore::load(path.clone()).unwrap().get().users.len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fills_keys_from_the_credentials_file() {
+        let path = std::env::temp_dir().join(format!("kucoin_arb_config_vault_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let vault = Vault::create(&path, "passphrase", KdfParams { m_cost: 64, t_cost: 1, p_cost: 1 }).unwrap();
+        let with_file = VALID
+            .replace(r#", "api_key": "k", "api_secret": "s", "api_pass": "p""#, "")
+            .replace(r#""users""#, &format!(r#""credentials_file": {:?}, "users""#, path));
+        let raw = Config::from_json(&with_file).unwrap();
+        assert!(raw.clone().with_secrets(Some(&vault)).is_err());
+        vault.put("a", &Secrets {
+            api_key: "k".to_string(),
+            api_secret: "s".to_string(),
+            api_pass: "p".to_string(),
+        }).unwrap();
+        let config = raw.clone().with_secrets(Some(&vault)).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.users[0].secrets().api_secret, "s");
+        assert!(Config::from_json(VALID).unwrap().with_secrets(Some(&vault)).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use zeroize::Zeroizing;
+
+use crate::credentials::credentials::{env_passphrase, read_passphrase, KdfParams, Secrets, Vault};
+use crate::error::error::MintError;
+
+const DEFAULT_CREDENTIALS_PATH: &str = "credentials.json.enc";
+
+const USAGE: &str = "Usage: kucoin_arb credentials [--file <path>] <command>
+
+Commands:
+    add <name>       Add an account, creating the file when it doesn't exist yet
+    list             List the account names, no passphrase needed
+    rotate <name>    Replace the API keys of an account";
+
+fn prompt_secret(prompt: &str) -> Result<String> {
+    let val = rpassword::prompt_password(prompt).context("Could not read from the terminal")?;
+    if val.trim().is_empty() {
+        return Err(MintError::from_str(format!("{} must not be empty", prompt.trim_end_matches(": "))).into());
+    }
+    Ok(val)
+}
+
+fn prompt_secrets() -> Result<Secrets> {
+    Ok(Secrets {
+        api_key: prompt_secret("API key: ")?,
+        api_secret: prompt_secret("API secret: ")?,
+        api_pass: prompt_secret("API passphrase: ")?,
+    })
+}
+
+fn open_or_create(path: &Path) -> Result<Vault> {
+    if path.exists() {
+        let passphrase = read_passphrase("Credentials passphrase: ")?;
+        return Vault::open(path, &passphrase);
+    }
+    println!("Creating {}", path.display());
+    let passphrase = read_passphrase("New credentials passphrase: ")?;
+    if env_passphrase().is_none() {
+        let confirmed = Zeroizing::new(prompt_secret("Repeat the passphrase: ")?);
+        if *confirmed != *passphrase {
+            return Err(MintError::from_str("Passphrases don't match".to_string()).into());
+        }
+    }
+    Vault::create(path, &passphrase, KdfParams::default())
+}
+
+fn run_command(file: PathBuf, command: &str, name: Option<&String>) -> Result<()> {
+    match (command, name) {
+        ("list", None) => {
+            for name in Vault::names(&file)? {
+                println!("{}", name);
+            }
+        }
+        ("add", Some(name)) => {
+            let vault = open_or_create(&file)?;
+            if vault.get(name)?.is_some() {
+                return Err(MintError::from_str(format!("{} already exists, use rotate to change its keys", name)).into());
+            }
+            vault.put(name, &prompt_secrets()?)?;
+            println!("Added {}", name);
+        }
+        ("rotate", Some(name)) => {
+            let passphrase = read_passphrase("Credentials passphrase: ")?;
+            let vault = Vault::open(&file, &passphrase)?;
+            if vault.get(name)?.is_none() {
+                return Err(MintError::from_str(format!("No entry named {}", name)).into());
+            }
+            vault.put(name, &prompt_secrets()?)?;
+            println!("Rotated the keys of {}, send SIGHUP or call /admin/reload to use them", name);
+        }
+        _ => {
+            println!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+    Ok(())
+}
+
+/// Entry point for `kucoin_arb credentials ...`.
+pub fn run(args: &[String]) -> Result<()> {
+    let mut file = PathBuf::from(DEFAULT_CREDENTIALS_PATH);
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--file" {
+            match args.next() {
+                Some(path) => file = PathBuf::from(path),
+                None => return run_command(file, "", None),
+            }
+        } else {
+            rest.push(arg);
+        }
+    }
+    match rest.as_slice() {
+        [command] => run_command(file, command, None),
+        [command, name] => run_command(file, command, Some(*name)),
+        _ => run_command(file, "", None),
+    }
+}
@@ -0,0 +1,288 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::error::error::MintError;
+
+const FILE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const PASSPHRASE_ENV: &str = "KUCOIN_ARB_PASSPHRASE";
+// Sealed with the key on creation so a wrong passphrase is caught even when there are no entries.
+const CHECK_NAME: &str = "kucoin_arb";
+const CHECK_PLAINTEXT: &[u8] = b"kucoin_arb credentials";
+
+fn fail(msg: String) -> anyhow::Error {
+    MintError::from_str(msg).into()
+}
+
+/// Argon2id cost parameters, kept in the file so they can be raised without breaking old files.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct KdfParams {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            m_cost: 19 * 1024,
+            t_cost: 2,
+            p_cost: 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop, Clone, PartialEq)]
+pub struct Secrets {
+    pub api_key: String,
+    pub api_secret: String,
+    pub api_pass: String,
+}
+
+impl std::fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Secrets")
+            .field("api_key", &"<redacted>")
+            .field("api_secret", &"<redacted>")
+            .field("api_pass", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SealedEntry {
+    name: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CredentialFile {
+    version: u32,
+    salt: String,
+    kdf: KdfParams,
+    check: SealedEntry,
+    entries: Vec<SealedEntry>,
+}
+
+fn decode(field: &str, val: &str) -> Result<Vec<u8>> {
+    base64::decode(val).with_context(|| format!("Credentials file has a malformed {}", field))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], kdf: KdfParams) -> Result<Zeroizing<[u8; 32]>> {
+    let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32))
+        .map_err(|e| fail(format!("Bad key derivation parameters: {}", e)))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+        .map_err(|e| fail(format!("Could not derive the credentials key: {}", e)))?;
+    Ok(key)
+}
+
+/// Encrypted credentials file. Entry names are stored in the clear, the keys of each entry are
+/// sealed with XChaCha20-Poly1305 using the name as associated data, so entries can't be swapped.
+pub struct Vault {
+    path: PathBuf,
+    salt: Vec<u8>,
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl Vault {
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(self.key.as_ref().into())
+    }
+    fn seal(&self, name: &str, plaintext: &[u8]) -> Result<SealedEntry> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher()
+            .encrypt(&nonce, Payload { msg: plaintext, aad: name.as_bytes() })
+            .map_err(|_| fail(format!("Could not encrypt credentials for {}", name)))?;
+        Ok(SealedEntry {
+            name: name.to_string(),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext),
+        })
+    }
+    fn open_entry(&self, entry: &SealedEntry) -> Result<Zeroizing<Vec<u8>>> {
+        let nonce = decode("nonce", &entry.nonce)?;
+        if nonce.len() != 24 {
+            return Err(fail(format!("Credentials for {} have a malformed nonce", entry.name)));
+        }
+        let ciphertext = decode("ciphertext", &entry.ciphertext)?;
+        let plaintext = self.cipher()
+            .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: entry.name.as_bytes() })
+            .map_err(|_| fail(format!("Could not decrypt credentials for {}, wrong passphrase or a tampered file", entry.name)))?;
+        Ok(Zeroizing::new(plaintext))
+    }
+    fn read_file(path: &Path) -> Result<CredentialFile> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read credentials file {}", path.display()))?;
+        let file: CredentialFile = serde_json::from_str(&json)
+            .with_context(|| format!("Credentials file {} is malformed", path.display()))?;
+        if file.version != FILE_VERSION {
+            return Err(fail(format!("Credentials file {} has unsupported version {}", path.display(), file.version)));
+        }
+        Ok(file)
+    }
+    fn load(&self) -> Result<CredentialFile> {
+        let file = Vault::read_file(&self.path)?;
+        if decode("salt", &file.salt)? != self.salt {
+            return Err(fail(format!("Credentials file {} was re-created with another passphrase, restart to unlock it", self.path.display())));
+        }
+        Ok(file)
+    }
+    fn save(&self, file: &CredentialFile) -> Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        {
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut tmp = options.open(&tmp_path)
+                .with_context(|| format!("Could not write {}", tmp_path.display()))?;
+            std::io::Write::write_all(&mut tmp, serde_json::to_string_pretty(file)?.as_bytes())?;
+        }
+        std::fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Could not replace credentials file {}", self.path.display()))?;
+        Ok(())
+    }
+    /// Creates an empty file, failing if one already exists.
+    pub fn create(path: &Path, passphrase: &str, kdf: KdfParams) -> Result<Vault> {
+        if path.exists() {
+            return Err(fail(format!("Credentials file {} already exists", path.display())));
+        }
+        if passphrase.trim().is_empty() {
+            return Err(fail("The credentials passphrase must not be empty".to_string()));
+        }
+        let mut salt = vec![0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let vault = Vault {
+            path: path.to_path_buf(),
+            key: derive_key(passphrase, &salt, kdf)?,
+            salt,
+        };
+        vault.save(&CredentialFile {
+            version: FILE_VERSION,
+            salt: base64::encode(&vault.salt),
+            kdf,
+            check: vault.seal(CHECK_NAME, CHECK_PLAINTEXT)?,
+            entries: Vec::new(),
+        })?;
+        Ok(vault)
+    }
+    pub fn open(path: &Path, passphrase: &str) -> Result<Vault> {
+        let file = Vault::read_file(path)?;
+        let salt = decode("salt", &file.salt)?;
+        let vault = Vault {
+            path: path.to_path_buf(),
+            key: derive_key(passphrase, &salt, file.kdf)?,
+            salt,
+        };
+        vault.open_entry(&file.check)
+            .map_err(|_| fail(format!("Wrong passphrase for credentials file {}", path.display())))?;
+        Ok(vault)
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Entry names, readable without the passphrase.
+    pub fn names(path: &Path) -> Result<Vec<String>> {
+        Ok(Vault::read_file(path)?.entries.into_iter().map(|entry| entry.name).collect())
+    }
+    pub fn get(&self, name: &str) -> Result<Option<Secrets>> {
+        let file = self.load()?;
+        match file.entries.iter().find(|entry| entry.name == name) {
+            Some(entry) => {
+                let plaintext = self.open_entry(entry)?;
+                Ok(Some(serde_json::from_slice(&plaintext)?))
+            }
+            None => Ok(None)
+        }
+    }
+    /// Adds the entry or replaces the one with the same name.
+    pub fn put(&self, name: &str, secrets: &Secrets) -> Result<()> {
+        self.put_all(&[(name.to_string(), secrets.clone())])
+    }
+    /// Adds or replaces all the given entries with a single write.
+    pub fn put_all(&self, entries: &[(String, Secrets)]) -> Result<()> {
+        let mut file = self.load()?;
+        for (name, secrets) in entries {
+            let plaintext = Zeroizing::new(serde_json::to_vec(secrets)?);
+            let sealed = self.seal(name, &plaintext)?;
+            match file.entries.iter_mut().find(|entry| entry.name == *name) {
+                Some(entry) => *entry = sealed,
+                None => file.entries.push(sealed)
+            }
+        }
+        self.save(&file)
+    }
+}
+
+/// The passphrase in `KUCOIN_ARB_PASSPHRASE`, an empty value counts as unset.
+pub fn env_passphrase() -> Option<Zeroizing<String>> {
+    std::env::var(PASSPHRASE_ENV).ok()
+        .filter(|passphrase| !passphrase.is_empty())
+        .map(Zeroizing::new)
+}
+
+/// Reads the passphrase from `KUCOIN_ARB_PASSPHRASE`, prompting on the terminal when it isn't set.
+pub fn read_passphrase(prompt: &str) -> Result<Zeroizing<String>> {
+    if let Some(passphrase) = env_passphrase() {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password(prompt)
+        .with_context(|| format!("Could not read the passphrase, set {} when there is no terminal", PASSPHRASE_ENV))?;
+    Ok(Zeroizing::new(passphrase))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::credentials::credentials::{KdfParams, Secrets, Vault};
+
+    fn cheap_kdf() -> KdfParams {
+        KdfParams { m_cost: 64, t_cost: 1, p_cost: 1 }
+    }
+
+    fn secrets(key: &str) -> Secrets {
+        Secrets {
+            api_key: key.to_string(),
+            api_secret: "very secret".to_string(),
+            api_pass: "pass phrase".to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trips_and_rejects_a_wrong_passphrase() {
+        let path = std::env::temp_dir().join(format!("kucoin_arb_vault_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let vault = Vault::create(&path, "correct horse", cheap_kdf()).unwrap();
+        vault.put("a", &secrets("key-a")).unwrap();
+        vault.put("b", &secrets("key-b")).unwrap();
+        vault.put("a", &secrets("key-a2")).unwrap();
+        assert_eq!(Vault::names(&path).unwrap(), vec!["a".to_string(), "b".to_string()]);
+
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(!json.contains("very secret"));
+
+        let reopened = Vault::open(&path, "correct horse").unwrap();
+        assert_eq!(reopened.get("a").unwrap(), Some(secrets("key-a2")));
+        assert_eq!(reopened.get("c").unwrap(), None);
+        assert!(Vault::open(&path, "wrong").is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(Vault::create(&path, "", cheap_kdf()).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn debug_never_prints_secrets() {
+        let printed = format!("{:?}", secrets("key"));
+        assert!(!printed.contains("very secret"));
+        assert!(!printed.contains("pass phrase"));
+    }
+}
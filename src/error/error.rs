@@ -1,8 +1,8 @@
-use crate::error::error::other_err::{KucoinApiError, KucoinClientError, KucoinErrorKind, MiscError};
+use kucoin_rs_custom::kucoin::error::APIError;
 
-pub mod other_err {
-    use kucoin_rs_custom::kucoin::error::APIError;
+use crate::error::error::other_err::MiscError;
 
+pub mod other_err {
     #[derive(thiserror::Error, Debug)]
     #[error("{:?}", msg)]
     pub struct MiscError {
@@ -20,67 +20,6 @@ pub mod other_err {
             format!("{}", self.msg)
         }
     }
-
-    #[derive(Debug)]
-    pub struct KucoinApiError {
-        e: kucoin_rs_custom::kucoin::error::APIError
-    }
-
-    impl std::fmt::Display for KucoinApiError {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            let fmt = match &self.e {
-                APIError::Serde(e) => format!("{}", e),
-                APIError::Websocket(e) => format!("{}", e),
-                APIError::HTTP(e) => format!("{}", e),
-                APIError::Other(e) => format!("{}", e),
-            };
-            write!(f, "{}", fmt)
-        }
-    }
-
-    impl std::error::Error for KucoinApiError {}
-
-    impl From<APIError> for KucoinApiError {
-        fn from(e: APIError) -> Self {
-            KucoinApiError { e }
-        }
-    }
-
-    #[derive(Debug)]
-    pub struct KucoinClientError {
-        e: kucoin_rs_custom::failure::Error
-    }
-
-    impl std::fmt::Display for KucoinClientError {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f, "{}", self.e)
-        }
-    }
-
-    impl std::error::Error for KucoinClientError {}
-
-    impl From<kucoin_rs_custom::failure::Error> for KucoinClientError {
-        fn from(e: kucoin_rs_custom::failure::Error) -> Self {
-            KucoinClientError { e }
-        }
-    }
-
-    pub enum KucoinErrorKind {
-        Api(APIError),
-        Client(kucoin_rs_custom::failure::Error),
-    }
-
-    impl Into<KucoinErrorKind> for APIError {
-        fn into(self) -> KucoinErrorKind {
-            KucoinErrorKind::Api(self)
-        }
-    }
-
-    impl Into<KucoinErrorKind> for kucoin_rs_custom::failure::Error {
-        fn into(self) -> KucoinErrorKind {
-            KucoinErrorKind::Client(self)
-        }
-    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -98,28 +37,26 @@ pub enum MintError {
     ParseIntError(#[from] std::num::ParseIntError),
 
     #[error(transparent)]
-    KucoinApiError(#[from] KucoinApiError),
-
-    #[error(transparent)]
-    KucoinClientError(#[from] KucoinClientError),
+    KucoinApiError(#[from] APIError),
 
     #[error(transparent)]
     Other(#[from] MiscError),
 
     #[error(transparent)]
     SystemTimeError(#[from] std::time::SystemTimeError),
+
+    /// Placing an order failed in a way that doesn't say whether the exchange took it, and looking it up failed too.
+    #[error("Order {client_oid} may have been placed: {reason}")]
+    OrderOutcomeUnknown { client_oid: String, reason: String },
+    /// An order of a batch whose request failed and that the exchange doesn't have, it can be sent again.
+    #[error("Order {client_oid} of a failed batch was not placed")]
+    OrderNotPlaced { client_oid: String },
 }
 
 impl MintError {
     pub fn from_str(msg: String) -> Self {
         MintError::Other(MiscError::from(msg))
     }
-    pub fn from_kucoin_err(e: KucoinErrorKind) -> Self {
-        match e {
-            KucoinErrorKind::Api(e) => MintError::KucoinApiError(KucoinApiError::from(e)),
-            KucoinErrorKind::Client(e) => MintError::KucoinClientError(KucoinClientError::from(e))
-        }
-    }
     pub fn get_fmt_error(&mut self) -> String {
         match self {
             MintError::Serde(e) => format!("{}", e),
@@ -128,8 +65,9 @@ impl MintError {
             MintError::Other(e) => format!("{}", e.get_fmt_error()),
             MintError::ParseIntError(e) => format!("{}", e),
             MintError::KucoinApiError(e) => format!("{}", e),
-            MintError::KucoinClientError(e) => format!("{}", e),
-            MintError::SystemTimeError(e) => format!("{}", e)
+            MintError::SystemTimeError(e) => format!("{}", e),
+            MintError::OrderOutcomeUnknown { client_oid, reason } => format!("Order {} may have been placed: {}", client_oid, reason),
+            MintError::OrderNotPlaced { client_oid } => format!("Order {} of a failed batch was not placed", client_oid)
         }
     }
 }
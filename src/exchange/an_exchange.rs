@@ -5,13 +5,14 @@ use crate::exchange::order::{Order, OrderSide};
 
 #[async_trait::async_trait]
 pub trait AnExchange {
-    async fn new(api_credentials: ApiCredentials) -> Self;
+    async fn new(api_credentials: ApiCredentials) -> Result<Self> where Self: Sized;
     async fn refresh(&mut self) -> Result<()>;
-    async fn limit_order<S>(&mut self, symbol: S, quantity: S, price: S, side: OrderSide) -> Result<Order>
+    /// `client_oid` should stay the same when an order is retried so a retry can look up whether the last attempt went through.
+    async fn limit_order<S>(&self, client_oid: S, symbol: S, quantity: S, price: S, side: OrderSide) -> Result<Order>
         where S: Into<String> + Send;
-    async fn market_order<S>(&mut self, symbol: S, quantity: S, side: OrderSide) -> Result<Order>
+    async fn market_order<S>(&self, client_oid: S, symbol: S, quantity: S, side: OrderSide) -> Result<Order>
         where S: Into<String> + Send;
-    async fn cancel_open_orders<S>(&mut self, symbol: S) -> Result<()>
+    async fn cancel_open_orders<S>(&self, symbol: S) -> Result<()>
         where S: Into<String> + Send;
 }
 
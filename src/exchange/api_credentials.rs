@@ -1,16 +1,137 @@
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
 
-static SETTINGS_JSON: &'static str = include_str!("../settings.json");
+use crate::config::config::{check_auto_fund_cap, check_balance_perc, check_take_profit_perc};
+use crate::credentials::credentials::Secrets;
+use crate::error::error::MintError;
+use crate::risk::risk::check_quote_limit;
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct ApiCredentials {
     pub(crate) name: String,
+    /// Left out of the config file when the keys are kept in the encrypted credentials file.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub(crate) api_key: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub(crate) api_secret: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub(crate) api_pass: String,
+    /// Overrides of the fleet wide settings, unset fields fall back to the top level config.
+    #[serde(default)]
+    pub(crate) take_profit_perc: Option<f32>,
+    #[serde(default)]
+    pub(crate) balance_perc: Option<f32>,
+    /// Most quote currency spent on a single buy.
+    #[serde(default)]
+    pub(crate) max_quote_per_trade: Option<f32>,
+    /// Most positions held at once. A buy counts until its take profit has filled or was cancelled.
+    #[serde(default)]
+    pub(crate) max_open_positions: Option<usize>,
+    #[serde(default)]
+    pub(crate) max_quote_per_day: Option<f32>,
+    /// Most quote held in the open positions of one token.
+    #[serde(default)]
+    pub(crate) max_exposure_per_token: Option<f32>,
+    /// Only these tokens are bought when set.
+    #[serde(default)]
+    pub(crate) allowed_symbols: Option<Vec<String>>,
+    #[serde(default)]
+    pub(crate) denied_symbols: Vec<String>,
+    #[serde(default)]
+    pub(crate) auto_fund_cap: Option<f32>,
+    #[serde(default)]
+    pub(crate) sweep_to_main: Option<bool>,
+    /// Simulated accounts go through every step but never send an order to Kucoin.
+    #[serde(default)]
+    pub(crate) simulated: bool,
 }
 
-pub fn load_api_credentials() -> Vec<ApiCredentials> {
-    // let file_str = fs::read_to_string("../settings.json").expect("No settings.json file found!");
-    serde_json::from_str::<Vec<ApiCredentials>>(&SETTINGS_JSON).expect("Unable to deserialize settings.json!")
+impl Drop for ApiCredentials {
+    fn drop(&mut self) {
+        self.api_key.zeroize();
+        self.api_secret.zeroize();
+        self.api_pass.zeroize();
+    }
+}
+
+impl std::fmt::Debug for ApiCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiCredentials")
+            .field("name", &self.name)
+            .field("api_key", &"<redacted>")
+            .field("api_secret", &"<redacted>")
+            .field("api_pass", &"<redacted>")
+            .field("simulated", &self.simulated)
+            .finish()
+    }
+}
+
+fn invalid(msg: String) -> anyhow::Error {
+    MintError::from_str(msg).into()
+}
+
+impl ApiCredentials {
+    pub fn validate(&self) -> Result<()> {
+        let fields = [
+            ("name", &self.name),
+            ("api_key", &self.api_key),
+            ("api_secret", &self.api_secret),
+            ("api_pass", &self.api_pass),
+        ];
+        for (field, value) in fields.iter() {
+            if value.trim().is_empty() {
+                return Err(invalid(format!("{} must not be empty", field)));
+            }
+        }
+        if let Some(perc) = self.take_profit_perc {
+            check_take_profit_perc(perc)?;
+        }
+        if let Some(perc) = self.balance_perc {
+            check_balance_perc(perc)?;
+        }
+        if let Some(max) = self.max_quote_per_trade {
+            if !max.is_finite() || max <= 0.0 {
+                return Err(invalid(format!("max_quote_per_trade must be above 0, got {}", max)));
+            }
+        }
+        if let Some(cap) = self.auto_fund_cap {
+            check_auto_fund_cap(cap)?;
+        }
+        check_quote_limit("max_quote_per_day", self.max_quote_per_day)?;
+        check_quote_limit("max_exposure_per_token", self.max_exposure_per_token)?;
+        if self.max_open_positions == Some(0) {
+            return Err(invalid("max_open_positions must be at least 1".to_string()));
+        }
+        if let Some(allowed) = &self.allowed_symbols {
+            if let Some(symbol) = allowed.iter().find(|s| self.denied_symbols.iter().any(|d| d.eq_ignore_ascii_case(s))) {
+                return Err(invalid(format!("{} is both allowed and denied", symbol)));
+            }
+        }
+        Ok(())
+    }
+    pub fn has_secrets(&self) -> bool {
+        !self.api_key.is_empty() || !self.api_secret.is_empty() || !self.api_pass.is_empty()
+    }
+    pub fn secrets(&self) -> Secrets {
+        Secrets {
+            api_key: self.api_key.clone(),
+            api_secret: self.api_secret.clone(),
+            api_pass: self.api_pass.clone(),
+        }
+    }
+    pub fn set_secrets(&mut self, secrets: &Secrets) {
+        self.api_key.clone_from(&secrets.api_key);
+        self.api_secret.clone_from(&secrets.api_secret);
+        self.api_pass.clone_from(&secrets.api_pass);
+    }
+    pub fn clear_secrets(&mut self) {
+        self.api_key.zeroize();
+        self.api_secret.zeroize();
+        self.api_pass.zeroize();
+    }
+    pub fn same_keys(&self, other: &ApiCredentials) -> bool {
+        self.api_key == other.api_key && self.api_secret == other.api_secret && self.api_pass == other.api_pass
+    }
 }
@@ -4,7 +4,7 @@ use anyhow::{Context, Result};
 use kucoin_rs_custom::kucoin::client::{Kucoin, KucoinEnv};
 use kucoin_rs_custom::kucoin::model::market::SymbolList;
 use serde::{Serialize, Deserialize};
-use crate::error::error::MintError;
+use crate::exchange::kucoin::kucoin::DENOMINATION;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KucoinPrecisionInfo {
@@ -39,9 +39,7 @@ pub async fn get_exchange_info_kc() -> Result<HashMap<String, KucoinPrecisionInf
     let client = get_anon_kc_client()?;
     let mut exchange_info_map: HashMap<String, KucoinPrecisionInfo> = HashMap::new();
     let exchange_info = client.get_symbol_list(None).await
-        .map_err(|e| MintError::from_kucoin_err(e.into()))
-        .with_context(|| MintError::from_str(format!("Error getting symbol list from Kucoin.")))?
-        .data.ok_or_else(|| MintError::from_str(format!("Didn't get any data in get_exchange_info_kc!")))?;
+        .context("Error getting symbol list from Kucoin.")?;
     for symbol in exchange_info.iter() {
         let one_symbol = match get_one_symbol_info_kc(symbol) {
             Ok(t) => t,
@@ -58,9 +56,44 @@ pub async fn get_exchange_info_kc() -> Result<HashMap<String, KucoinPrecisionInf
     Ok(exchange_info_map)
 }
 
+/// Env var pointing the bot at another Kucoin API, e.g. a running `kucoin_mock`.
+pub const API_URL_ENV: &str = "KUCOIN_API_URL";
+
+/// The live API unless `KUCOIN_API_URL` is set.
+pub fn get_kc_env() -> KucoinEnv {
+    match std::env::var(API_URL_ENV) {
+        Ok(url) if !url.is_empty() => KucoinEnv::Custom(url),
+        _ => KucoinEnv::Live
+    }
+}
+
 pub fn get_anon_kc_client() -> Result<Kucoin> {
-    let client = Kucoin::new(KucoinEnv::Live, None)
-        .map_err(|e| MintError::from_kucoin_err(e.into()))
+    let client = Kucoin::new(get_kc_env(), None)
         .with_context(|| format!("Failed to get anonymous Kucoin client!"))?;
     Ok(client)
 }
+
+/// Last price of every currency in the denomination, from both `XXX-BTC` and `BTC-XXX` pairs.
+pub async fn get_denomination_prices_kc() -> Result<HashMap<String, f32>> {
+    let client = get_anon_kc_client()?;
+    let tickers = client.get_all_tickers().await
+        .context("Error getting all tickers from Kucoin.")?;
+    let mut prices: HashMap<String, f32> = HashMap::new();
+    for tick in tickers.ticker.iter() {
+        let last = match tick.last.parse::<f32>() {
+            Ok(t) if t > 0.0 => t,
+            _ => continue
+        };
+        match tick.symbol.split_once('-') {
+            Some((base, quote)) if quote == DENOMINATION => {
+                prices.insert(base.to_string(), last);
+            }
+            Some((base, quote)) if base == DENOMINATION => {
+                prices.entry(quote.to_string()).or_insert(1.0 / last);
+            }
+            _ => {}
+        }
+    }
+    prices.insert(DENOMINATION.to_string(), 1.0);
+    Ok(prices)
+}
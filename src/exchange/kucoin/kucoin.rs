@@ -1,16 +1,17 @@
 use std::collections::HashMap;
 
 use anyhow::{Context, Result};
-use kucoin_rs_custom::kucoin::client::{Credentials, Kucoin, KucoinEnv};
-use kucoin_rs_custom::kucoin::model::APIDatum;
+use kucoin_rs_custom::kucoin::client::{Credentials, Kucoin};
+use kucoin_rs_custom::kucoin::error::APIError;
+use kucoin_rs_custom::kucoin::model::trade::{Side, TradeType};
 use kucoin_rs_custom::kucoin::model::user::AccountType;
+use kucoin_rs_custom::kucoin::trade::{BatchOrder, MAX_BATCH_ORDERS};
 
 use crate::error::error::MintError;
 use crate::exchange::an_exchange::AnExchange;
 use crate::exchange::api_credentials::ApiCredentials;
-use crate::exchange::kucoin::exchange_info::{get_exchange_info_kc, KucoinPrecisionInfo};
+use crate::exchange::kucoin::exchange_info::{get_exchange_info_kc, get_kc_env, KucoinPrecisionInfo};
 use crate::exchange::order::*;
-use crate::utils::time::get_ms_str;
 
 pub struct KucoinExchange {
     pub account: Kucoin,
@@ -19,25 +20,184 @@ pub struct KucoinExchange {
 
 pub const DENOMINATION: &'static str = "BTC";
 
+fn account_name(account: &AccountType) -> &'static str {
+    match account {
+        AccountType::Main => "main",
+        AccountType::Trade => "trade",
+        AccountType::Margin => "margin"
+    }
+}
+
+fn kucoin_side(side: &OrderSide) -> Side {
+    match side {
+        OrderSide::Buy => Side::Buy,
+        OrderSide::Sell => Side::Sell
+    }
+}
+
 impl KucoinExchange {
-    pub async fn get_balance_of(&mut self, symbol: &str) -> Result<f32> {
+    /// A client for the same account with other keys. It draws from this client's rate limit buckets
+    /// and signs with its clock estimate, so new keys neither refill the buckets nor forget a 429.
+    pub fn reconnect(&self, api_credentials: &ApiCredentials) -> Result<KucoinExchange> {
+        let mut account = Kucoin::new(get_kc_env(), Some(Credentials::new(&api_credentials.api_key, &api_credentials.api_secret, &api_credentials.api_pass)))?;
+        account.set_rate_limiter(self.account.rate_limiter().clone());
+        account.set_time_sync(self.account.time_sync().clone());
+        Ok(KucoinExchange {
+            account,
+            exchange_info: self.exchange_info.clone()
+        })
+    }
+    pub async fn get_balance_of(&self, symbol: &str) -> Result<f32> {
         let bal = self.account.get_transferable_balance(symbol, AccountType::Trade)
-            .await.map_err(|e| MintError::from_kucoin_err(e.into()))
-            .context(format!("Failed to get Kucoin transferable balance!"))?;
-        Ok(KucoinExchange::unwrap_data(bal)?.balance.parse::<f32>()?)
+            .await.with_context(|| format!("Failed to get Kucoin transferable {} balance!", symbol))?;
+        Ok(bal.balance.parse::<f32>()?)
     }
-    pub async fn get_denomination_balance(&mut self) -> Result<f32> {
+    pub async fn get_denomination_balance(&self) -> Result<f32> {
         self.get_balance_of(DENOMINATION).await
     }
-    pub fn unwrap_data<T>(res: APIDatum<T>) -> Result<T> {
-        let msg = &res.msg;
-        Ok(res.data.ok_or_else(|| {
-            if let Some(msg) = msg {
-                MintError::from_str(format!("Error in unwrap_data!\n{}", msg.clone()))
-            } else {
-                MintError::from_str(format!("Didn't get any data in unwrap_data!"))
+    pub async fn get_main_denomination_balance(&self) -> Result<f32> {
+        let bal = self.account.get_transferable_balance(DENOMINATION, AccountType::Main)
+            .await.context("Failed to get Kucoin Main account balance!")?;
+        Ok(bal.balance.parse::<f32>()?)
+    }
+    /// Moves `amount` of the denomination between the user's own accounts. The amount is rounded
+    /// down to the 8 decimals Kucoin accepts so it never asks for more than is there.
+    pub async fn transfer_denomination(&self, client_oid: &str, amount: f32, from: AccountType, to: AccountType) -> Result<()> {
+        let amount = format!("{:.8}", (amount as f64 * 1e8).floor() / 1e8);
+        self.account.inner_transfer(client_oid, DENOMINATION, account_name(&from), account_name(&to), &amount)
+            .await.context(format!("Failed to move {} {} from {} to {}!", &amount, DENOMINATION, account_name(&from), account_name(&to)))?;
+        Ok(())
+    }
+    /// Everything held in the Main and Trade accounts valued in the denomination. Currencies
+    /// without a price in `prices` are left out.
+    pub async fn get_equity(&self, prices: &HashMap<String, f32>) -> Result<f32> {
+        let accounts = self.account.get_accounts_list(None, None)
+            .await.context("Failed to get Kucoin accounts!")?;
+        let mut equity = 0.0;
+        for account in accounts.iter().filter(|a| a.r#type == "main" || a.r#type == "trade") {
+            if let Some(price) = prices.get(&account.currency) {
+                equity += account.balance.parse::<f32>()? * price;
+            }
+        }
+        Ok(equity)
+    }
+    /// Currencies other than the denomination available in the Trade account.
+    pub async fn get_trade_holdings(&self) -> Result<Vec<(String, f32)>> {
+        let accounts = self.account.get_accounts_list(None, Some("trade"))
+            .await.context("Failed to get Kucoin trade accounts!")?;
+        let mut holdings = Vec::new();
+        for account in accounts.iter().filter(|a| a.currency != DENOMINATION) {
+            let available = account.available.parse::<f32>()?;
+            if available > 0.0 {
+                holdings.push((account.currency.clone(), available));
+            }
+        }
+        Ok(holdings)
+    }
+    pub async fn market_sell_size(&self, client_oid: &str, symbol: &str, size: &str) -> Result<Order> {
+        let order_res = self.account.post_market_order(
+            client_oid,
+            symbol,
+            Side::Sell,
+            Some(size.parse::<f32>()?),
+            None,
+            None,
+        ).await.context(format!("Failed to market sell {} {}!", size, symbol))?;
+        Ok(Order {
+            symbol: symbol.to_string(),
+            order_id: order_res.order_id,
+            kind: Some(OrderKind::Market),
+            side: Some(OrderSide::Sell),
+            health: 5,
+            alive: false,
+            simulated: false,
+            cost: 0.0
+        })
+    }
+    /// The order placed with `client_oid`, `None` when Kucoin has no such order.
+    pub async fn find_order(&self, client_oid: &str) -> Result<Option<Order>> {
+        let info = self.account.get_order_by_client_oid(client_oid)
+            .await.context(format!("Failed to look up Kucoin order {}!", client_oid))?;
+        Ok(info.map(|info| Order {
+            symbol: info.symbol,
+            order_id: info.id,
+            kind: Some(if info.r#type == "market" { OrderKind::Market } else { OrderKind::Limit }),
+            side: Some(if info.side == "buy" { OrderSide::Buy } else { OrderSide::Sell }),
+            health: 5,
+            alive: true,
+            simulated: false,
+            cost: 0.0
+        }))
+    }
+    /// Looks up the orders of a batch whose request failed. `None` when none of them went through,
+    /// otherwise a result per request in which the ones Kucoin doesn't have are errors.
+    pub async fn find_orders(&self, requests: &[LimitOrderRequest]) -> Result<Option<Vec<Result<Order>>>> {
+        let mut found = Vec::new();
+        for request in requests {
+            found.push(self.find_order(&request.client_oid).await?);
+        }
+        if found.iter().all(|order| order.is_none()) {
+            return Ok(None);
+        }
+        Ok(Some(requests.iter().zip(found).map(|(request, order)| order.ok_or_else(|| {
+            MintError::OrderNotPlaced { client_oid: request.client_oid.clone() }.into()
+        })).collect()))
+    }
+    /// Quote received for a sell order once it is done, `None` while it is still open.
+    pub async fn get_sell_proceeds(&self, order_id: &str) -> Result<Option<f32>> {
+        let info = self.account.get_order(order_id)
+            .await.context(format!("Failed to get Kucoin order {}!", order_id))?;
+        if info.is_active.unwrap_or(true) {
+            return Ok(None);
+        }
+        let mut proceeds = info.deal_funds.parse::<f32>()?;
+        if info.fee_currency == DENOMINATION {
+            proceeds -= info.fee.parse::<f32>()?;
+        }
+        Ok(Some(proceeds.max(0.0)))
+    }
+    /// Places up to `MAX_BATCH_ORDERS` limit orders on `symbol` in one request. Fails only when
+    /// the request does, otherwise each order gets its own result, in the order given.
+    pub async fn limit_orders(&self, symbol: &str, requests: &[LimitOrderRequest]) -> Result<Vec<Result<Order>>> {
+        if requests.len() > MAX_BATCH_ORDERS {
+            return Err(MintError::from_str(format!("Kucoin takes at most {} orders per batch, got {}", MAX_BATCH_ORDERS, requests.len())).into());
+        }
+        let batch: Vec<BatchOrder> = requests.iter()
+            .map(|r| BatchOrder::new(&r.client_oid, kucoin_side(&r.side), &r.price, &r.quantity))
+            .collect();
+        let results = self.account.post_multi_orders(symbol, &batch)
+            .await.context(format!("Failed to create {} Kucoin limit orders on {}!", batch.len(), symbol))?;
+        Ok(requests.iter().map(|request| {
+            let result = results.iter().find(|r| r.client_oid == request.client_oid)
+                .ok_or_else(|| MintError::from_str(format!("Kucoin sent no result for order {}", &request.client_oid)))?;
+            match &result.id {
+                Some(id) if result.is_success() => Ok(Order {
+                    symbol: symbol.to_string(),
+                    order_id: id.clone(),
+                    kind: Some(OrderKind::Limit),
+                    side: Some(request.side.clone()),
+                    health: 5,
+                    alive: true,
+                    simulated: false,
+                    cost: 0.0
+                }),
+                _ => {
+                    let reason = result.error().unwrap_or_else(|| APIError::Other(String::from("no order id")));
+                    Err(anyhow::Error::from(MintError::from(reason))
+                        .context(format!("Failed to create Kucoin limit {} order on {}!", &request.side, symbol)))
+                }
             }
-        })?)
+        }).collect())
+    }
+    pub async fn cancel_all_open_orders(&self) -> Result<()> {
+        self.account.cancel_all_orders(None, Some(TradeType::Trade))
+            .await.context("Failed to cancel Kucoin open orders!")?;
+        Ok(())
+    }
+    pub async fn cancel_all_stop_orders(&self) -> Result<()> {
+        self.account.cancel_all_stop_orders(None, Some(TradeType::Trade), None)
+            .await.context("Failed to cancel Kucoin stop orders!")?;
+        Ok(())
     }
     pub fn round_to_sig(price: f32, sig_digits: i8) -> Result<String> {
         let res_chars: Vec<char> = price.to_string().chars().collect();
@@ -57,16 +217,16 @@ impl KucoinExchange {
         let val: String = new_arr.iter().collect();
         Ok(val)
     }
-    pub fn round_to_sig_digits_price(&mut self, symbol: &String, val: f32) -> Result<String> {
+    pub fn round_to_sig_digits_price(&self, symbol: &String, val: f32) -> Result<String> {
         let price_sig_digits = self.exchange_info
-            .get_mut(symbol)
+            .get(symbol)
             .ok_or_else(|| MintError::from_str(format!("Could not get info for symbol {}", symbol)))?
             .price_sig_digs;
         Ok(KucoinExchange::round_to_sig(val, price_sig_digits)?)
     }
-    pub fn round_to_sig_digits_base(&mut self, symbol: &String, val: f32) -> Result<String> {
+    pub fn round_to_sig_digits_base(&self, symbol: &String, val: f32) -> Result<String> {
         let quantity_sig_digits = self.exchange_info
-            .get_mut(symbol)
+            .get(symbol)
             .ok_or_else(|| MintError::from_str(format!("Could not get info for symbol {}", symbol)))?
             .base_sig_digs;
         Ok(KucoinExchange::round_to_sig(val, quantity_sig_digits)?)
@@ -75,19 +235,17 @@ impl KucoinExchange {
 
 #[async_trait::async_trait]
 impl AnExchange for KucoinExchange {
-    async fn new(api_credentials: ApiCredentials) -> Self {
-        let exchange_info = match get_exchange_info_kc().await {
-            Ok(t) => t,
-            Err(e) => panic!("{}", e)
-        };
-        let account = match Kucoin::new(KucoinEnv::Live, Some(Credentials::new(&api_credentials.api_key, &api_credentials.api_secret, &api_credentials.api_pass))) {
-            Ok(t) => t,
-            Err(e) => panic!("{}", MintError::from_kucoin_err(e.into()).get_fmt_error())
-        };
-        KucoinExchange {
+    async fn new(api_credentials: ApiCredentials) -> Result<Self> {
+        let exchange_info = get_exchange_info_kc().await?;
+        let account = Kucoin::new(get_kc_env(), Some(Credentials::new(&api_credentials.api_key, &api_credentials.api_secret, &api_credentials.api_pass)))
+            .context("Failed to create the Kucoin client!")?;
+        if let Err(e) = account.sync_time().await {
+            println!("Could not sync with the Kucoin clock, signing with the local one: {}", e);
+        }
+        Ok(KucoinExchange {
             account,
             exchange_info
-        }
+        })
     }
 
     async fn refresh(&mut self) -> Result<()> {
@@ -95,70 +253,64 @@ impl AnExchange for KucoinExchange {
         Ok(())
     }
 
-    async fn limit_order<S>(&mut self, symbol: S, quantity: S, price: S, side: OrderSide) -> Result<Order>
+    async fn limit_order<S>(&self, client_oid: S, symbol: S, quantity: S, price: S, side: OrderSide) -> Result<Order>
         where S: Into<String> + Send
     {
         let kind = OrderKind::Limit;
-        let side_text = match &side {
-            OrderSide::Buy => "buy",
-            OrderSide::Sell => "sell"
-        };
         let symbol = symbol.into();
         let quantity = quantity.into();
         let price = price.into();
         let order_res = self.account.post_limit_order(
-            &get_ms_str()?,
+            &client_oid.into(),
             &symbol,
-            side_text,
+            kucoin_side(&side),
             &*price,
             &*quantity,
             None,
-        ).await.map_err(|e| MintError::from_kucoin_err(e.into()))
-            .context(format!("Failed to create Kucoin limit {} order!", &side))?;
-        let order_id = KucoinExchange::unwrap_data(order_res)?.order_id;
+        ).await.context(format!("Failed to create Kucoin limit {} order!", &side))?;
+        let order_id = order_res.order_id;
         Ok(Order {
             symbol: symbol.clone(),
             order_id: String::from(order_id),
             kind: Some(kind),
             side: Some(side),
             health: 5,
-            alive: true
+            alive: true,
+            simulated: false,
+            cost: 0.0
         })
     }
 
-    async fn market_order<S>(&mut self, symbol: S, funds: S, side: OrderSide) -> Result<Order>
+    async fn market_order<S>(&self, client_oid: S, symbol: S, funds: S, side: OrderSide) -> Result<Order>
         where S: Into<String> + Send
     {
         let kind = OrderKind::Market;
-        let side_text = match &side {
-            OrderSide::Buy => "buy",
-            OrderSide::Sell => "sell"
-        };
         let symbol = symbol.into();
         let funds = funds.into();
         let order_res = self.account.post_market_order(
-            &get_ms_str()?,
+            &client_oid.into(),
             &symbol,
-            side_text,
+            kucoin_side(&side),
             None,
             Some(funds),
             None,
-        ).await.map_err(|e| MintError::from_kucoin_err(e.into()))
-            .context(format!("Failed to create Kucoin limit {} order!", &side))?;
-        let order_id = KucoinExchange::unwrap_data(order_res)?.order_id;
+        ).await.context(format!("Failed to create Kucoin limit {} order!", &side))?;
+        let order_id = order_res.order_id;
         Ok(Order {
             symbol,
             order_id: String::from(order_id),
             kind: Some(kind),
             side: Some(side),
             health: 5,
-            alive: true
+            alive: true,
+            simulated: false,
+            cost: 0.0
         })
     }
 
-    async fn cancel_open_orders<S>(&mut self, symbol: S) -> Result<()> where S: Into<String> + Send {
-        self.account.cancel_all_orders(Some(&symbol.into()), Some("TRADE"))
-            .await.map_err(|e| MintError::from_kucoin_err(e.into()))?;
+    async fn cancel_open_orders<S>(&self, symbol: S) -> Result<()> where S: Into<String> + Send {
+        self.account.cancel_all_orders(Some(&symbol.into()), Some(TradeType::Trade))
+            .await?;
         Ok(())
     }
 }
@@ -1,3 +1,4 @@
 pub mod kucoin;
 pub mod exchange_info;
 pub mod token_info;
+pub mod server_clock;
@@ -0,0 +1,29 @@
+use anyhow::{Context, Result};
+use kucoin_rs_custom::kucoin::client::Kucoin;
+
+use crate::exchange::kucoin::exchange_info::get_anon_kc_client;
+
+/// Kucoin's clock as seen from here, for timing scheduled signals. Signed requests keep their
+/// own estimate inside each account's client.
+#[derive(Clone)]
+pub struct ServerClock {
+    client: Kucoin,
+}
+
+impl ServerClock {
+    pub fn new() -> Result<Self> {
+        Ok(ServerClock { client: get_anon_kc_client()? })
+    }
+    /// Milliseconds to add to the local clock to get Kucoin's.
+    pub fn offset_ms(&self) -> i64 {
+        self.client.time_sync().offset_ms()
+    }
+    pub fn now_ms(&self) -> i64 {
+        self.client.time_sync().now_ms()
+    }
+    /// Measures the offset again against `get_server_time`.
+    pub async fn sample(&self) -> Result<i64> {
+        self.client.sync_time().await
+            .context("Failed to get Kucoin server time!")
+    }
+}
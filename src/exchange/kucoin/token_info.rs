@@ -1,7 +1,5 @@
 use anyhow::{Context, Result};
 use crate::exchange::kucoin::exchange_info::get_anon_kc_client;
-use crate::error::error::MintError;
-use crate::exchange::kucoin::kucoin::KucoinExchange;
 
 #[derive(Clone)]
 pub struct SymbolInfo {
@@ -14,12 +12,11 @@ impl SymbolInfo {
     pub async fn load_price(symbol_info: SymbolInfo) -> Result<SymbolInfo> {
         let kc = get_anon_kc_client()?;
         let ticker = kc.get_ticker(&symbol_info.symbol_with_pair).await
-            .map_err(|e| MintError::from_kucoin_err(e.into()))
             .context(format!("Could not get symbol price for {}!", &symbol_info.symbol))?;
         Ok(SymbolInfo {
             symbol: symbol_info.symbol,
             symbol_with_pair: symbol_info.symbol_with_pair,
-            price: Some(KucoinExchange::unwrap_data(ticker)?.price.parse::<f32>()?)
+            price: Some(ticker.price.parse::<f32>()?)
         })
     }
 }
@@ -28,6 +28,15 @@ impl std::fmt::Display for OrderSide {
     }
 }
 
+/// One order of a batch, see `KucoinExchange::limit_orders`.
+#[derive(Clone)]
+pub struct LimitOrderRequest {
+    pub client_oid: String,
+    pub quantity: String,
+    pub price: String,
+    pub side: OrderSide,
+}
+
 #[derive(Clone)]
 pub struct Order {
     pub symbol: String,
@@ -36,9 +45,25 @@ pub struct Order {
     pub side: Option<OrderSide>,
     pub health: i8,
     pub alive: bool,
+    /// Never sent to the exchange, see `ApiCredentials::simulated`.
+    pub simulated: bool,
+    /// Quote spent on a buy, set once it went through.
+    pub cost: f32,
 }
 
 impl Order {
+    pub fn new_simulated(symbol: String, kind: OrderKind, side: OrderSide, client_oid: &str) -> Self {
+        Order {
+            symbol,
+            order_id: format!("simulated-{}", client_oid),
+            kind: Some(kind),
+            side: Some(side),
+            health: 5,
+            alive: true,
+            simulated: true,
+            cost: 0.0
+        }
+    }
     pub fn lower_health(&mut self) {
         self.health -= 1;
         if self.health <= 0 {
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use tide::{Request, StatusCode};
+
+use crate::exchange::api_credentials::ApiCredentials;
+use crate::http_server::http_server::{http_ok_json, State, UPSET_SMILEY};
+use crate::risk::kill_switch::KillReport;
+use crate::user::settings::UserSettings;
+use crate::user::user::User;
+
+const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
+
+#[derive(Serialize)]
+struct ReloadRes {
+    users: usize
+}
+
+#[derive(Serialize)]
+struct UserSettingsRes {
+    name: String,
+    settings: UserSettings
+}
+
+#[derive(Deserialize)]
+struct RemoveUserQuery {
+    #[serde(default)]
+    cancel_orders: bool
+}
+
+#[derive(Deserialize)]
+struct RotateKeysReq {
+    api_key: String,
+    api_secret: String,
+    api_pass: String
+}
+
+#[derive(Serialize)]
+struct UserChangedRes {
+    name: String
+}
+
+#[derive(Deserialize)]
+struct KillQuery {
+    #[serde(default)]
+    flatten: bool
+}
+
+#[derive(Deserialize)]
+struct ResetDrawdownQuery {
+    user: Option<String>
+}
+
+#[derive(Serialize)]
+struct AdminErrorRes {
+    error: String
+}
+
+fn admin_error(status: StatusCode, error: String) -> tide::Result {
+    let mut res = http_ok_json(&AdminErrorRes { error })?;
+    res.set_status(status);
+    Ok(res)
+}
+
+// Compares without returning early so response times don't leak how much of the token matched.
+fn tokens_match(expected: &str, given: &str) -> bool {
+    let expected = expected.as_bytes();
+    let given = given.as_bytes();
+    let diff = expected.iter()
+        .zip(given.iter())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+    diff == 0 && expected.len() == given.len()
+}
+
+/// Admin endpoints are disabled unless an `admin_token` is configured.
+pub(crate) fn check_admin(req: &Request<State>) -> tide::Result<()> {
+    let config = req.state().config.get();
+    let authorized = match (&config.admin_token, req.header(ADMIN_TOKEN_HEADER)) {
+        (Some(expected), Some(given)) => tokens_match(expected, given.as_str()),
+        _ => false
+    };
+    if authorized {
+        Ok(())
+    } else {
+        Err(tide::Error::from_str(StatusCode::Unauthorized, UPSET_SMILEY))
+    }
+}
+
+/// Re-reads the config file. Users pick the new settings up once the job in flight is done.
+pub(crate) async fn post_reload_config(req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    match req.state().config.reload() {
+        Ok(config) => http_ok_json(&ReloadRes { users: config.users.len() }),
+        Err(e) => admin_error(StatusCode::BadRequest, format!("{:#}", e))
+    }
+}
+
+/// Effective settings of every configured user, overrides applied. Credentials are never included.
+pub(crate) async fn get_user_settings(req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    let config = req.state().config.get();
+    let users: Vec<UserSettingsRes> = config.users.iter()
+        .map(|cred| UserSettingsRes {
+            name: cred.name.clone(),
+            settings: UserSettings::resolve(&config, cred),
+        })
+        .collect();
+    http_ok_json(&users)
+}
+
+/// Adds an account once Kucoin confirms its keys by returning a balance.
+pub(crate) async fn post_add_user(mut req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    let cred: ApiCredentials = req.body_json().await
+        .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e.to_string()))?;
+    if let Err(e) = cred.validate() {
+        return admin_error(StatusCode::BadRequest, format!("{:#}", e));
+    }
+    let state = req.state().clone();
+    let name = cred.name.clone();
+    if state.user_manager.get(&name).await.is_some() {
+        return admin_error(StatusCode::Conflict, format!("User {} already exists", name));
+    }
+    let config = state.config.get();
+    let new_cred = cred.clone();
+    let user = match state.on_tokio(async move { User::new(new_cred, &config).await }).await? {
+        Ok(user) => user,
+        Err(e) => return admin_error(StatusCode::BadRequest, format!("{:#}", e))
+    };
+    if let Err(e) = state.user_manager.add_user(user).await {
+        return admin_error(StatusCode::Conflict, format!("{:#}", e));
+    }
+    let persisted = state.config.update_users(|users| {
+        users.push(cred);
+        Ok(())
+    });
+    if let Err(e) = persisted {
+        let _ = state.user_manager.remove_user(&name, false).await;
+        return admin_error(StatusCode::InternalServerError, format!("{:#}", e));
+    }
+    http_ok_json(&UserChangedRes { name })
+}
+
+/// Removes an account, `?cancel_orders=true` cancels its open orders first.
+pub(crate) async fn delete_user(req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    let name = req.param("name")?.to_string();
+    let query: RemoveUserQuery = req.query()?;
+    let state = req.state().clone();
+    if state.user_manager.get(&name).await.is_none() {
+        return admin_error(StatusCode::NotFound, format!("No user named {}", name));
+    }
+    let user_manager = state.user_manager.clone();
+    let removed_name = name.clone();
+    let removed = state.on_tokio(async move {
+        user_manager.remove_user(&removed_name, query.cancel_orders).await
+    }).await?;
+    if let Err(e) = removed {
+        return admin_error(StatusCode::BadGateway, format!("{:#}", e));
+    }
+    let persisted = state.config.update_users(|users| {
+        users.retain(|cred| cred.name != name);
+        Ok(())
+    });
+    if let Err(e) = persisted {
+        return admin_error(StatusCode::InternalServerError, format!("{:#}", e));
+    }
+    http_ok_json(&UserChangedRes { name })
+}
+
+/// Swaps in new API keys once Kucoin accepts them, the old keys stay in use otherwise.
+pub(crate) async fn put_user_keys(mut req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    let keys: RotateKeysReq = req.body_json().await
+        .map_err(|e| tide::Error::from_str(StatusCode::BadRequest, e.to_string()))?;
+    let name = req.param("name")?.to_string();
+    let state = req.state().clone();
+    let mut cred = match state.config.get().users.iter().find(|cred| cred.name == name) {
+        Some(cred) => cred.clone(),
+        None => return admin_error(StatusCode::NotFound, format!("No user named {}", name))
+    };
+    cred.api_key = keys.api_key;
+    cred.api_secret = keys.api_secret;
+    cred.api_pass = keys.api_pass;
+    if let Err(e) = cred.validate() {
+        return admin_error(StatusCode::BadRequest, format!("{:#}", e));
+    }
+    let user_manager = state.user_manager.clone();
+    let rotated_cred = cred.clone();
+    let rotated = state.on_tokio(async move {
+        let name = rotated_cred.name.clone();
+        user_manager.rotate_keys(&name, rotated_cred).await
+    }).await?;
+    if let Err(e) = rotated {
+        return admin_error(StatusCode::BadRequest, format!("{:#}", e));
+    }
+    let persisted = state.config.update_users(|users| {
+        for user in users.iter_mut().filter(|user| user.name == name) {
+            *user = cred.clone();
+        }
+        Ok(())
+    });
+    if let Err(e) = persisted {
+        return admin_error(StatusCode::InternalServerError, format!("{:#}", e));
+    }
+    http_ok_json(&UserChangedRes { name })
+}
+
+/// Equity, the day's high and breaker state of the fleet and every user.
+pub(crate) async fn get_drawdown(req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    http_ok_json(&req.state().user_manager.risk().drawdown().status())
+}
+
+/// Lets a halted user buy again, `?user=<name>` resets one user, otherwise the fleet and every user.
+pub(crate) async fn post_reset_drawdown(req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    let query: ResetDrawdownQuery = req.query()?;
+    let drawdown = req.state().user_manager.risk().drawdown();
+    match drawdown.reset(query.user.as_deref()) {
+        Ok(_) => http_ok_json(&drawdown.status()),
+        Err(e) => admin_error(StatusCode::NotFound, format!("{:#}", e))
+    }
+}
+
+/// Stops all trading: signals are refused, open and stop orders are cancelled for every user and with
+/// `?flatten=true` holdings are sold back to the quote currency. Answers 502 if any user failed.
+pub(crate) async fn post_kill(req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    let query: KillQuery = req.query()?;
+    let state = req.state().clone();
+    let user_manager = state.user_manager.clone();
+    let reports = state.on_tokio(async move { user_manager.kill_all(query.flatten).await }).await?;
+    let mut res = http_ok_json(&state.user_manager.risk().kill_switch().status())?;
+    if !reports.iter().all(KillReport::succeeded) {
+        res.set_status(StatusCode::BadGateway);
+    }
+    Ok(res)
+}
+
+pub(crate) async fn get_kill(req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    http_ok_json(&req.state().user_manager.risk().kill_switch().status())
+}
+
+/// Takes signals again after the kill switch was engaged.
+pub(crate) async fn post_rearm(req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    let kill_switch = req.state().user_manager.risk().kill_switch();
+    match kill_switch.rearm() {
+        Ok(_) => http_ok_json(&kill_switch.status()),
+        Err(e) => admin_error(StatusCode::InternalServerError, format!("{:#}", e))
+    }
+}
@@ -1,31 +1,136 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
-use dotenv::dotenv;
-use dotenv_codegen::dotenv;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use tide::{Request, Response, StatusCode};
 use tide::utils::After;
 
+use crate::config::config::ConfigStore;
+use crate::http_server::admin::{check_admin, delete_user, get_drawdown, get_kill, get_user_settings, post_add_user, post_kill, post_rearm, post_reload_config, post_reset_drawdown, put_user_keys};
+use crate::job::job::{Job, JobEvent, JobRegistry, JobState};
+use crate::exchange::kucoin::server_clock::ServerClock;
+use crate::risk::risk::{RiskNote, SignalCheck};
+use crate::schedule::scheduler::Scheduler;
+use crate::signal::signal_filter::{Filtered, SignalFilter};
+use crate::user::user_manager::UserManager;
+
 #[derive(Serialize, Deserialize)]
 pub struct PurchaseAndTakeProfitReq {
-    tokens: Vec<String>
+    tokens: Vec<String>,
+    /// Holds the signal until this time, in milliseconds since the epoch on Kucoin's clock.
+    #[serde(default)]
+    execute_at: Option<i64>
+}
+
+#[derive(Serialize)]
+struct ScheduledRes {
+    schedule_id: u64,
+    execute_at: i64,
+    /// Kucoin's clock minus ours when the signal was taken.
+    clock_offset_ms: i64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    risk: Vec<RiskNote>
+}
+
+#[derive(Serialize)]
+struct JobCreatedRes {
+    job_id: u64,
+    /// Tokens dropped by the risk limits, or that some users can't buy anymore today.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    risk: Vec<RiskNote>,
+    /// Set when nothing was queued, the job status has the details.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suppressed: Option<String>
 }
 
 #[derive(Clone)]
-struct State {
-    pub send_token_s: Arc<tokio::sync::mpsc::Sender<Vec<String>>>
+pub(crate) struct State {
+    pub send_token_s: Arc<tokio::sync::mpsc::Sender<Job>>,
+    pub jobs: JobRegistry,
+    pub config: ConfigStore,
+    pub user_manager: UserManager,
+    pub signals: SignalFilter,
+    pub scheduler: Scheduler,
+    pub clock: ServerClock,
+    runtime: tokio::runtime::Handle
+}
+
+impl State {
+    /// Tide serves requests on async-std, anything talking to Kucoin has to run on the tokio runtime.
+    pub(crate) async fn on_tokio<F>(&self, fut: F) -> tide::Result<F::Output>
+        where F: std::future::Future + Send + 'static,
+              F::Output: Send + 'static
+    {
+        self.runtime.spawn(fut).await
+            .map_err(|e| tide::Error::from_str(StatusCode::InternalServerError, e.to_string()))
+    }
 }
 
-const UPSET_SMILEY: &str = ":(";
+pub(crate) const UPSET_SMILEY: &str = ":(";
 
-fn http_ok() -> tide::Result {
+pub(crate) fn http_ok_json<T: Serialize>(body: &T) -> tide::Result {
     tide::Result::Ok(Response::builder(StatusCode::Ok)
+        .body(tide::Body::from_json(body)?)
         .build()
     )
 }
 
+async fn get_job_status(req: Request<State>) -> tide::Result {
+    let job_id = req.param("id")?
+        .parse::<u64>()
+        .map_err(|_| tide::Error::from_str(StatusCode::BadRequest, UPSET_SMILEY))?;
+    match req.state().jobs.get(job_id) {
+        Some(status) => http_ok_json(&status),
+        None => tide::Result::Err(tide::Error::from_str(StatusCode::NotFound, UPSET_SMILEY))
+    }
+}
+
+/// Signals with an `execute_at` skip the duplicate and cooldown checks, they are sent on purpose.
+fn schedule_signal(state: &State, check: SignalCheck, execute_at: i64) -> tide::Result {
+    let now = state.clock.now_ms();
+    if execute_at <= now {
+        return tide::Result::Err(tide::Error::from_str(StatusCode::UnprocessableEntity, format!("execute_at {} is not in the future, Kucoin time is {}", execute_at, now)));
+    }
+    let signal = state.scheduler.add(check.tokens, execute_at)
+        .map_err(|e| tide::Error::from_str(StatusCode::InternalServerError, format!("{:#}", e)))?;
+    http_ok_json(&ScheduledRes {
+        schedule_id: signal.id,
+        execute_at,
+        clock_offset_ms: state.clock.offset_ms(),
+        risk: check.dropped,
+    })
+}
+
+async fn get_scheduled(req: Request<State>) -> tide::Result {
+    http_ok_json(&req.state().scheduler.list())
+}
+
+/// Cancels a signal that hasn't fired yet, it takes the admin token like every other change.
+async fn delete_scheduled(req: Request<State>) -> tide::Result {
+    check_admin(&req)?;
+    let id = req.param("id")?
+        .parse::<u64>()
+        .map_err(|_| tide::Error::from_str(StatusCode::BadRequest, UPSET_SMILEY))?;
+    let scheduler = &req.state().scheduler;
+    match scheduler.cancel(id) {
+        Ok(signal) => http_ok_json(&signal),
+        Err(e) => {
+            let status = if scheduler.list().iter().any(|signal| signal.id == id) {
+                StatusCode::Conflict
+            } else {
+                StatusCode::NotFound
+            };
+            tide::Result::Err(tide::Error::from_str(status, format!("{:#}", e)))
+        }
+    }
+}
+
+async fn get_metrics(req: Request<State>) -> tide::Result {
+    http_ok_json(&req.state().signals.metrics())
+}
+
 async fn post_purchase_and_take_profit(mut req: Request<State>) -> tide::Result {
     let body_string = req.body_string().await?;
     let message = match serde_json::from_str::<PurchaseAndTakeProfitReq>(&*body_string) {
@@ -36,8 +141,50 @@ async fn post_purchase_and_take_profit(mut req: Request<State>) -> tide::Result
         }
     }?;
     let state = &mut req.state();
-    match state.send_token_s.send(message.tokens).await {
-        Ok(_) => http_ok(),
+    if state.user_manager.risk().kill_switch().is_engaged() {
+        return tide::Result::Err(tide::Error::from_str(StatusCode::ServiceUnavailable, "kill switch is engaged"));
+    }
+    let config = state.config.get();
+    let check = config.risk.check_signal(message.tokens)
+        .map_err(|reason| tide::Error::from_str(StatusCode::UnprocessableEntity, reason))?;
+    if let Some(execute_at) = message.execute_at {
+        return schedule_signal(state, check, execute_at);
+    }
+    let mut job = state.jobs.create(check.tokens.clone());
+    let job_id = job.id;
+    for note in &check.dropped {
+        job.record(JobEvent::RiskRejected {
+            user: None,
+            symbol: note.symbol.clone(),
+            reason: note.reason.clone(),
+        });
+    }
+    let mut risk = check.dropped;
+    let dedup_window = Duration::from_secs(config.dedup_window_secs);
+    let token_cooldown = Duration::from_secs(config.token_cooldown_secs);
+    let tokens = match state.signals.filter(&check.tokens, job_id, dedup_window, token_cooldown) {
+        Filtered::Duplicate { of_job } => {
+            job.record(JobEvent::Duplicate { of_job });
+            job.set_state(JobState::Suppressed);
+            let suppressed = Some(format!("same tokens as job {}", of_job));
+            return http_ok_json(&JobCreatedRes { job_id, risk, suppressed });
+        }
+        Filtered::Accepted { tokens, cooling_down } => {
+            for symbol in cooling_down {
+                job.record(JobEvent::CoolingDown { symbol });
+            }
+            tokens
+        }
+    };
+    if tokens.is_empty() {
+        job.set_state(JobState::Suppressed);
+        let suppressed = Some("every token is cooling down".to_string());
+        return http_ok_json(&JobCreatedRes { job_id, risk, suppressed });
+    }
+    job.set_tokens(tokens);
+    risk.extend(state.user_manager.risk().preview(&config, &job.tokens));
+    match state.send_token_s.send(job).await {
+        Ok(_) => http_ok_json(&JobCreatedRes { job_id, risk, suppressed: None }),
         Err(e) => {
             println!("{}", e);
             tide::Result::Err(tide::Error::from_str(StatusCode::InternalServerError, UPSET_SMILEY))
@@ -45,9 +192,18 @@ async fn post_purchase_and_take_profit(mut req: Request<State>) -> tide::Result
     }
 }
 
-pub async fn tide_server(send_token_s: tokio::sync::mpsc::Sender<Vec<String>>) -> Result<()> {
+pub async fn tide_server(send_token_s: tokio::sync::mpsc::Sender<Job>, jobs: JobRegistry, config: ConfigStore, user_manager: UserManager, scheduler: Scheduler, clock: ServerClock) -> Result<()> {
+    // The port is only read on startup, a reload can't move a listening server.
+    let port = config.get().port;
     let mut app = tide::with_state(State {
-        send_token_s: Arc::new(send_token_s)
+        send_token_s: Arc::new(send_token_s),
+        jobs,
+        config,
+        user_manager,
+        signals: SignalFilter::new(),
+        scheduler,
+        clock,
+        runtime: tokio::runtime::Handle::current()
     });
     app.with(After(|mut res: Response| async move {
         if let Some(err) = res.downcast_error::<async_std::io::Error>() {
@@ -59,10 +215,20 @@ pub async fn tide_server(send_token_s: tokio::sync::mpsc::Sender<Vec<String>>) -
         }
         Ok(res)
     }));
-    dotenv().ok();
-    let port = dotenv!("PORT");
 
     app.at("/create_order").post(post_purchase_and_take_profit);
+    app.at("/jobs/:id").get(get_job_status);
+    app.at("/metrics").get(get_metrics);
+    app.at("/scheduled").get(get_scheduled);
+    app.at("/scheduled/:id").delete(delete_scheduled);
+    app.at("/admin/reload").post(post_reload_config);
+    app.at("/admin/users").get(get_user_settings).post(post_add_user);
+    app.at("/admin/users/:name").delete(delete_user);
+    app.at("/admin/users/:name/keys").put(put_user_keys);
+    app.at("/admin/risk/drawdown").get(get_drawdown);
+    app.at("/admin/risk/drawdown/reset").post(post_reset_drawdown);
+    app.at("/admin/kill").get(get_kill).post(post_kill);
+    app.at("/admin/kill/rearm").post(post_rearm);
     let _ = app.listen(format!("0.0.0.0:{}", port)).await?;
     Ok(())
 }
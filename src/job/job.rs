@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+/// Only the most recent jobs are kept around for the status endpoint.
+const MAX_JOBS: u64 = 500;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Finished,
+    /// Nothing was left to buy once duplicates and cooldowns were filtered out.
+    Suppressed,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobEvent {
+    /// Quote moved from the Main account into Trade ahead of the buys.
+    Funded { user: String, amount: f32, simulated: bool },
+    Bought { user: String, symbol: String, order_id: String, simulated: bool },
+    TakeProfitPlaced { user: String, symbol: String, order_id: String, simulated: bool },
+    /// The user's settings kept it from buying this token.
+    Skipped { user: String, symbol: String, reason: String },
+    /// The same tokens were already taken as job `of_job` a moment ago.
+    Duplicate { of_job: u64 },
+    /// The token was bought too recently, see `token_cooldown_secs`.
+    CoolingDown { symbol: String },
+    /// A risk limit kept the token from being bought, `user` is unset for fleet wide limits.
+    RiskRejected { user: Option<String>, symbol: String, reason: String },
+    /// A risk limit cut the quote spent on this buy down to `funds`.
+    RiskScaledDown { user: String, symbol: String, funds: f32, reason: String },
+    /// `fatal` is set when the exchange rejected the request outright, otherwise retries ran out.
+    Failed { user: Option<String>, symbol: String, fatal: bool, reason: String },
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct JobStatus {
+    pub id: u64,
+    pub tokens: Vec<String>,
+    pub state: JobState,
+    pub events: Vec<JobEvent>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_id: u64,
+    by_id: HashMap<u64, JobStatus>,
+}
+
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<Jobs>>,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        JobRegistry::default()
+    }
+    fn with_jobs<T>(&self, f: impl FnOnce(&mut Jobs) -> T) -> T {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut jobs)
+    }
+    pub fn create(&self, tokens: Vec<String>) -> Job {
+        let id = self.with_jobs(|jobs| {
+            jobs.next_id += 1;
+            let id = jobs.next_id;
+            if id > MAX_JOBS {
+                jobs.by_id.remove(&(id - MAX_JOBS));
+            }
+            jobs.by_id.insert(id, JobStatus {
+                id,
+                tokens: tokens.clone(),
+                state: JobState::Queued,
+                events: Vec::new(),
+            });
+            id
+        });
+        Job {
+            id,
+            tokens,
+            registry: self.clone(),
+        }
+    }
+    pub fn get(&self, id: u64) -> Option<JobStatus> {
+        self.with_jobs(|jobs| jobs.by_id.get(&id).cloned())
+    }
+    fn update(&self, id: u64, f: impl FnOnce(&mut JobStatus)) {
+        self.with_jobs(|jobs| {
+            if let Some(status) = jobs.by_id.get_mut(&id) {
+                f(status);
+            }
+        })
+    }
+}
+
+/// A signal on its way through the bot, carrying a handle back to its status entry.
+#[derive(Clone)]
+pub struct Job {
+    pub id: u64,
+    pub tokens: Vec<String>,
+    registry: JobRegistry,
+}
+
+impl Job {
+    pub fn set_tokens(&mut self, tokens: Vec<String>) {
+        self.registry.update(self.id, |status| status.tokens = tokens.clone());
+        self.tokens = tokens;
+    }
+    pub fn set_state(&self, state: JobState) {
+        self.registry.update(self.id, |status| status.state = state);
+    }
+    pub fn record(&self, event: JobEvent) {
+        self.registry.update(self.id, |status| status.events.push(event));
+    }
+}
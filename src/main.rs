@@ -1,8 +1,14 @@
 use std::sync::Arc;
 
-use tokio::sync::Mutex;
+use dotenv::dotenv;
 
+use crate::config::config::{config_path_from_args, ConfigStore};
+#[cfg(unix)]
+use crate::config::config::reload_on_sighup;
 use crate::http_server::http_server::tide_server;
+use crate::exchange::kucoin::server_clock::ServerClock;
+use crate::job::job::{Job, JobRegistry, JobState};
+use crate::schedule::scheduler::Scheduler;
 use crate::user::user_manager::UserManager;
 use exchange::kucoin::token_info::make_token_info_vec;
 
@@ -11,32 +17,98 @@ mod exchange;
 mod user;
 mod utils;
 mod error;
+mod job;
+mod config;
+mod credentials;
+mod risk;
+mod signal;
+mod schedule;
 
 
 #[tokio::main]
 async fn main() {
-    let user_manager = Arc::new(Mutex::new(UserManager::new().await));
-    let user_manager_ref1 = Arc::clone(&user_manager);
-    let user_manager_ref2 = Arc::clone(&user_manager);
-    let (send_token_s, mut token) = tokio::sync::mpsc::channel::<Vec<String>>(24);
+    dotenv().ok();
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|arg| arg == "credentials").unwrap_or(false) {
+        if let Err(e) = credentials::cli::run(&args[2..]) {
+            println!("{:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let config = match ConfigStore::load(config_path_from_args()) {
+        Ok(t) => t,
+        Err(e) => {
+            println!("{:?}", e);
+            std::process::exit(1);
+        }
+    };
+    let user_manager = UserManager::new(&config.get()).await;
+    let user_manager_ref1 = user_manager.clone();
+    let user_manager_ref2 = user_manager.clone();
+    let user_manager_ref3 = user_manager.clone();
+    let user_manager_ref4 = user_manager.clone();
+    let jobs = JobRegistry::new();
+    let (send_token_s, mut token) = tokio::sync::mpsc::channel::<Job>(24);
+    let scheduler = match Scheduler::load(&config.get().schedule_file) {
+        Ok(t) => t,
+        Err(e) => {
+            println!("{:?}", e);
+            std::process::exit(1);
+        }
+    };
+    let clock = match ServerClock::new() {
+        Ok(t) => t,
+        Err(e) => {
+            println!("{:?}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = clock.sample().await {
+        println!("{:?}", e);
+    }
+    let resample_clock = clock.clone();
     tokio::spawn(async move {
         loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(600)).await;
-            {
-                let mut um = user_manager_ref1.lock().await;
-                um.refresh_users().await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(300)).await;
+            if let Err(e) = resample_clock.sample().await {
+                println!("{:?}", e);
             }
         }
     });
+    tokio::spawn(scheduler.clone().run(clock.clone(), jobs.clone(), send_token_s.clone()));
     tokio::spawn(async move {
         loop {
-            let maybe_tokens = token.recv().await.ok_or_else(|| println!("Failed attempting to receive tokens from server!")).ok();
-            if let Some(tokens) = maybe_tokens {
-                let tokens = make_token_info_vec(tokens);
-                let mut um = user_manager_ref2.lock().await;
-                um.purchase_and_take_profit(tokens).await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(600)).await;
+            user_manager_ref1.refresh_users().await;
+        }
+    });
+    tokio::spawn(async move {
+        loop {
+            let maybe_job = token.recv().await.ok_or_else(|| println!("Failed attempting to receive tokens from server!")).ok();
+            if let Some(job) = maybe_job {
+                let tokens = make_token_info_vec(job.tokens.clone());
+                job.set_state(JobState::Running);
+                user_manager_ref2.purchase_and_take_profit(tokens, &job).await;
+                job.set_state(JobState::Finished);
             }
         }
     });
-    tide_server(send_token_s).await.expect("tide server failed!");
+    let equity_config = config.clone();
+    tokio::spawn(async move {
+        loop {
+            user_manager_ref4.sample_equity(&equity_config.get()).await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
+        }
+    });
+    let mut config_updates = config.subscribe();
+    tokio::spawn(async move {
+        while config_updates.changed().await.is_ok() {
+            let new_config = Arc::clone(&config_updates.borrow());
+            user_manager_ref3.apply_config(&new_config).await;
+        }
+    });
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(config.clone()));
+    tide_server(send_token_s, jobs, config, user_manager, scheduler, clock).await.expect("tide server failed!");
 }
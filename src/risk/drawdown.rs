@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::error::error::MintError;
+
+/// Equity of one user, or of the whole fleet, against its high of the day.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct EquityWatch {
+    #[serde(skip)]
+    day: u128,
+    pub equity: f32,
+    pub day_high: f32,
+    /// Set when the drawdown went over the limit, cleared only by a reset.
+    pub tripped: Option<String>,
+}
+
+impl EquityWatch {
+    /// Share of the day's high lost since, between 0 and 1.
+    pub fn drawdown(&self) -> f32 {
+        if self.day_high <= 0.0 {
+            return 0.0;
+        }
+        ((self.day_high - self.equity) / self.day_high).max(0.0)
+    }
+    fn sample(&mut self, day: u128, equity: f32, max_drawdown: Option<f32>) -> Option<String> {
+        if self.day != day {
+            self.day = day;
+            self.day_high = equity;
+        }
+        self.equity = equity;
+        self.day_high = self.day_high.max(equity);
+        match max_drawdown {
+            Some(max) if self.tripped.is_none() && self.drawdown() > max => {
+                let reason = format!("equity {} is down {:.2}% from the day's high {}, the limit is {:.2}%",
+                                     equity, self.drawdown() * 100.0, self.day_high, max * 100.0);
+                self.tripped = Some(reason.clone());
+                Some(reason)
+            }
+            _ => None
+        }
+    }
+    /// Trading resumes from the current equity as the new high.
+    fn reset(&mut self) {
+        self.tripped = None;
+        self.day_high = self.equity;
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct DrawdownStatus {
+    pub fleet: EquityWatch,
+    pub users: BTreeMap<String, EquityWatch>,
+    /// The users the fleet's equity was last summed over.
+    #[serde(skip)]
+    members: BTreeSet<String>,
+}
+
+/// What is written to the drawdown file, the reasons of the breakers that are tripped.
+#[derive(Serialize, Deserialize, Default, PartialEq)]
+struct TrippedFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fleet: Option<String>,
+    #[serde(default)]
+    users: BTreeMap<String, String>,
+}
+
+impl DrawdownStatus {
+    fn tripped(&self) -> TrippedFile {
+        TrippedFile {
+            fleet: self.fleet.tripped.clone(),
+            users: self.users.iter()
+                .filter_map(|(name, watch)| watch.tripped.clone().map(|reason| (name.clone(), reason)))
+                .collect(),
+        }
+    }
+    /// Moves the fleet's high onto a new set of users: those that left take their share with
+    /// them, those that joined count from their equity now, and the drawdown so far is kept.
+    fn rebase_fleet(&mut self, equities: &HashMap<String, f32>) {
+        let base: f32 = equities.iter()
+            .map(|(name, equity)| match self.users.get(name) {
+                Some(watch) if self.members.contains(name) => watch.equity,
+                _ => *equity
+            })
+            .sum();
+        if self.fleet.equity > 0.0 {
+            self.fleet.day_high *= base / self.fleet.equity;
+        } else {
+            self.fleet.day_high = base;
+        }
+        self.fleet.equity = base;
+    }
+}
+
+/// Halts new buys once equity falls too far from the day's high. A tripped breaker stays
+/// tripped, even over a new day or a restart, until it is reset through the admin API.
+#[derive(Clone, Default)]
+pub struct DrawdownBreaker {
+    /// Where tripped breakers are kept, nothing is written without one.
+    path: Option<PathBuf>,
+    state: Arc<Mutex<DrawdownStatus>>,
+}
+
+impl DrawdownBreaker {
+    /// Reads the breakers tripped before a restart, a missing file means none are.
+    pub fn load(path: &Path) -> Result<DrawdownBreaker> {
+        let file = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str::<TrippedFile>(&json)
+                .with_context(|| format!("Drawdown file {} is not valid", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => TrippedFile::default(),
+            Err(e) => return Err(e).with_context(|| format!("Could not read drawdown file {}", path.display()))
+        };
+        let mut state = DrawdownStatus::default();
+        state.fleet.tripped = file.fleet;
+        for (name, reason) in file.users {
+            state.users.entry(name).or_default().tripped = Some(reason);
+        }
+        Ok(DrawdownBreaker {
+            path: Some(path.to_path_buf()),
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+    /// Runs `f` on the state and writes the tripped breakers when `f` changed them. The state
+    /// is kept even when the write fails, a halt must not be lifted because a file couldn't be written.
+    fn with_state<T>(&self, f: impl FnOnce(&mut DrawdownStatus) -> T) -> Result<T> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let before = state.tripped();
+        let res = f(&mut state);
+        let after = state.tripped();
+        match &self.path {
+            Some(path) if after != before => save(path, &after).map(|_| res),
+            _ => Ok(res)
+        }
+    }
+    /// Records the equity of the users that could be valued. The fleet is only sampled when every
+    /// user was, a missing account would look like a loss. A complete sample also tells who the
+    /// users are: the fleet's high follows users coming and going, and removed users are dropped.
+    pub fn sample(&self, day: u128, equities: &HashMap<String, f32>, complete: bool, max_user_drawdown: Option<f32>, max_fleet_drawdown: Option<f32>) {
+        let saved = self.with_state(|state| {
+            if complete {
+                let members: BTreeSet<String> = equities.keys().cloned().collect();
+                if !state.members.is_empty() && members != state.members {
+                    state.rebase_fleet(equities);
+                }
+                state.users.retain(|name, _| members.contains(name));
+                state.members = members;
+            }
+            for (name, equity) in equities {
+                let watch = state.users.entry(name.clone()).or_default();
+                if let Some(reason) = watch.sample(day, *equity, max_user_drawdown) {
+                    println!("Drawdown breaker tripped for {}: {}", name, reason);
+                }
+            }
+            if complete {
+                let total = equities.values().sum();
+                if let Some(reason) = state.fleet.sample(day, total, max_fleet_drawdown) {
+                    println!("Fleet drawdown breaker tripped: {}", reason);
+                }
+            }
+        });
+        if let Err(e) = saved {
+            println!("{:?}", e);
+        }
+    }
+    /// Why the user may not buy right now, if a breaker is tripped.
+    pub fn halt_reason(&self, user: &str) -> Option<String> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        {
+            if let Some(reason) = &state.fleet.tripped {
+                return Some(format!("fleet drawdown breaker is tripped: {}", reason));
+            }
+            state.users.get(user)
+                .and_then(|watch| watch.tripped.as_ref())
+                .map(|reason| format!("drawdown breaker is tripped: {}", reason))
+        }
+    }
+    /// Resets one user's breaker, or the fleet's and every user's without a name.
+    pub fn reset(&self, user: Option<&str>) -> Result<()> {
+        self.with_state(|state| match user {
+            Some(name) => match state.users.get_mut(name) {
+                Some(watch) => {
+                    watch.reset();
+                    Ok(())
+                }
+                None => Err(MintError::from_str(format!("No equity recorded for {}", name)).into())
+            },
+            None => {
+                state.fleet.reset();
+                state.users.values_mut().for_each(EquityWatch::reset);
+                Ok(())
+            }
+        })?
+    }
+    pub fn status(&self) -> DrawdownStatus {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+fn save(path: &Path, file: &TrippedFile) -> Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_string_pretty(file)?)
+        .with_context(|| format!("Could not write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("Could not replace drawdown file {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::risk::drawdown::DrawdownBreaker;
+
+    fn equities(a: f32, b: f32) -> HashMap<String, f32> {
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), a);
+        map.insert("b".to_string(), b);
+        map
+    }
+
+    #[test]
+    fn trips_on_drawdown_and_stays_until_reset() {
+        let breaker = DrawdownBreaker::default();
+        breaker.sample(1, &equities(1.0, 1.0), true, Some(0.2), Some(0.3));
+        breaker.sample(1, &equities(1.2, 1.0), true, Some(0.2), Some(0.3));
+        breaker.sample(1, &equities(0.9, 1.0), true, Some(0.2), Some(0.3));
+        assert!(breaker.halt_reason("a").is_some());
+        assert!(breaker.halt_reason("b").is_none());
+        // Recovering or a new day doesn't lift it.
+        breaker.sample(2, &equities(1.5, 1.0), true, Some(0.2), Some(0.3));
+        assert!(breaker.halt_reason("a").is_some());
+        breaker.reset(Some("a")).unwrap();
+        assert!(breaker.halt_reason("a").is_none());
+        assert!(breaker.reset(Some("c")).is_err());
+    }
+
+    #[test]
+    fn fleet_breaker_halts_everyone() {
+        let breaker = DrawdownBreaker::default();
+        breaker.sample(1, &equities(1.0, 1.0), true, None, Some(0.3));
+        // An incomplete sample is not counted against the fleet.
+        breaker.sample(1, &equities(1.0, 0.0), false, None, Some(0.3));
+        assert!(breaker.halt_reason("a").is_none());
+        breaker.sample(1, &equities(0.6, 0.6), true, None, Some(0.3));
+        assert!(breaker.halt_reason("a").is_some() && breaker.halt_reason("b").is_some());
+        breaker.reset(None).unwrap();
+        assert!(breaker.halt_reason("b").is_none());
+        assert_eq!(breaker.status().fleet.day_high, 1.2);
+    }
+
+    #[test]
+    fn follows_users_coming_and_going() {
+        let breaker = DrawdownBreaker::default();
+        breaker.sample(1, &equities(1.0, 1.0), true, None, Some(0.3));
+        // "b" was removed, that is not a loss and "b" is no longer reported.
+        let only_a: HashMap<String, f32> = vec![("a".to_string(), 1.0)].into_iter().collect();
+        breaker.sample(1, &only_a, true, None, Some(0.3));
+        assert!(breaker.halt_reason("a").is_none());
+        assert!(!breaker.status().users.contains_key("b"));
+        assert_eq!(breaker.status().fleet.day_high, 1.0);
+        // "b" is back with more than before, it doesn't raise the high past what the fleet holds.
+        breaker.sample(1, &equities(1.0, 3.0), true, None, Some(0.3));
+        assert_eq!(breaker.status().fleet.day_high, 4.0);
+        breaker.sample(1, &equities(1.0, 2.0), true, None, Some(0.3));
+        assert!(breaker.halt_reason("a").is_none());
+    }
+
+    #[test]
+    fn tripped_breakers_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("kucoin_arb_drawdown_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let breaker = DrawdownBreaker::load(&path).unwrap();
+        breaker.sample(1, &equities(1.0, 1.0), true, Some(0.2), None);
+        breaker.sample(1, &equities(0.5, 1.0), true, Some(0.2), None);
+        let reloaded = DrawdownBreaker::load(&path).unwrap();
+        assert!(reloaded.halt_reason("a").is_some());
+        assert!(reloaded.halt_reason("b").is_none());
+        reloaded.reset(None).unwrap();
+        assert!(DrawdownBreaker::load(&path).unwrap().halt_reason("a").is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
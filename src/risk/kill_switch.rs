@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::time::get_ms;
+
+/// What the kill switch managed to do for one user.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct KillReport {
+    pub user: String,
+    pub cancelled: bool,
+    /// Stop orders are kept apart from the others on Kucoin and cancelled separately.
+    #[serde(default)]
+    pub stops_cancelled: bool,
+    /// Market sells placed to get back to the quote currency, by symbol.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sold: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+impl KillReport {
+    pub fn succeeded(&self) -> bool {
+        self.cancelled && self.stops_cancelled && self.errors.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct KillSwitchStatus {
+    pub engaged: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engaged_at: Option<u128>,
+    #[serde(default)]
+    pub flatten: bool,
+    #[serde(default)]
+    pub reports: Vec<KillReport>,
+}
+
+/// Once engaged no signal is taken and no buy goes out, until it is re-armed through the admin API.
+/// That holds over a restart too, the state is written to the kill switch file on every change.
+#[derive(Clone, Default)]
+pub struct KillSwitch {
+    /// Where the state is kept, nothing is written without one.
+    path: Option<PathBuf>,
+    state: Arc<Mutex<KillSwitchStatus>>,
+}
+
+impl KillSwitch {
+    /// Reads the state left before a restart, a missing file means the switch is not engaged.
+    pub fn load(path: &Path) -> Result<KillSwitch> {
+        let state = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str::<KillSwitchStatus>(&json)
+                .with_context(|| format!("Kill switch file {} is not valid", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => KillSwitchStatus::default(),
+            Err(e) => return Err(e).with_context(|| format!("Could not read kill switch file {}", path.display()))
+        };
+        Ok(KillSwitch {
+            path: Some(path.to_path_buf()),
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+    /// Runs `f` on the state and writes it. The state is kept even when the write fails, an
+    /// engaged switch must hold whether or not it could be written.
+    fn with_state<T>(&self, f: impl FnOnce(&mut KillSwitchStatus) -> T) -> Result<T> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let res = f(&mut state);
+        match &self.path {
+            Some(path) => save(path, &state).map(|_| res),
+            None => Ok(res)
+        }
+    }
+    pub fn engage(&self, flatten: bool) -> Result<()> {
+        self.with_state(|state| {
+            if !state.engaged {
+                state.engaged_at = get_ms().ok();
+            }
+            state.engaged = true;
+            state.flatten |= flatten;
+            state.reports.clear();
+        })
+    }
+    pub fn set_reports(&self, reports: Vec<KillReport>) -> Result<()> {
+        self.with_state(|state| state.reports = reports)
+    }
+    pub fn rearm(&self) -> Result<()> {
+        self.with_state(|state| *state = KillSwitchStatus::default())
+    }
+    pub fn is_engaged(&self) -> bool {
+        self.status().engaged
+    }
+    pub fn status(&self) -> KillSwitchStatus {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+fn save(path: &Path, state: &KillSwitchStatus) -> Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_string_pretty(state)?)
+        .with_context(|| format!("Could not write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("Could not replace kill switch file {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::risk::kill_switch::{KillReport, KillSwitch};
+
+    #[test]
+    fn stays_engaged_until_rearmed() {
+        let switch = KillSwitch::default();
+        assert!(!switch.is_engaged());
+        switch.engage(false).unwrap();
+        switch.engage(true).unwrap();
+        switch.set_reports(vec![KillReport { user: "a".to_string(), cancelled: true, ..KillReport::default() }]).unwrap();
+        let status = switch.status();
+        assert!(status.engaged && status.flatten && status.engaged_at.is_some());
+        // Stop orders still out can trigger while halted.
+        assert!(!status.reports[0].succeeded());
+        switch.set_reports(vec![KillReport { user: "a".to_string(), cancelled: true, stops_cancelled: true, ..KillReport::default() }]).unwrap();
+        assert!(switch.status().reports[0].succeeded());
+        switch.rearm().unwrap();
+        assert!(!switch.is_engaged());
+        assert!(switch.status().reports.is_empty());
+    }
+
+    #[test]
+    fn stays_engaged_over_a_restart() {
+        let path = std::env::temp_dir().join(format!("kucoin_arb_kill_switch_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let switch = KillSwitch::load(&path).unwrap();
+        assert!(!switch.is_engaged());
+        switch.engage(true).unwrap();
+        let reloaded = KillSwitch::load(&path).unwrap();
+        assert!(reloaded.is_engaged() && reloaded.status().flatten);
+        reloaded.rearm().unwrap();
+        assert!(!KillSwitch::load(&path).unwrap().is_engaged());
+        std::fs::remove_file(&path).unwrap();
+
+        let unwritable = KillSwitch::load(&std::env::temp_dir().join("kucoin_arb_missing_dir").join("kill_switch.json")).unwrap();
+        assert!(unwritable.engage(false).is_err());
+        assert!(unwritable.is_engaged());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod risk;
+pub mod drawdown;
+pub mod kill_switch;
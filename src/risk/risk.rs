@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::config::Config;
+use crate::error::error::MintError;
+use crate::exchange::kucoin::token_info::make_token_info_vec;
+use crate::risk::drawdown::DrawdownBreaker;
+use crate::risk::kill_switch::KillSwitch;
+use crate::user::settings::UserSettings;
+use crate::utils::time::get_ms;
+
+const MS_PER_DAY: u128 = 24 * 60 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreachAction {
+    /// Buys what still fits under the limits, dropping what doesn't fit at all.
+    #[default]
+    ScaleDown,
+    /// Refuses anything that goes over a limit.
+    Reject,
+}
+
+/// Fleet wide limits, `max_open_positions`, `max_quote_per_day` and `max_exposure_per_token`
+/// can be overridden per user. Days are UTC days.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RiskLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_per_signal: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_quote_per_day: Option<f32>,
+    /// Most positions a user holds at once, a buy counts until its take profit is done.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_open_positions: Option<usize>,
+    /// Most quote a user has in the open positions of one token, a buy counts until its take profit is done.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_exposure_per_token: Option<f32>,
+    #[serde(default)]
+    pub on_breach: BreachAction,
+    /// Share of a user's equity lost from the day's high that halts its buys, e.g. `0.1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_user_drawdown_perc: Option<f32>,
+    /// Same for the equity of all users together, halting every user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_fleet_drawdown_perc: Option<f32>,
+}
+
+fn invalid(msg: String) -> anyhow::Error {
+    MintError::from_str(msg).into()
+}
+
+pub(crate) fn check_quote_limit(field: &str, limit: Option<f32>) -> Result<()> {
+    match limit {
+        Some(max) if !max.is_finite() || max <= 0.0 => Err(invalid(format!("{} must be above 0, got {}", field, max))),
+        _ => Ok(())
+    }
+}
+
+/// Why part of a signal was dropped or scaled down, returned when the signal is posted.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RiskNote {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    pub symbol: String,
+    pub reason: String,
+}
+
+/// The signal once the fleet wide limits are applied.
+#[derive(Debug, PartialEq)]
+pub struct SignalCheck {
+    pub tokens: Vec<String>,
+    pub dropped: Vec<RiskNote>,
+}
+
+impl RiskLimits {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_tokens_per_signal == Some(0) {
+            return Err(invalid("risk.max_tokens_per_signal must be at least 1".to_string()));
+        }
+        if self.max_open_positions == Some(0) {
+            return Err(invalid("risk.max_open_positions must be at least 1".to_string()));
+        }
+        check_quote_limit("risk.max_quote_per_day", self.max_quote_per_day)?;
+        check_quote_limit("risk.max_exposure_per_token", self.max_exposure_per_token)?;
+        for (field, perc) in [("max_user_drawdown_perc", self.max_user_drawdown_perc), ("max_fleet_drawdown_perc", self.max_fleet_drawdown_perc)].iter() {
+            match perc {
+                Some(perc) if !perc.is_finite() || *perc <= 0.0 || *perc > 1.0 => {
+                    return Err(invalid(format!("risk.{} must be above 0 and at most 1, got {}", field, perc)));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    /// Applies `max_tokens_per_signal`, the error is the reason the whole signal was refused.
+    pub fn check_signal(&self, tokens: Vec<String>) -> std::result::Result<SignalCheck, String> {
+        let max = match self.max_tokens_per_signal {
+            Some(max) if tokens.len() > max => max,
+            _ => return Ok(SignalCheck { tokens, dropped: Vec::new() })
+        };
+        let reason = format!("{} tokens is more than max_tokens_per_signal {}", tokens.len(), max);
+        if self.on_breach == BreachAction::Reject {
+            return Err(reason);
+        }
+        let mut tokens = tokens;
+        let dropped = tokens.split_off(max)
+            .into_iter()
+            .map(|symbol| RiskNote { user: None, symbol, reason: reason.clone() })
+            .collect();
+        Ok(SignalCheck { tokens, dropped })
+    }
+}
+
+/// What to do with one buy of one user.
+#[derive(Debug, PartialEq)]
+pub enum Decision {
+    Allow(f32),
+    ScaleDown(f32, String),
+    Reject(String),
+}
+
+/// The positions a user holds when one of its buys is checked, see `User::open_positions`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OpenPositions {
+    pub count: usize,
+    /// Quote spent on the positions in the token being bought.
+    pub in_token: f32,
+}
+
+#[derive(Default)]
+struct Ledger {
+    day: u128,
+    spent: HashMap<String, f32>,
+}
+
+impl Ledger {
+    fn roll_over(&mut self, day: u128) {
+        if self.day != day {
+            self.day = day;
+            self.spent.clear();
+        }
+    }
+}
+
+/// Keeps what each user spent today. The ledger lives in memory only, a restart starts the day over.
+#[derive(Clone, Default)]
+pub struct RiskEngine {
+    ledger: Arc<Mutex<Ledger>>,
+    drawdown: DrawdownBreaker,
+    kill_switch: KillSwitch,
+}
+
+fn today() -> u128 {
+    get_ms().map(|ms| ms / MS_PER_DAY).unwrap_or(0)
+}
+
+impl RiskEngine {
+    /// Picks up the breakers that were tripped and the kill switch as they were before a restart.
+    pub fn load(config: &Config) -> Result<Self> {
+        Ok(RiskEngine {
+            drawdown: DrawdownBreaker::load(&config.drawdown_file)?,
+            kill_switch: KillSwitch::load(&config.kill_switch_file)?,
+            ..RiskEngine::default()
+        })
+    }
+    fn with_ledger<T>(&self, day: u128, f: impl FnOnce(&mut Ledger) -> T) -> T {
+        let mut ledger = self.ledger.lock().unwrap_or_else(|e| e.into_inner());
+        ledger.roll_over(day);
+        f(&mut ledger)
+    }
+    fn assess_on(&self, day: u128, user: &str, symbol: &str, funds: f32, positions: OpenPositions, settings: &UserSettings) -> Decision {
+        if self.kill_switch.is_engaged() {
+            return Decision::Reject("kill switch is engaged".to_string());
+        }
+        if let Some(reason) = self.drawdown.halt_reason(user) {
+            return Decision::Reject(reason);
+        }
+        if let Some(max) = settings.max_open_positions {
+            if positions.count >= max {
+                return Decision::Reject(format!("{} positions are already open, max_open_positions is {}", positions.count, max));
+            }
+        }
+        let spent = self.with_ledger(day, |ledger| ledger.spent.get(user).copied().unwrap_or(0.0));
+        let exposure = positions.in_token;
+        let mut limit: Option<(f32, String)> = None;
+        if let Some(max) = settings.max_quote_per_day {
+            limit = Some((max - spent, format!("{} of max_quote_per_day {} is already spent", spent, max)));
+        }
+        if let Some(max) = settings.max_exposure_per_token {
+            let left = max - exposure;
+            if limit.as_ref().map(|(l, _)| left < *l).unwrap_or(true) {
+                limit = Some((left, format!("{} of max_exposure_per_token {} is already held in {}", exposure, max, symbol)));
+            }
+        }
+        match limit {
+            Some((left, reason)) if funds > left => {
+                if left <= 0.0 || settings.on_breach == BreachAction::Reject {
+                    Decision::Reject(reason)
+                } else {
+                    Decision::ScaleDown(left, reason)
+                }
+            }
+            _ => Decision::Allow(funds)
+        }
+    }
+    /// Checks one buy of `funds` quote against the user's limits, what it spent today and the
+    /// positions it holds.
+    pub fn assess(&self, user: &str, symbol: &str, funds: f32, positions: OpenPositions, settings: &UserSettings) -> Decision {
+        self.assess_on(today(), user, symbol, funds, positions, settings)
+    }
+    fn record_buy_on(&self, day: u128, user: &str, funds: f32) {
+        self.with_ledger(day, |ledger| {
+            *ledger.spent.entry(user.to_string()).or_insert(0.0) += funds;
+        })
+    }
+    pub fn record_buy(&self, user: &str, funds: f32) {
+        self.record_buy_on(today(), user, funds)
+    }
+    pub fn drawdown(&self) -> &DrawdownBreaker {
+        &self.drawdown
+    }
+    pub fn kill_switch(&self) -> &KillSwitch {
+        &self.kill_switch
+    }
+    /// Feeds the drawdown breakers, `complete` is set when every user could be valued.
+    pub fn record_equity(&self, limits: &RiskLimits, equities: &HashMap<String, f32>, complete: bool) {
+        self.drawdown.sample(today(), equities, complete, limits.max_user_drawdown_perc, limits.max_fleet_drawdown_perc)
+    }
+    /// Tokens users can't buy anymore today, to tell the sender up front. The limits are checked
+    /// again when the job runs, the balance and open positions are only known then.
+    pub fn preview(&self, config: &Config, tokens: &[String]) -> Vec<RiskNote> {
+        let mut notes = Vec::new();
+        for cred in &config.users {
+            let settings = UserSettings::resolve(config, cred);
+            for symbol_info in make_token_info_vec(tokens.to_vec()) {
+                let symbol = symbol_info.symbol_with_pair;
+                if let Decision::Reject(reason) = self.assess(&cred.name, &symbol, f32::MIN_POSITIVE, OpenPositions::default(), &settings) {
+                    notes.push(RiskNote { user: Some(cred.name.clone()), symbol, reason });
+                }
+            }
+        }
+        notes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::config::Config;
+    use crate::risk::risk::{BreachAction, Decision, OpenPositions, RiskEngine, RiskLimits};
+    use crate::user::settings::UserSettings;
+
+    const CONFIG: &str = r#"{
+        "port": 5000,
+        "take_profit_perc": 0.05,
+        "balance_perc": 0.5,
+        "risk": { "max_quote_per_day": 1.0, "max_exposure_per_token": 0.4, "max_open_positions": 2 },
+        "users": [{ "name": "a", "api_key": "k", "api_secret": "s", "api_pass": "p" }]
+    }"#;
+
+    fn tokens(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn caps_tokens_per_signal() {
+        let mut limits = RiskLimits { max_tokens_per_signal: Some(2), ..RiskLimits::default() };
+        let check = limits.check_signal(tokens(&["ETH", "XRP", "ADA"])).unwrap();
+        assert_eq!(check.tokens, tokens(&["ETH", "XRP"]));
+        assert_eq!(check.dropped.len(), 1);
+        assert_eq!(check.dropped[0].symbol, "ADA");
+        limits.on_breach = BreachAction::Reject;
+        assert!(limits.check_signal(tokens(&["ETH", "XRP", "ADA"])).is_err());
+        assert!(limits.check_signal(tokens(&["ETH"])).is_ok());
+    }
+
+    fn held(count: usize, in_token: f32) -> OpenPositions {
+        OpenPositions { count, in_token }
+    }
+
+    #[test]
+    fn scales_down_then_rejects_once_spent() {
+        let config = Config::from_json(CONFIG).unwrap();
+        config.validate().unwrap();
+        let mut settings = UserSettings::resolve(&config, &config.users[0]);
+        let risk = RiskEngine::default();
+        assert_eq!(risk.assess_on(1, "a", "ETH-BTC", 0.3, held(0, 0.0), &settings), Decision::Allow(0.3));
+        risk.record_buy_on(1, "a", 0.3);
+        match risk.assess_on(1, "a", "ETH-BTC", 0.3, held(1, 0.3), &settings) {
+            Decision::ScaleDown(funds, _) => assert!((funds - 0.1).abs() < 1e-6),
+            other => panic!("expected a scale down, got {:?}", other),
+        }
+        risk.record_buy_on(1, "a", 0.4);
+        risk.record_buy_on(1, "a", 0.3);
+        assert!(matches!(risk.assess_on(1, "a", "DOT-BTC", 0.1, held(0, 0.0), &settings), Decision::Reject(_)));
+        assert!(matches!(risk.assess_on(1, "a", "DOT-BTC", 0.1, held(2, 0.0), &settings), Decision::Reject(_)));
+        // A new day starts the spending over.
+        assert_eq!(risk.assess_on(2, "a", "DOT-BTC", 0.1, held(0, 0.0), &settings), Decision::Allow(0.1));
+        settings.on_breach = BreachAction::Reject;
+        assert!(matches!(risk.assess_on(2, "a", "DOT-BTC", 0.1, held(1, 0.35), &settings), Decision::Reject(_)));
+    }
+
+    #[test]
+    fn exposure_counts_positions_held_from_earlier_days() {
+        let config = Config::from_json(CONFIG).unwrap();
+        let settings = UserSettings::resolve(&config, &config.users[0]);
+        let risk = RiskEngine::default();
+        // Bought yesterday and still held, nothing spent today.
+        match risk.assess_on(2, "a", "ETH-BTC", 0.3, held(1, 0.3), &settings) {
+            Decision::ScaleDown(funds, reason) => {
+                assert!((funds - 0.1).abs() < 1e-6);
+                assert!(reason.contains("max_exposure_per_token"));
+            }
+            other => panic!("expected a scale down, got {:?}", other),
+        }
+        assert_eq!(risk.assess_on(2, "a", "XRP-BTC", 0.3, held(1, 0.0), &settings), Decision::Allow(0.3));
+    }
+
+    #[test]
+    fn kill_switch_rejects_every_buy() {
+        let config = Config::from_json(CONFIG).unwrap();
+        let settings = UserSettings::resolve(&config, &config.users[0]);
+        let risk = RiskEngine::default();
+        risk.kill_switch().engage(false).unwrap();
+        assert!(matches!(risk.assess_on(1, "a", "ETH-BTC", 0.1, held(0, 0.0), &settings), Decision::Reject(_)));
+        assert_eq!(risk.preview(&config, &["eth".to_string()]).len(), 1);
+        risk.kill_switch().rearm().unwrap();
+        assert_eq!(risk.assess_on(1, "a", "ETH-BTC", 0.1, held(0, 0.0), &settings), Decision::Allow(0.1));
+    }
+}
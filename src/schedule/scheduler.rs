@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::error::error::MintError;
+use crate::exchange::kucoin::server_clock::ServerClock;
+use crate::job::job::{Job, JobRegistry};
+
+/// Signals due more than this long ago, e.g. after the bot was down, are expired rather than bought.
+const MAX_LATE_MS: i64 = 60_000;
+/// Fired, cancelled and expired signals kept around for the listing.
+const MAX_DONE: usize = 500;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleState {
+    Pending,
+    Fired,
+    Cancelled,
+    Expired,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScheduledSignal {
+    pub id: u64,
+    pub tokens: Vec<String>,
+    /// Milliseconds since the epoch on Kucoin's clock.
+    pub execute_at: i64,
+    pub state: ScheduleState,
+    /// The job the signal became once fired.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<u64>,
+}
+
+/// What is written to the schedule file, only signals that haven't fired yet.
+#[derive(Serialize, Deserialize, Default)]
+struct ScheduleFile {
+    next_id: u64,
+    signals: Vec<ScheduledSignal>,
+}
+
+#[derive(Default, Clone, PartialEq)]
+struct Schedule {
+    next_id: u64,
+    signals: BTreeMap<u64, ScheduledSignal>,
+}
+
+/// Signals held until their `execute_at`, kept in a file so they survive a restart.
+#[derive(Clone)]
+pub struct Scheduler {
+    path: PathBuf,
+    schedule: Arc<Mutex<Schedule>>,
+    wake: Arc<Notify>,
+}
+
+fn invalid(msg: String) -> anyhow::Error {
+    MintError::from_str(msg).into()
+}
+
+impl Scheduler {
+    /// Reads the schedule file, a missing file is an empty schedule.
+    pub fn load(path: &Path) -> Result<Scheduler> {
+        let file = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str::<ScheduleFile>(&json)
+                .with_context(|| format!("Schedule file {} is not valid", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ScheduleFile::default(),
+            Err(e) => return Err(e).with_context(|| format!("Could not read schedule file {}", path.display()))
+        };
+        let signals = file.signals.into_iter().map(|signal| (signal.id, signal)).collect();
+        Ok(Scheduler {
+            path: path.to_path_buf(),
+            schedule: Arc::new(Mutex::new(Schedule { next_id: file.next_id, signals })),
+            wake: Arc::new(Notify::new()),
+        })
+    }
+    /// Runs `f` on a copy of the schedule that only replaces it once saved, a failed write leaves
+    /// the schedule as it was. Nothing is written when `f` changed nothing.
+    fn with_schedule<T>(&self, f: impl FnOnce(&mut Schedule) -> Result<T>) -> Result<T> {
+        let mut schedule = self.schedule.lock().unwrap_or_else(|e| e.into_inner());
+        let mut updated = schedule.clone();
+        let res = f(&mut updated)?;
+        if updated != *schedule {
+            self.save(&updated)?;
+            *schedule = updated;
+        }
+        Ok(res)
+    }
+    fn save(&self, schedule: &Schedule) -> Result<()> {
+        let file = ScheduleFile {
+            next_id: schedule.next_id,
+            signals: schedule.signals.values()
+                .filter(|signal| signal.state == ScheduleState::Pending)
+                .cloned()
+                .collect(),
+        };
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(&file)?)
+            .with_context(|| format!("Could not write {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Could not replace schedule file {}", self.path.display()))
+    }
+    pub fn add(&self, tokens: Vec<String>, execute_at: i64) -> Result<ScheduledSignal> {
+        let signal = self.with_schedule(|schedule| {
+            schedule.next_id += 1;
+            let signal = ScheduledSignal {
+                id: schedule.next_id,
+                tokens,
+                execute_at,
+                state: ScheduleState::Pending,
+                job_id: None,
+            };
+            schedule.signals.insert(signal.id, signal.clone());
+            Ok(signal)
+        })?;
+        self.wake.notify_one();
+        Ok(signal)
+    }
+    pub fn list(&self) -> Vec<ScheduledSignal> {
+        let schedule = self.schedule.lock().unwrap_or_else(|e| e.into_inner());
+        schedule.signals.values().cloned().collect()
+    }
+    /// Only signals that haven't fired yet can be cancelled.
+    pub fn cancel(&self, id: u64) -> Result<ScheduledSignal> {
+        self.with_schedule(|schedule| match schedule.signals.get_mut(&id) {
+            Some(signal) if signal.state == ScheduleState::Pending => {
+                signal.state = ScheduleState::Cancelled;
+                Ok(signal.clone())
+            }
+            Some(signal) => Err(invalid(format!("Scheduled signal {} is {:?} and can't be cancelled", id, signal.state))),
+            None => Err(invalid(format!("No scheduled signal {}", id)))
+        })
+    }
+    fn next_due(&self) -> Option<i64> {
+        let schedule = self.schedule.lock().unwrap_or_else(|e| e.into_inner());
+        schedule.signals.values()
+            .filter(|signal| signal.state == ScheduleState::Pending)
+            .map(|signal| signal.execute_at)
+            .min()
+    }
+    /// Marks the signals due at `now` as fired, or expired when they are too late, and returns
+    /// the fired ones. `job_for` turns each fired signal into a job, only once that is saved.
+    fn fire_due(&self, now: i64, mut job_for: impl FnMut(&ScheduledSignal) -> u64) -> Result<Vec<ScheduledSignal>> {
+        let fired = self.with_schedule(|schedule| {
+            let mut fired = Vec::new();
+            for signal in schedule.signals.values_mut() {
+                if signal.state != ScheduleState::Pending || signal.execute_at > now {
+                    continue;
+                }
+                if now - signal.execute_at > MAX_LATE_MS {
+                    println!("Scheduled signal {} is {} ms late, expiring it", signal.id, now - signal.execute_at);
+                    signal.state = ScheduleState::Expired;
+                    continue;
+                }
+                signal.state = ScheduleState::Fired;
+                fired.push(signal.clone());
+            }
+            let done: Vec<u64> = schedule.signals.values()
+                .filter(|signal| signal.state != ScheduleState::Pending)
+                .map(|signal| signal.id)
+                .collect();
+            for id in done.iter().take(done.len().saturating_sub(MAX_DONE)) {
+                schedule.signals.remove(id);
+            }
+            Ok(fired)
+        })?;
+        // Fired signals aren't in the file, their jobs are only kept in memory.
+        let mut schedule = self.schedule.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(fired.into_iter().map(|mut signal| {
+            signal.job_id = Some(job_for(&signal));
+            if let Some(kept) = schedule.signals.get_mut(&signal.id) {
+                kept.job_id = signal.job_id;
+            }
+            signal
+        }).collect())
+    }
+    /// Fires signals as they come due on Kucoin's clock. Sleeps at most a second at a time so
+    /// new and cancelled signals are picked up even without a wake up.
+    pub async fn run(self, clock: ServerClock, jobs: JobRegistry, send_job: tokio::sync::mpsc::Sender<Job>) {
+        loop {
+            let mut due_jobs = Vec::new();
+            let fired = self.fire_due(clock.now_ms(), |signal| {
+                let job = jobs.create(signal.tokens.clone());
+                let id = job.id;
+                due_jobs.push(job);
+                id
+            });
+            match fired {
+                Ok(fired) => fired.iter().for_each(|signal| println!("Fired scheduled signal {} as job {:?}", signal.id, signal.job_id)),
+                Err(e) => println!("{:?}", e)
+            }
+            for job in due_jobs {
+                if let Err(e) = send_job.send(job).await {
+                    println!("{}", e);
+                }
+            }
+            let wait = match self.next_due() {
+                Some(at) => (at - clock.now_ms()).clamp(0, 1000) as u64,
+                None => 1000
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_millis(wait)) => {}
+                _ = self.wake.notified() => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::schedule::scheduler::{ScheduleState, Scheduler};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("kucoin_arb_{}_{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn survives_a_restart_and_fires_once() {
+        let path = temp_path("schedule");
+        let _ = std::fs::remove_file(&path);
+        let scheduler = Scheduler::load(&path).unwrap();
+        let first = scheduler.add(vec!["ETH".to_string()], 1_000).unwrap();
+        let second = scheduler.add(vec!["XRP".to_string()], 2_000).unwrap();
+        scheduler.cancel(second.id).unwrap();
+        let reloaded = Scheduler::load(&path).unwrap();
+        let pending = reloaded.list();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, first.id);
+        assert_eq!(reloaded.fire_due(999, |_| 1).unwrap().len(), 0);
+        let fired = reloaded.fire_due(1_000, |_| 7).unwrap();
+        assert_eq!(fired[0].job_id, Some(7));
+        assert!(reloaded.fire_due(1_500, |_| 8).unwrap().is_empty());
+        assert!(reloaded.cancel(first.id).is_err());
+        // Ids keep counting after a restart.
+        assert_eq!(Scheduler::load(&path).unwrap().add(vec![], 3_000).unwrap().id, 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn expires_signals_that_are_too_late() {
+        let path = temp_path("schedule_late");
+        let _ = std::fs::remove_file(&path);
+        let scheduler = Scheduler::load(&path).unwrap();
+        let signal = scheduler.add(vec!["ETH".to_string()], 1_000).unwrap();
+        assert!(scheduler.fire_due(1_000 + 120_000, |_| 1).unwrap().is_empty());
+        assert_eq!(scheduler.list()[0].state, ScheduleState::Expired);
+        assert!(scheduler.cancel(signal.id).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn writes_only_changes_and_keeps_nothing_it_could_not_write() {
+        let path = temp_path("schedule_writes");
+        let _ = std::fs::remove_file(&path);
+        let scheduler = Scheduler::load(&path).unwrap();
+        scheduler.add(vec!["ETH".to_string()], 1_000).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(scheduler.fire_due(999, |_| 1).unwrap().is_empty());
+        assert!(!path.exists());
+
+        let unwritable = Scheduler::load(&std::env::temp_dir().join("kucoin_arb_missing_dir").join("scheduled.json")).unwrap();
+        assert!(unwritable.add(vec!["ETH".to_string()], 1_000).is_err());
+        assert!(unwritable.list().is_empty());
+        let mut created = 0;
+        assert!(unwritable.fire_due(1_000, |_| { created += 1; 1 }).unwrap().is_empty());
+        assert_eq!(created, 0);
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Counters since startup, served on `/metrics`.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct SignalMetrics {
+    pub signals_received: u64,
+    pub signals_accepted: u64,
+    pub signals_duplicate: u64,
+    pub tokens_cooling_down: u64,
+}
+
+/// What is left of a signal once duplicates and tokens in their cooldown are taken out.
+#[derive(Debug, PartialEq)]
+pub enum Filtered {
+    /// The same token set was accepted as job `of_job` within the window.
+    Duplicate { of_job: u64 },
+    Accepted { tokens: Vec<String>, cooling_down: Vec<String> },
+}
+
+#[derive(Default)]
+struct Seen {
+    signals: HashMap<Vec<String>, (Instant, u64)>,
+    tokens: HashMap<String, Instant>,
+    metrics: SignalMetrics,
+}
+
+/// Tokens uppercased, trimmed, sorted and without repeats, so `eth, XRP` and `xrp,ETH,eth` match.
+pub fn normalize(tokens: &[String]) -> Vec<String> {
+    let mut tokens: Vec<String> = tokens.iter()
+        .map(|t| t.trim().to_uppercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+/// Drops signals already seen within `dedup_window` and tokens accepted less than `token_cooldown`
+/// ago. The cooldown starts when a signal is accepted rather than once its buy fills, so a
+/// duplicate that is still queued can't slip through. A zero window turns the check off.
+#[derive(Clone, Default)]
+pub struct SignalFilter {
+    seen: Arc<Mutex<Seen>>,
+}
+
+impl SignalFilter {
+    pub fn new() -> Self {
+        SignalFilter::default()
+    }
+    fn with_seen<T>(&self, f: impl FnOnce(&mut Seen) -> T) -> T {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut seen)
+    }
+    /// `job_id` is the job the signal becomes if it is accepted.
+    pub fn filter(&self, tokens: &[String], job_id: u64, dedup_window: Duration, token_cooldown: Duration) -> Filtered {
+        self.filter_at(Instant::now(), tokens, job_id, dedup_window, token_cooldown)
+    }
+    fn filter_at(&self, now: Instant, tokens: &[String], job_id: u64, dedup_window: Duration, token_cooldown: Duration) -> Filtered {
+        let key = normalize(tokens);
+        self.with_seen(|seen| {
+            seen.metrics.signals_received += 1;
+            seen.signals.retain(|_, (at, _)| now.duration_since(*at) < dedup_window);
+            seen.tokens.retain(|_, at| now.duration_since(*at) < token_cooldown);
+            if let Some((_, of_job)) = seen.signals.get(&key) {
+                seen.metrics.signals_duplicate += 1;
+                return Filtered::Duplicate { of_job: *of_job };
+            }
+            let (cooling_down, tokens): (Vec<String>, Vec<String>) = key.iter()
+                .cloned()
+                .partition(|token| seen.tokens.contains_key(token));
+            seen.metrics.tokens_cooling_down += cooling_down.len() as u64;
+            if !dedup_window.is_zero() {
+                seen.signals.insert(key, (now, job_id));
+            }
+            if !token_cooldown.is_zero() {
+                for token in &tokens {
+                    seen.tokens.insert(token.clone(), now);
+                }
+            }
+            if !tokens.is_empty() {
+                seen.metrics.signals_accepted += 1;
+            }
+            Filtered::Accepted { tokens, cooling_down }
+        })
+    }
+    pub fn metrics(&self) -> SignalMetrics {
+        self.with_seen(|seen| seen.metrics.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::signal::signal_filter::{Filtered, SignalFilter};
+
+    fn tokens(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn suppresses_the_same_set_within_the_window() {
+        let filter = SignalFilter::new();
+        let now = Instant::now();
+        let window = Duration::from_secs(30);
+        let first = filter.filter_at(now, &tokens(&["eth", "XRP"]), 1, window, Duration::from_secs(0));
+        assert_eq!(first, Filtered::Accepted { tokens: tokens(&["ETH", "XRP"]), cooling_down: vec![] });
+        let again = filter.filter_at(now + Duration::from_secs(5), &tokens(&[" xrp", "ETH", "eth"]), 2, window, Duration::from_secs(0));
+        assert_eq!(again, Filtered::Duplicate { of_job: 1 });
+        let later = filter.filter_at(now + Duration::from_secs(31), &tokens(&["ETH", "XRP"]), 3, window, Duration::from_secs(0));
+        assert!(matches!(later, Filtered::Accepted { .. }));
+        let metrics = filter.metrics();
+        assert_eq!((metrics.signals_received, metrics.signals_accepted, metrics.signals_duplicate), (3, 2, 1));
+    }
+
+    #[test]
+    fn holds_tokens_back_during_the_cooldown() {
+        let filter = SignalFilter::new();
+        let now = Instant::now();
+        let cooldown = Duration::from_secs(60);
+        filter.filter_at(now, &tokens(&["ETH"]), 1, Duration::from_secs(0), cooldown);
+        let next = filter.filter_at(now + Duration::from_secs(10), &tokens(&["ETH", "ADA"]), 2, Duration::from_secs(0), cooldown);
+        assert_eq!(next, Filtered::Accepted { tokens: tokens(&["ADA"]), cooling_down: tokens(&["ETH"]) });
+        let after = filter.filter_at(now + Duration::from_secs(61), &tokens(&["ETH"]), 3, Duration::from_secs(0), cooldown);
+        assert_eq!(after, Filtered::Accepted { tokens: tokens(&["ETH"]), cooling_down: vec![] });
+        assert_eq!(filter.metrics().tokens_cooling_down, 1);
+    }
+}
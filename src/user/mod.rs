@@ -1,2 +1,3 @@
 pub mod user_manager;
 pub mod user;
+pub mod settings;
@@ -0,0 +1,133 @@
+use serde::Serialize;
+
+use crate::config::config::Config;
+use crate::exchange::api_credentials::ApiCredentials;
+use crate::risk::risk::BreachAction;
+
+/// What a user actually trades with once its overrides are laid over the fleet wide config.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct UserSettings {
+    pub take_profit_perc: f32,
+    pub balance_perc: f32,
+    pub max_quote_per_trade: Option<f32>,
+    /// Most positions held at once, a buy counts until its take profit is done.
+    pub max_open_positions: Option<usize>,
+    pub max_quote_per_day: Option<f32>,
+    pub max_exposure_per_token: Option<f32>,
+    pub on_breach: BreachAction,
+    pub allowed_symbols: Option<Vec<String>>,
+    pub denied_symbols: Vec<String>,
+    pub simulated: bool,
+    pub auto_fund_cap: Option<f32>,
+    pub sweep_to_main: bool,
+}
+
+impl UserSettings {
+    pub fn resolve(config: &Config, cred: &ApiCredentials) -> Self {
+        let upper = |symbols: &Vec<String>| symbols.iter().map(|s| s.to_uppercase()).collect::<Vec<String>>();
+        UserSettings {
+            take_profit_perc: cred.take_profit_perc.unwrap_or(config.take_profit_perc),
+            balance_perc: cred.balance_perc.unwrap_or(config.balance_perc),
+            max_quote_per_trade: cred.max_quote_per_trade,
+            max_open_positions: cred.max_open_positions.or(config.risk.max_open_positions),
+            max_quote_per_day: cred.max_quote_per_day.or(config.risk.max_quote_per_day),
+            max_exposure_per_token: cred.max_exposure_per_token.or(config.risk.max_exposure_per_token),
+            on_breach: config.risk.on_breach,
+            allowed_symbols: cred.allowed_symbols.as_ref().map(upper),
+            denied_symbols: upper(&cred.denied_symbols),
+            simulated: cred.simulated,
+            auto_fund_cap: cred.auto_fund_cap.or(config.auto_fund_cap),
+            sweep_to_main: cred.sweep_to_main.unwrap_or(config.sweep_to_main),
+        }
+    }
+    /// Returns why the token may not be bought, `symbol` being the base currency e.g. `ETH`.
+    pub fn symbol_rejection(&self, symbol: &str) -> Option<String> {
+        if self.denied_symbols.iter().any(|s| s == symbol) {
+            return Some(format!("{} is denied for this user", symbol));
+        }
+        match &self.allowed_symbols {
+            Some(allowed) if !allowed.iter().any(|s| s == symbol) => Some(format!("{} is not in the allowed symbols", symbol)),
+            _ => None
+        }
+    }
+    /// Quote to spend on one of `num_tokens` tokens bought from `balance`.
+    pub fn funds_per_token(&self, balance: f32, num_tokens: f32) -> f32 {
+        let funds = balance * self.balance_perc / num_tokens;
+        match self.max_quote_per_trade {
+            Some(max) => funds.min(max),
+            None => funds
+        }
+    }
+    /// Quote to move from Main so every buy of the signal can be paid from Trade, counting
+    /// what sits in Main up to the cap as part of the balance. Zero without auto funding.
+    pub fn funding_needed(&self, trade: f32, main: f32, num_tokens: f32) -> f32 {
+        let movable = match self.auto_fund_cap {
+            Some(cap) => cap.min(main).max(0.0),
+            None => return 0.0
+        };
+        let wanted = self.funds_per_token(trade + movable, num_tokens) * num_tokens;
+        (wanted - trade).max(0.0).min(movable)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::config::Config;
+    use crate::user::settings::UserSettings;
+
+    const CONFIG: &str = r#"{
+        "port": 5000,
+        "take_profit_perc": 0.05,
+        "balance_perc": 0.5,
+        "sweep_to_main": true,
+        "users": [
+            { "name": "a", "api_key": "k", "api_secret": "s", "api_pass": "p" },
+            { "name": "b", "api_key": "k", "api_secret": "s", "api_pass": "p",
+              "take_profit_perc": 0.2, "max_quote_per_trade": 0.01,
+              "allowed_symbols": ["eth", "xrp"], "denied_symbols": ["doge"], "simulated": true,
+              "auto_fund_cap": 0.5, "sweep_to_main": false }
+        ]
+    }"#;
+
+    #[test]
+    fn overrides_fall_back_to_config() {
+        let config = Config::from_json(CONFIG).unwrap();
+        config.validate().unwrap();
+        let a = UserSettings::resolve(&config, &config.users[0]);
+        let b = UserSettings::resolve(&config, &config.users[1]);
+        assert_eq!(a.take_profit_perc, 0.05);
+        assert_eq!(b.take_profit_perc, 0.2);
+        assert_eq!(b.balance_perc, 0.5);
+        assert!(b.simulated && !a.simulated);
+        assert_eq!(a.funds_per_token(1.0, 2.0), 0.25);
+        assert_eq!(b.funds_per_token(1.0, 2.0), 0.01);
+    }
+
+    #[test]
+    fn filters_symbols() {
+        let config = Config::from_json(CONFIG).unwrap();
+        let a = UserSettings::resolve(&config, &config.users[0]);
+        let b = UserSettings::resolve(&config, &config.users[1]);
+        assert_eq!(a.symbol_rejection("DOGE"), None);
+        assert!(b.symbol_rejection("DOGE").is_some());
+        assert!(b.symbol_rejection("ADA").is_some());
+        assert_eq!(b.symbol_rejection("ETH"), None);
+    }
+
+    #[test]
+    fn funds_from_main_up_to_the_cap() {
+        let config = Config::from_json(CONFIG).unwrap();
+        let a = UserSettings::resolve(&config, &config.users[0]);
+        let mut b = UserSettings::resolve(&config, &config.users[1]);
+        assert!(a.sweep_to_main && !b.sweep_to_main);
+        assert_eq!(a.funding_needed(0.0, 1.0, 2.0), 0.0);
+        b.max_quote_per_trade = None;
+        // Half of 0.2 + 0.5 is wanted, 0.2 is already in Trade.
+        assert!((b.funding_needed(0.2, 3.0, 2.0) - 0.15).abs() < 1e-6);
+        assert_eq!(b.funding_needed(1.0, 3.0, 2.0), 0.0);
+        assert!((b.funding_needed(0.0, 0.1, 1.0) - 0.05).abs() < 1e-6);
+        // Capped by max_quote_per_trade again.
+        b.max_quote_per_trade = Some(0.01);
+        assert!((b.funding_needed(0.0, 3.0, 2.0) - 0.02).abs() < 1e-6);
+    }
+}
@@ -1,12 +1,29 @@
-use anyhow::Result;
-use dotenv::dotenv;
-use dotenv_codegen::dotenv;
+use std::collections::HashMap;
 
+use anyhow::{Context, Result};
+use kucoin_rs_custom::kucoin::model::user::AccountType;
+use kucoin_rs_custom::kucoin::trade::MAX_BATCH_ORDERS;
+
+use crate::config::config::Config;
 use crate::exchange::an_exchange::AnExchange;
 use crate::exchange::api_credentials::ApiCredentials;
-use crate::exchange::kucoin::kucoin::KucoinExchange;
-use crate::exchange::order::{Order, OrderSide};
-use crate::exchange::kucoin::token_info::SymbolInfo;
+use crate::exchange::kucoin::kucoin::{KucoinExchange, DENOMINATION};
+use crate::exchange::order::{LimitOrderRequest, Order, OrderKind, OrderSide};
+use crate::exchange::kucoin::token_info::{prep_symbol_for_kucoin, SymbolInfo};
+use crate::job::job::{Job, JobEvent};
+use crate::risk::kill_switch::KillReport;
+use crate::risk::risk::{Decision, OpenPositions, RiskEngine};
+use crate::user::settings::UserSettings;
+use crate::utils::retry::{classify, retry, retry_placement, FailureKind, RetryPolicy};
+use crate::utils::time::get_ms_str;
+
+/// A take profit sell, the position it closes is held until it is done.
+struct TakeProfit {
+    order_id: String,
+    symbol: String,
+    /// Quote spent on the buy it closes.
+    cost: f32,
+}
 
 pub struct User {
     balance: f32,
@@ -15,41 +32,137 @@ pub struct User {
     alive: bool,
     health: i8,
     active_orders: Vec<Order>,
-    take_profit_perc: f32,
-    balance_perc: f32,
+    settings: UserSettings,
+    /// Set once the account is removed; it takes no new buys but sees the take profits of a running job through.
+    retired: bool,
+    /// Take profits placed and not done yet, each one a position still held. Their proceeds go back
+    /// to Main once they fill when `UserSettings::sweep_to_main` is set.
+    take_profits: Vec<TakeProfit>,
 }
 
 impl User {
-    pub async fn new(api_credentials: ApiCredentials) -> Self {
-        let mut exchange = KucoinExchange::new(api_credentials.clone()).await;
-        let balance = exchange.get_denomination_balance().await.expect(&*format!("Could not get balance for {} on startup!", &api_credentials.name));
-        dotenv().ok();
-        User {
+    /// Connects with the given keys, fetching the balance doubles as a check that they work.
+    pub async fn connect(api_credentials: &ApiCredentials) -> Result<(KucoinExchange, f32)> {
+        let exchange = KucoinExchange::new(api_credentials.clone()).await
+            .with_context(|| format!("Could not connect {} to Kucoin!", &api_credentials.name))?;
+        let balance = exchange.get_denomination_balance().await
+            .with_context(|| format!("Could not get balance for {}!", &api_credentials.name))?;
+        Ok((exchange, balance))
+    }
+    /// `connect` for new keys of this user's account, the new client shares the current one's rate limits.
+    pub async fn reconnect(&self, api_credentials: &ApiCredentials) -> Result<(KucoinExchange, f32)> {
+        let exchange = self.exchange.reconnect(api_credentials)?;
+        let balance = exchange.get_denomination_balance().await
+            .with_context(|| format!("Could not get balance for {}!", &api_credentials.name))?;
+        Ok((exchange, balance))
+    }
+    pub async fn new(api_credentials: ApiCredentials, config: &Config) -> Result<Self> {
+        let (exchange, balance) = User::connect(&api_credentials).await?;
+        Ok(User {
             balance,
             exchange,
-            api_credentials,
             alive: true,
             health: 10,
             active_orders: Vec::new(),
-            take_profit_perc: String::from(dotenv!("TAKE_PROFIT_PERC"))
-                .parse::<f32>()
-                .expect("Got bad value for TAKE_PROFIT_PERC!"),
-            balance_perc: String::from(dotenv!("BALANCE_PERC"))
-                .parse::<f32>()
-                .expect("Got bad value for BALANCE_PERC!"),
+            settings: UserSettings::resolve(config, &api_credentials),
+            api_credentials,
+            retired: false,
+            take_profits: Vec::new(),
+        })
+    }
+    pub fn name(&self) -> &str {
+        &self.api_credentials.name
+    }
+    /// Picks up reloaded settings. New keys reconnect the exchange client, orders already placed are kept.
+    pub async fn apply_config(&mut self, config: &Config) {
+        match config.users.iter().find(|cred| cred.name == self.api_credentials.name) {
+            Some(cred) => {
+                self.retired = false;
+                self.settings = UserSettings::resolve(config, cred);
+                let reconnect = !cred.same_keys(&self.api_credentials);
+                self.api_credentials = cred.clone();
+                if reconnect {
+                    match self.exchange.reconnect(&self.api_credentials) {
+                        Ok(exchange) => self.exchange = exchange,
+                        Err(e) => println!("Could not reconnect {} with the new keys: {:?}", &self.api_credentials.name, e)
+                    }
+                }
+            }
+            None => self.retired = true
+        }
+    }
+    pub fn retire(&mut self) {
+        self.retired = true;
+    }
+    pub fn replace_exchange(&mut self, api_credentials: ApiCredentials, exchange: KucoinExchange, balance: f32) {
+        self.api_credentials.set_secrets(&api_credentials.secrets());
+        self.exchange = exchange;
+        self.balance = balance;
+    }
+    /// Cancels everything open on the account and gives up on take profits not placed yet.
+    pub async fn cancel_open_orders(&mut self) -> Result<()> {
+        if !self.settings.simulated {
+            retry(&RetryPolicy::default(), || self.exchange.cancel_all_open_orders()).await?;
+        }
+        for order in &mut self.active_orders {
+            order.alive = false;
+        }
+        Ok(())
+    }
+    /// Cancels the stop orders that haven't triggered yet, Kucoin keeps them apart from the other open orders.
+    pub async fn cancel_stop_orders(&mut self) -> Result<()> {
+        if !self.settings.simulated {
+            retry(&RetryPolicy::default(), || self.exchange.cancel_all_stop_orders()).await?;
+        }
+        Ok(())
+    }
+    /// Market sells everything in the Trade account that has a pair with the denomination.
+    /// Holdings that can't be sold are reported in `report.errors`.
+    pub async fn flatten(&mut self, report: &mut KillReport) {
+        if self.settings.simulated {
+            return;
+        }
+        let policy = RetryPolicy::default();
+        let exchange = &self.exchange;
+        let holdings = match retry(&policy, || exchange.get_trade_holdings()).await {
+            Ok(t) => t,
+            Err(e) => {
+                report.errors.push(format!("{:#}", e));
+                return;
+            }
+        };
+        for (currency, available) in holdings {
+            let symbol = prep_symbol_for_kucoin(currency.clone());
+            let size = match exchange.round_to_sig_digits_base(&symbol, available) {
+                Ok(t) => t,
+                Err(_) => {
+                    report.errors.push(format!("{} has no {} pair to sell into", currency, DENOMINATION));
+                    continue;
+                }
+            };
+            let sold = match get_ms_str() {
+                Ok(client_oid) => retry_placement(&policy, &client_oid,
+                    || exchange.market_sell_size(&client_oid, &symbol, &size),
+                    || exchange.find_order(&client_oid)).await,
+                Err(e) => Err(e)
+            };
+            match sold {
+                Ok(order) => report.sold.push(order.symbol),
+                Err(e) => report.errors.push(format!("{:#}", e))
+            }
         }
     }
     fn remove_dead(&mut self) {
         self.active_orders = self.active_orders.clone().into_iter().filter(|order| order.alive).collect();
     }
-    async fn refresh_exchange_connection(&mut self) {
-        self.exchange = KucoinExchange::new(self.api_credentials.clone()).await
-    }
     async fn refresh_balance(&mut self) -> Result<()> {
         Ok(self.balance = self.exchange.get_denomination_balance().await?)
     }
+    /// Keeps the client, and with it the account's rate limits, only the symbol info is fetched again.
     pub async fn refresh(&mut self) {
-        self.refresh_exchange_connection().await;
+        if let Err(e) = self.exchange.refresh().await {
+            println!("Could not refresh the symbol info for {}: {:?}", &self.api_credentials.name, e);
+        }
         match self.refresh_balance().await {
             Err(e) => {
                 println!("{}", e);
@@ -57,90 +170,344 @@ impl User {
             }
             _ => {}
         }
+        self.check_take_profits().await;
         self.remove_dead();
     }
+    /// Forgets the take profits that are done since the last refresh, closing their positions, and
+    /// moves their proceeds back to Main when `sweep_to_main` is set. Orders still open are checked
+    /// again next time, as are ones Kucoin couldn't be asked about; ones that fail to sweep are dropped.
+    async fn check_take_profits(&mut self) {
+        let policy = RetryPolicy::default();
+        let exchange = &self.exchange;
+        let mut still_open = Vec::new();
+        for take_profit in self.take_profits.drain(..) {
+            let order_id = &take_profit.order_id;
+            let proceeds = match retry(&policy, || exchange.get_sell_proceeds(order_id)).await {
+                Ok(Some(t)) => t,
+                Ok(None) => {
+                    still_open.push(take_profit);
+                    continue;
+                }
+                Err(e) => {
+                    println!("{:?}", e);
+                    if classify(&e) == FailureKind::Retryable {
+                        still_open.push(take_profit);
+                    }
+                    continue;
+                }
+            };
+            if !self.settings.sweep_to_main {
+                continue;
+            }
+            let swept = match (retry(&policy, || exchange.get_denomination_balance()).await, get_ms_str()) {
+                (Ok(trade), Ok(client_oid)) if proceeds.min(trade) > 0.0 => {
+                    let amount = proceeds.min(trade);
+                    retry(&policy, || exchange.transfer_denomination(&client_oid, amount, AccountType::Trade, AccountType::Main)).await
+                        .map(|_| amount)
+                }
+                (Err(e), _) | (_, Err(e)) => Err(e),
+                _ => Ok(0.0)
+            };
+            match swept {
+                Ok(amount) => println!("Swept {} {} of take profit {} to Main for {}", amount, DENOMINATION, order_id, &self.api_credentials.name),
+                Err(e) => println!("{:?}", e)
+            }
+        }
+        self.take_profits = still_open;
+    }
+    /// Main and Trade holdings valued in the denomination at `prices`.
+    pub async fn equity(&self, prices: &HashMap<String, f32>) -> Result<f32> {
+        retry(&RetryPolicy::default(), || self.exchange.get_equity(prices)).await
+    }
     fn lower_health(&mut self, amount: i8) {
         self.health -= amount;
         if self.health <= 0 {
             self.alive = false;
         }
     }
-    async fn buy_token(&mut self, symbol_info: SymbolInfo, num_tokens: f32) {
+    /// Records a failed exchange call on the job. Only errors that were still failing after retries
+    /// count against the user's health, a fatal rejection says nothing about the connection.
+    fn report_failure(&mut self, job: &Job, symbol: &str, e: &anyhow::Error) -> FailureKind {
+        println!("{:?}", e);
+        let kind = classify(e);
+        job.record(JobEvent::Failed {
+            user: Some(self.api_credentials.name.clone()),
+            symbol: symbol.to_string(),
+            fatal: kind == FailureKind::Fatal,
+            reason: format!("{:#}", e),
+        });
+        if kind == FailureKind::Retryable {
+            self.lower_health(1);
+        }
+        kind
+    }
+    async fn buy_token(&mut self, symbol_info: SymbolInfo, balance_per_token: f32, job: &Job, risk: &RiskEngine) {
         if !self.alive {
             println!("Tried to buy but user {} is dead!", &self.api_credentials.name);
             return;
         }
-        let balance_per_token = self.balance * self.balance_perc / num_tokens;
+        if self.retired {
+            return;
+        }
+        if let Some(reason) = self.buy_rejection(&symbol_info) {
+            job.record(JobEvent::Skipped {
+                user: self.api_credentials.name.clone(),
+                symbol: symbol_info.symbol_with_pair,
+                reason,
+            });
+            return;
+        }
+        let positions = self.open_positions(&symbol_info.symbol_with_pair);
+        let balance_per_token = match risk.assess(&self.api_credentials.name, &symbol_info.symbol_with_pair, balance_per_token, positions, &self.settings) {
+            Decision::Allow(funds) => funds,
+            Decision::ScaleDown(funds, reason) => {
+                job.record(JobEvent::RiskScaledDown {
+                    user: self.api_credentials.name.clone(),
+                    symbol: symbol_info.symbol_with_pair.clone(),
+                    funds,
+                    reason,
+                });
+                funds
+            }
+            Decision::Reject(reason) => {
+                job.record(JobEvent::RiskRejected {
+                    user: Some(self.api_credentials.name.clone()),
+                    symbol: symbol_info.symbol_with_pair,
+                    reason,
+                });
+                return;
+            }
+        };
         if let Ok(funds) = self.exchange.round_to_sig_digits_price(&symbol_info.symbol_with_pair, balance_per_token) {
-            match self.exchange.market_order(
-                symbol_info.symbol_with_pair,
-                funds,
-                OrderSide::Buy,
-            ).await {
-                Ok(order) => {
+            let exchange = &self.exchange;
+            let symbol = &symbol_info.symbol_with_pair;
+            let simulated = self.settings.simulated;
+            let order = match get_ms_str() {
+                Ok(client_oid) if simulated => {
+                    println!("Simulated market buy of {} {} for {}", &funds, symbol, &self.api_credentials.name);
+                    Ok(Order::new_simulated(symbol.clone(), OrderKind::Market, OrderSide::Buy, &client_oid))
+                }
+                Ok(client_oid) => retry_placement(&RetryPolicy::default(), &client_oid, || exchange.market_order(
+                    client_oid.clone(),
+                    symbol.clone(),
+                    funds.clone(),
+                    OrderSide::Buy,
+                ), || exchange.find_order(&client_oid)).await,
+                Err(e) => Err(e)
+            };
+            match order {
+                Ok(mut order) => {
+                    order.cost = funds.parse::<f32>().unwrap_or(balance_per_token);
+                    risk.record_buy(&self.api_credentials.name, order.cost);
+                    job.record(JobEvent::Bought {
+                        user: self.api_credentials.name.clone(),
+                        symbol: order.symbol.clone(),
+                        order_id: order.order_id.clone(),
+                        simulated: order.simulated,
+                    });
                     let num_orders = self.active_orders.len();
                     self.active_orders.insert(num_orders, order);
                 }
                 Err(e) => {
-                    println!("{}", e);
-                    self.lower_health(1);
+                    self.report_failure(job, &symbol_info.symbol_with_pair, &e);
                 }
             }
         }
     }
-    pub async fn buy_tokens(&mut self, symbols: Vec<SymbolInfo>) {
+    /// Buys still waiting on their take profit plus take profits that haven't filled yet, with what
+    /// was spent on the ones in `symbol`.
+    fn open_positions(&self, symbol: &str) -> OpenPositions {
+        let buys = self.active_orders.iter()
+            .filter(|order| order.alive)
+            .map(|order| (order.symbol.as_str(), order.cost));
+        let sells = self.take_profits.iter().map(|tp| (tp.symbol.as_str(), tp.cost));
+        buys.chain(sells).fold(OpenPositions::default(), |mut positions, (held, cost)| {
+            positions.count += 1;
+            if held == symbol {
+                positions.in_token += cost;
+            }
+            positions
+        })
+    }
+    fn buy_rejection(&self, symbol_info: &SymbolInfo) -> Option<String> {
+        self.settings.symbol_rejection(&symbol_info.symbol)
+    }
+    pub async fn buy_tokens(&mut self, symbols: Vec<SymbolInfo>, job: &Job, risk: &RiskEngine) {
         let num_tokens = symbols.len() as f32;
+        let balance_per_token = match self.settings.auto_fund_cap {
+            Some(_) if self.alive && !self.retired => self.fund_trade_account(num_tokens, job).await,
+            _ => self.settings.funds_per_token(self.balance, num_tokens)
+        };
         for symbol in symbols {
-            self.buy_token(symbol, num_tokens).await;
+            self.buy_token(symbol, balance_per_token, job, risk).await;
+        }
+    }
+    /// Tops up the Trade account from Main so the buys aren't undersized when the quote sits in Main.
+    /// Returns the quote to spend per token, based on what actually made it into Trade.
+    async fn fund_trade_account(&mut self, num_tokens: f32, job: &Job) -> f32 {
+        let policy = RetryPolicy::default();
+        let exchange = &self.exchange;
+        let balances = match retry(&policy, || exchange.get_denomination_balance()).await {
+            Ok(trade) => retry(&policy, || exchange.get_main_denomination_balance()).await
+                .map(|main| (trade, main)),
+            Err(e) => Err(e)
+        };
+        let (trade, main) = match balances {
+            Ok(t) => t,
+            Err(e) => {
+                self.report_failure(job, DENOMINATION, &e);
+                return self.settings.funds_per_token(self.balance, num_tokens);
+            }
+        };
+        let amount = self.settings.funding_needed(trade, main, num_tokens);
+        if amount <= 0.0 {
+            self.balance = trade;
+            return self.settings.funds_per_token(trade, num_tokens);
+        }
+        let simulated = self.settings.simulated;
+        let moved = match get_ms_str() {
+            Ok(_) if simulated => {
+                println!("Simulated move of {} {} from Main to Trade for {}", amount, DENOMINATION, &self.api_credentials.name);
+                Ok(())
+            }
+            Ok(client_oid) => retry(&policy, || exchange.transfer_denomination(&client_oid, amount, AccountType::Main, AccountType::Trade)).await,
+            Err(e) => Err(e)
+        };
+        match moved {
+            Ok(_) => {
+                job.record(JobEvent::Funded {
+                    user: self.api_credentials.name.clone(),
+                    amount,
+                    simulated,
+                });
+                self.balance = trade + amount;
+                // The share of Trade and Main together, which is now all in Trade.
+                let movable = self.settings.auto_fund_cap.unwrap_or(0.0).min(main);
+                self.settings.funds_per_token(trade + movable, num_tokens)
+                    .min(self.balance / num_tokens)
+            }
+            Err(e) => {
+                self.balance = trade;
+                self.report_failure(job, DENOMINATION, &e);
+                self.settings.funds_per_token(trade, num_tokens)
+            }
         }
     }
-    async fn try_place_one_sell_limit(&mut self, symbol_info: &SymbolInfo) -> bool {
-        let mut acc_errors: i8 = 0;
+    /// Places the take profits of every live buy on this symbol. What is held is split evenly
+    /// between them and they go out in batches, one request per `MAX_BATCH_ORDERS` orders.
+    async fn try_place_one_sell_limit(&mut self, symbol_info: &SymbolInfo, job: &Job) -> bool {
+        // Each failure with the orders it leaves without a take profit.
+        let mut failures: Vec<(Vec<usize>, anyhow::Error)> = Vec::new();
         let mut all_orders_finished = true;
-        for order in &mut self.active_orders {
+        let policy = RetryPolicy::default();
+        let price = symbol_info.price.unwrap();
+        let price = price + (price * self.settings.take_profit_perc);
+        let mut pending: Vec<usize> = Vec::new();
+        for (i, order) in self.active_orders.iter_mut().enumerate() {
             if !order.alive || order.symbol != symbol_info.symbol_with_pair {
                 continue;
             }
-            let quantity = match self.exchange.get_balance_of(&*symbol_info.symbol.clone()).await {
-                Ok(t) => t,
-                Err(e) => {
-                    println!("{}", e);
-                    all_orders_finished = false;
-                    order.lower_health();
-                    continue;
+            if order.simulated {
+                println!("Simulated take profit of {} at {} for {}", &order.symbol, price, &self.api_credentials.name);
+                job.record(JobEvent::TakeProfitPlaced {
+                    user: self.api_credentials.name.clone(),
+                    symbol: order.symbol.clone(),
+                    order_id: order.order_id.clone(),
+                    simulated: true,
+                });
+                order.alive = false;
+                continue;
+            }
+            pending.push(i);
+        }
+        if pending.is_empty() {
+            return all_orders_finished;
+        }
+        let exchange = &self.exchange;
+        let symbol = &symbol_info.symbol_with_pair;
+        let quantity = match retry(&policy, || exchange.get_balance_of(&symbol_info.symbol)).await {
+            Ok(t) => Some(t),
+            Err(e) => {
+                println!("{}", e);
+                match classify(&e) {
+                    FailureKind::Fatal => failures.push((pending.clone(), e)),
+                    FailureKind::Retryable => for i in &pending {
+                        self.active_orders[*i].lower_health();
+                    }
                 }
-            };
-            let price = symbol_info.price.unwrap();
-            let price = price + (price * self.take_profit_perc);
-            if let Ok(price) = self.exchange.round_to_sig_digits_price(&symbol_info.symbol_with_pair, price) {
-                if let Ok(quantity) = self.exchange.round_to_sig_digits_base(&symbol_info.symbol_with_pair, quantity) {
-                    match self.exchange.limit_order(
-                        &symbol_info.symbol_with_pair,
-                        &quantity,
-                        &price,
-                        OrderSide::Sell
-                    ).await {
-                        Ok(_) => {
-                            order.alive = false;
-                        }
+                all_orders_finished = false;
+                None
+            }
+        };
+        let rounded = quantity.map(|quantity| (
+            exchange.round_to_sig_digits_price(symbol, price),
+            exchange.round_to_sig_digits_base(symbol, quantity / pending.len() as f32)
+        ));
+        if let Some((Ok(price), Ok(quantity))) = rounded {
+            let mut placed: Vec<(usize, Result<Order>)> = Vec::new();
+            match get_ms_str() {
+                Ok(ms) => for (n, chunk) in pending.chunks(MAX_BATCH_ORDERS).enumerate() {
+                    // Kept across retries to find out whether Kucoin took a batch whose request failed.
+                    let requests: Vec<LimitOrderRequest> = (0..chunk.len()).map(|k| LimitOrderRequest {
+                        client_oid: format!("{}-{}", ms, n * MAX_BATCH_ORDERS + k),
+                        quantity: quantity.clone(),
+                        price: price.clone(),
+                        side: OrderSide::Sell
+                    }).collect();
+                    let client_oids: Vec<&str> = requests.iter().map(|r| r.client_oid.as_str()).collect();
+                    match retry_placement(&policy, &client_oids.join(","),
+                        || exchange.limit_orders(symbol, &requests),
+                        || exchange.find_orders(&requests)).await {
+                        Ok(results) => placed.extend(chunk.iter().copied().zip(results)),
                         Err(e) => {
-                            println!("{}", e);
                             all_orders_finished = false;
-                            acc_errors += 1;
+                            failures.push((chunk.to_vec(), e));
                         }
                     }
+                },
+                Err(e) => {
+                    all_orders_finished = false;
+                    failures.push((pending.clone(), e));
+                }
+            }
+            for (i, sell) in placed {
+                match sell {
+                    Ok(sell_order) => {
+                        self.take_profits.push(TakeProfit {
+                            order_id: sell_order.order_id.clone(),
+                            symbol: sell_order.symbol.clone(),
+                            cost: self.active_orders[i].cost,
+                        });
+                        job.record(JobEvent::TakeProfitPlaced {
+                            user: self.api_credentials.name.clone(),
+                            symbol: sell_order.symbol,
+                            order_id: sell_order.order_id,
+                            simulated: false,
+                        });
+                        self.active_orders[i].alive = false;
+                    }
+                    Err(e) => {
+                        all_orders_finished = false;
+                        failures.push((vec![i], e));
+                    }
                 }
             }
         }
-        if acc_errors > 0 {
-            self.lower_health(acc_errors);
+        for (orders, e) in failures {
+            if self.report_failure(job, &symbol_info.symbol_with_pair, &e) == FailureKind::Fatal {
+                // The exchange won't accept these take profits no matter how often they're sent,
+                // the other orders on the symbol are still tried.
+                for i in orders {
+                    self.active_orders[i].alive = false;
+                }
+            }
         }
         all_orders_finished
     }
-    pub async fn try_place_sell_limit(&mut self, symbols: &Vec<SymbolInfo>) -> bool {
+    pub async fn try_place_sell_limit(&mut self, symbols: &Vec<SymbolInfo>, job: &Job) -> bool {
         let mut all_finished = true;
         for symbol in symbols {
-            if !self.try_place_one_sell_limit(symbol).await {
+            if !self.try_place_one_sell_limit(symbol, job).await {
                 all_finished = false;
             }
         }
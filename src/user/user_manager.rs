@@ -1,66 +1,227 @@
-use crate::exchange::api_credentials::load_api_credentials;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::config::config::Config;
+use crate::error::error::MintError;
+use crate::exchange::api_credentials::ApiCredentials;
 use crate::user::user::User;
+use crate::exchange::kucoin::exchange_info::get_denomination_prices_kc;
 use crate::exchange::kucoin::token_info::SymbolInfo;
+use crate::job::job::{Job, JobEvent};
+use crate::risk::kill_switch::KillReport;
+use crate::risk::risk::RiskEngine;
+use crate::utils::retry::{classify, retry, FailureKind, RetryPolicy};
+
+pub type SharedUser = Arc<Mutex<User>>;
 
+/// Users are locked one at a time, so admin changes to one account only wait for the step a job
+/// is taking with that account rather than the whole job.
+#[derive(Clone)]
 pub struct UserManager {
-    users: Vec<User>
+    users: Arc<RwLock<BTreeMap<String, SharedUser>>>,
+    risk: RiskEngine
 }
 
-async fn load_users() -> Vec<User> {
-    let api_credentials = load_api_credentials();
-    api_credentials.into_iter().map(|cred| futures::executor::block_on(User::new(cred))).collect()
+async fn load_users(config: &Config) -> BTreeMap<String, SharedUser> {
+    let mut users = BTreeMap::new();
+    for cred in config.users.iter().cloned() {
+        let name = cred.name.clone();
+        match User::new(cred, config).await {
+            Ok(user) => users.insert(name, Arc::new(Mutex::new(user))),
+            Err(e) => panic!("Could not load user {} on startup: {:?}", name, e)
+        };
+    }
+    users
 }
 
 impl UserManager {
-    pub async fn new() -> Self {
+    pub async fn new(config: &Config) -> Self {
         UserManager {
-            users: load_users().await
+            users: Arc::new(RwLock::new(load_users(config).await)),
+            risk: match RiskEngine::load(config) {
+                Ok(risk) => risk,
+                Err(e) => panic!("Could not load the risk state on startup: {:?}", e)
+            }
         }
     }
-    pub async fn refresh_users(&mut self) {
-        for user in &mut self.users {
-            user.refresh().await;
+    pub fn risk(&self) -> &RiskEngine {
+        &self.risk
+    }
+    /// The users as they are right now. A job keeps the users it started with until it's done,
+    /// even if some of them are removed in the meantime.
+    async fn snapshot(&self) -> Vec<(String, SharedUser)> {
+        self.users.read().await
+            .iter()
+            .map(|(name, user)| (name.clone(), Arc::clone(user)))
+            .collect()
+    }
+    pub async fn get(&self, name: &str) -> Option<SharedUser> {
+        self.users.read().await.get(name).cloned()
+    }
+    /// Engages the kill switch, then cancels every user's open and stop orders and with `flatten` sells
+    /// what they hold back to the quote currency. Users are handled one after the other.
+    pub async fn kill_all(&self, flatten: bool) -> Vec<KillReport> {
+        let kill_switch = self.risk.kill_switch();
+        if let Err(e) = kill_switch.engage(flatten) {
+            println!("{:?}", e);
+        }
+        let mut reports = Vec::new();
+        for (name, user) in self.snapshot().await {
+            let mut user = user.lock().await;
+            let mut report = KillReport { user: name, ..KillReport::default() };
+            match user.cancel_open_orders().await {
+                Ok(_) => report.cancelled = true,
+                Err(e) => report.errors.push(format!("{:#}", e))
+            }
+            match user.cancel_stop_orders().await {
+                Ok(_) => report.stops_cancelled = true,
+                Err(e) => report.errors.push(format!("{:#}", e))
+            }
+            if flatten {
+                user.flatten(&mut report).await;
+            }
+            println!("Kill switch for {}: {:?}", &report.user, &report);
+            reports.push(report);
         }
+        if let Err(e) = kill_switch.set_reports(reports.clone()) {
+            println!("{:?}", e);
+        }
+        reports
     }
-    pub async fn buy_tokens(&mut self, symbols: Vec<SymbolInfo>) {
-        let mut future_list: Vec<futures::future::BoxFuture<()>> = Vec::new();
-        let mut i = 0;
-        for user in &mut self.users {
-            let buy_promise = user.buy_tokens(symbols.clone());
-            future_list.insert(i, Box::pin(buy_promise));
-            i += 1;
+    /// Values every user's holdings at the current tickers and feeds the drawdown breakers.
+    pub async fn sample_equity(&self, config: &Config) {
+        let prices = match retry(&RetryPolicy::default(), get_denomination_prices_kc).await {
+            Ok(t) => t,
+            Err(e) => {
+                println!("Could not get prices to value equity: {:?}", e);
+                return;
+            }
+        };
+        let mut equities = HashMap::new();
+        let mut complete = true;
+        for (name, user) in self.snapshot().await {
+            match user.lock().await.equity(&prices).await {
+                Ok(equity) => {
+                    equities.insert(name, equity);
+                }
+                Err(e) => {
+                    println!("Could not value equity of {}: {:?}", name, e);
+                    complete = false;
+                }
+            }
+        }
+        self.risk.record_equity(&config.risk, &equities, complete);
+    }
+    pub async fn refresh_users(&self) {
+        for (_, user) in self.snapshot().await {
+            user.lock().await.refresh().await;
         }
-        futures::future::join_all(future_list).await;
     }
-    async fn resolve_place_sell_order(&mut self, symbols: &Vec<SymbolInfo>) -> bool {
+    /// Applies a reloaded config. Accounts that are new to the config are added, accounts that were
+    /// removed stop buying straight away but still see the take profits of a running job through.
+    pub async fn apply_config(&self, config: &Config) {
+        for (name, user) in self.snapshot().await {
+            let mut user = user.lock().await;
+            if config.users.iter().any(|cred| cred.name == name) {
+                user.apply_config(config).await;
+            } else {
+                user.retire();
+                self.users.write().await.remove(&name);
+            }
+        }
+        for cred in &config.users {
+            if self.get(&cred.name).await.is_some() {
+                continue;
+            }
+            match User::new(cred.clone(), config).await {
+                Ok(user) => {
+                    if let Err(e) = self.add_user(user).await {
+                        println!("{}", e);
+                    }
+                }
+                Err(e) => println!("Could not add user {} from the reloaded config: {:?}", &cred.name, e)
+            }
+        }
+    }
+    pub async fn add_user(&self, user: User) -> Result<()> {
+        let mut users = self.users.write().await;
+        if users.contains_key(user.name()) {
+            return Err(MintError::from_str(format!("User {} already exists", user.name())).into());
+        }
+        users.insert(user.name().to_string(), Arc::new(Mutex::new(user)));
+        Ok(())
+    }
+    /// Stops the user from trading. With `cancel_orders` its open orders are cancelled first and
+    /// the user is kept if that fails.
+    pub async fn remove_user(&self, name: &str, cancel_orders: bool) -> Result<()> {
+        let user = self.get(name).await
+            .ok_or_else(|| MintError::from_str(format!("No user named {}", name)))?;
+        let mut user = user.lock().await;
+        if cancel_orders {
+            user.cancel_open_orders().await?;
+        }
+        user.retire();
+        self.users.write().await.remove(name);
+        Ok(())
+    }
+    /// Checks the new keys against Kucoin before swapping them in.
+    pub async fn rotate_keys(&self, name: &str, api_credentials: ApiCredentials) -> Result<()> {
+        let user = self.get(name).await
+            .ok_or_else(|| MintError::from_str(format!("No user named {}", name)))?;
+        let mut user = user.lock().await;
+        let (exchange, balance) = user.reconnect(&api_credentials).await?;
+        user.replace_exchange(api_credentials, exchange, balance);
+        Ok(())
+    }
+    async fn buy_tokens(&self, users: &[(String, SharedUser)], symbols: Vec<SymbolInfo>, job: &Job) {
+        let buys = users.iter().map(|(_, user)| {
+            let symbols = symbols.clone();
+            async move {
+                user.lock().await.buy_tokens(symbols, job, &self.risk).await;
+            }
+        });
+        futures::future::join_all(buys).await;
+    }
+    async fn resolve_place_sell_order(&self, users: &[(String, SharedUser)], symbols: &Vec<SymbolInfo>, job: &Job) -> bool {
         let mut should_continue = true;
-        for user in &mut self.users {
-            if !user.try_place_sell_limit(symbols).await {
+        for (_, user) in users {
+            if !user.lock().await.try_place_sell_limit(symbols, job).await {
                 should_continue = false;
             }
         }
         should_continue
     }
-    async fn load_prices(&mut self, symbol_info_vec: Vec<SymbolInfo>) -> Vec<SymbolInfo> {
+    async fn load_prices(&self, symbol_info_vec: Vec<SymbolInfo>, job: &Job) -> Vec<SymbolInfo> {
         let mut new_vec: Vec<SymbolInfo> = Vec::new();
         let mut i = 0;
+        let policy = RetryPolicy::default();
         for symbol in symbol_info_vec {
-            match SymbolInfo::load_price(symbol).await {
+            match retry(&policy, || SymbolInfo::load_price(symbol.clone())).await {
                 Ok(symbol_with_price) => {
                     new_vec.insert(i, symbol_with_price);
                     i += 1;
                 }
                 Err(e) => {
-                    println!("{}", e)
+                    println!("{}", e);
+                    job.record(JobEvent::Failed {
+                        user: None,
+                        symbol: symbol.symbol_with_pair,
+                        fatal: classify(&e) == FailureKind::Fatal,
+                        reason: format!("{:#}", e),
+                    });
                 }
             }
         }
         new_vec
     }
-    pub async fn purchase_and_take_profit(&mut self, symbols: Vec<SymbolInfo>) {
-        self.buy_tokens(symbols.clone()).await;
-        let symbols = self.load_prices(symbols).await;
-        while !self.resolve_place_sell_order(&symbols).await {
+    pub async fn purchase_and_take_profit(&self, symbols: Vec<SymbolInfo>, job: &Job) {
+        let users = self.snapshot().await;
+        self.buy_tokens(&users, symbols.clone(), job).await;
+        let symbols = self.load_prices(symbols, job).await;
+        while !self.resolve_place_sell_order(&users, &symbols, job).await {
             tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
         }
     }
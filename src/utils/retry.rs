@@ -0,0 +1,232 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use rand::Rng;
+use tokio::time::Instant;
+
+use kucoin_rs_custom::kucoin::error::APIError;
+
+use crate::error::error::MintError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureKind {
+    Retryable,
+    Fatal,
+}
+
+/// Decides whether an exchange call is worth repeating. Only Kucoin errors and orders left out of a
+/// failed batch can be transient, everything else (bad numbers coming back, missing symbol info...)
+/// fails the same way twice.
+pub fn classify(err: &anyhow::Error) -> FailureKind {
+    for cause in err.chain() {
+        let api_err = match cause.downcast_ref::<MintError>() {
+            Some(MintError::KucoinApiError(e)) => Some(e),
+            Some(MintError::OrderNotPlaced { .. }) => return FailureKind::Retryable,
+            _ => cause.downcast_ref::<APIError>(),
+        };
+        if let Some(api_err) = api_err {
+            return if api_err.is_retryable() {
+                FailureKind::Retryable
+            } else {
+                FailureKind::Fatal
+            };
+        }
+    }
+    FailureKind::Fatal
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// No new attempt is started once this much time has passed since the first one.
+    pub deadline: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+            deadline: Duration::from_secs(20),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Full jitter: a random delay between zero and the exponential back off for this attempt.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        let cap = exp.min(self.max_delay);
+        let millis = rand::thread_rng().gen_range(0..=cap.as_millis() as u64);
+        Duration::from_millis(millis)
+    }
+}
+
+/// Runs `op` until it succeeds, fails with a fatal error, runs out of attempts or hits the deadline.
+/// The last error is returned as is so callers can `classify` it again.
+pub async fn retry<T, F, Fut>(policy: &RetryPolicy, mut op: F) -> Result<T>
+    where F: FnMut() -> Fut,
+          Fut: Future<Output=Result<T>>
+{
+    let started = Instant::now();
+    let mut attempt = 0;
+    loop {
+        let err = match op().await {
+            Ok(t) => return Ok(t),
+            Err(e) => e,
+        };
+        attempt += 1;
+        if classify(&err) == FailureKind::Fatal || attempt >= policy.max_attempts {
+            return Err(err);
+        }
+        let delay = policy.backoff(attempt - 1);
+        if started.elapsed() + delay >= policy.deadline {
+            return Err(err);
+        }
+        println!("Retrying after error (attempt {}): {}", attempt, err);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// `retry` for calls that place an order, which can't simply be sent again: a timeout or a server
+/// error may come after the exchange took the order. Before every new attempt `placed` looks the
+/// order up by `client_oid` and what it finds is returned instead of placing it twice. When the
+/// lookup fails too the outcome is unknown, that comes back as a fatal `MintError::OrderOutcomeUnknown`
+/// and the account is left to be reconciled rather than risking a second order.
+pub async fn retry_placement<T, F, Fut, L, LFut>(policy: &RetryPolicy, client_oid: &str, mut op: F, mut placed: L) -> Result<T>
+    where F: FnMut() -> Fut,
+          Fut: Future<Output=Result<T>>,
+          L: FnMut() -> LFut,
+          LFut: Future<Output=Result<Option<T>>>
+{
+    let started = Instant::now();
+    let mut attempt = 0;
+    loop {
+        let err = match op().await {
+            Ok(t) => return Ok(t),
+            Err(e) => e,
+        };
+        attempt += 1;
+        if classify(&err) == FailureKind::Fatal {
+            return Err(err);
+        }
+        match retry(policy, &mut placed).await {
+            Ok(Some(t)) => return Ok(t),
+            Ok(None) => {}
+            Err(lookup) => return Err(MintError::OrderOutcomeUnknown {
+                client_oid: client_oid.to_string(),
+                reason: format!("{:#}, then the lookup failed: {:#}", err, lookup),
+            }.into())
+        }
+        let delay = policy.backoff(attempt - 1);
+        if attempt >= policy.max_attempts || started.elapsed() + delay >= policy.deadline {
+            return Err(err);
+        }
+        println!("Placing {} again after error (attempt {}): {}", client_oid, attempt, err);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use kucoin_rs_custom::kucoin::error::{APIError, KucoinCode};
+
+    use crate::error::error::MintError;
+    use crate::utils::retry::{classify, retry, retry_placement, FailureKind, RetryPolicy};
+
+    fn kucoin_err(code: KucoinCode) -> anyhow::Error {
+        let e = APIError::Kucoin { code, msg: String::new() };
+        anyhow::Error::from(e).context("exchange call failed")
+    }
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+            deadline: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn classifies_through_context() {
+        let wrapped = anyhow::Error::from(MintError::from(APIError::Kucoin {
+            code: KucoinCode::InternalError,
+            msg: String::new(),
+        }));
+        assert_eq!(classify(&wrapped), FailureKind::Retryable);
+        assert_eq!(classify(&kucoin_err(KucoinCode::TooManyRequests)), FailureKind::Retryable);
+        assert_eq!(classify(&kucoin_err(KucoinCode::InsufficientBalance)), FailureKind::Fatal);
+        assert_eq!(classify(&anyhow::anyhow!("not an exchange error")), FailureKind::Fatal);
+        let not_placed = anyhow::Error::from(MintError::OrderNotPlaced { client_oid: "oid-1".to_string() });
+        assert_eq!(classify(&not_placed), FailureKind::Retryable);
+        // A batch's per order rejection keeps Kucoin's reason.
+        let rejected = anyhow::Error::from(MintError::from(APIError::Other("Price increment invalid.".to_string())));
+        assert_eq!(classify(&rejected.context("Failed to create Kucoin limit sell order")), FailureKind::Fatal);
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_until_success() {
+        let mut calls = 0;
+        let res = retry(&fast_policy(), || {
+            calls += 1;
+            let fail = calls < 3;
+            async move {
+                if fail { Err(kucoin_err(KucoinCode::InternalError)) } else { Ok(calls) }
+            }
+        }).await;
+        assert_eq!(res.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn fatal_errors_are_not_retried() {
+        let mut calls = 0;
+        let res: anyhow::Result<()> = retry(&fast_policy(), || {
+            calls += 1;
+            async { Err(kucoin_err(KucoinCode::UnknownSymbol)) }
+        }).await;
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn placements_are_looked_up_before_sending_again() {
+        // The first attempt timed out after the exchange took the order.
+        let mut sent = 0;
+        let res = retry_placement(&fast_policy(), "oid-1", || {
+            sent += 1;
+            async { Err::<&str, _>(kucoin_err(KucoinCode::InternalError)) }
+        }, || async { Ok(Some("placed")) }).await;
+        assert_eq!(res.unwrap(), "placed");
+        assert_eq!(sent, 1);
+
+        // Not there, so it is sent again.
+        let mut sent = 0;
+        let res = retry_placement(&fast_policy(), "oid-2", || {
+            sent += 1;
+            let fail = sent < 2;
+            async move {
+                if fail { Err(kucoin_err(KucoinCode::InternalError)) } else { Ok("sent") }
+            }
+        }, || async { Ok(None) }).await;
+        assert_eq!(res.unwrap(), "sent");
+        assert_eq!(sent, 2);
+
+        // No way to tell, so it is not sent again and the failure is fatal.
+        let mut sent = 0;
+        let res: anyhow::Result<()> = retry_placement(&fast_policy(), "oid-3", || {
+            sent += 1;
+            async { Err(kucoin_err(KucoinCode::InternalError)) }
+        }, || async { Err(kucoin_err(KucoinCode::InternalError)) }).await;
+        let err = res.unwrap_err();
+        assert_eq!(sent, 1);
+        assert_eq!(classify(&err), FailureKind::Fatal);
+        assert!(matches!(err.downcast_ref::<MintError>(), Some(MintError::OrderOutcomeUnknown { .. })));
+    }
+}